@@ -0,0 +1,68 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::api::names::owned_names;
+use crate::api::{parse_key, ApiError};
+use crate::context::SharedNode;
+
+// What an account holds now and what's pending for it. Everything in a response is read under one lock, so the
+// balance, names and pending amounts all agree with each other and with the reported tip.
+
+pub fn routes() -> Router<SharedNode> {
+    Router::new().route("/accounts/{pk}", get(get_account))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountResponse {
+    pub pk: String,
+    pub balance: u64,
+    // Sorted
+    pub names: Vec<String>,
+    pub pending_incoming: u64,
+    // Pending txn spends plus pending rename fees
+    pub pending_outgoing: u64,
+    pub height: usize,
+    pub tip: String,
+}
+
+pub async fn get_account(
+    State(node): State<SharedNode>,
+    Path(pk): Path<String>,
+) -> Result<Json<AccountResponse>, ApiError> {
+    let key = parse_key(&pk)?;
+
+    let response = node.read(|node| {
+        let state = &node.chain.state;
+
+        AccountResponse {
+            pk: hex::encode(key),
+            balance: state.account_set.get(&key).copied().unwrap_or(0),
+            names: owned_names(state, &key),
+            pending_incoming: node.mempool.pending_receipts(&key, &state.name_set),
+            pending_outgoing: node
+                .mempool
+                .pending_spend(&key)
+                .saturating_add(node.renames.pending_fees(&key)),
+            height: state.height,
+            tip: hex::encode(node.chain.tip_hash()),
+        }
+    });
+
+    // Emptied accounts are dropped from the account set, so anything with nothing at all is unknown
+    if response.balance == 0
+        && response.names.is_empty()
+        && response.pending_incoming == 0
+        && response.pending_outgoing == 0
+    {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            format!("{pk} has no balance, names or pending txns"),
+        ));
+    }
+
+    Ok(Json(response))
+}
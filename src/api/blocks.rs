@@ -0,0 +1,195 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::api::json::{HeaderJson, RenameJson, TxnJson};
+use crate::api::{parse_hash, ApiError};
+use crate::chain::Chain;
+use crate::context::SharedNode;
+use crate::*;
+
+// Browsing the block store. Lookups by hash find side chain blocks too, while heights always mean the main branch.
+// Genesis has no txns and isn't stored as a block, so it comes back with only its header filled in.
+
+// The most headers a single request returns
+pub const MAX_HEADERS: usize = 1_000;
+
+pub fn routes() -> Router<SharedNode> {
+    Router::new()
+        .route("/tip", get(get_tip))
+        .route("/headers", get(get_headers))
+        .route("/blocks/{hash}", get(get_block))
+        .route("/blocks/height/{height}", get(get_block_at))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeaderResponse {
+    pub hash: String,
+    pub height: usize,
+    pub main_chain: bool,
+    // The header hash had to be at or below this
+    pub target: String,
+    #[serde(flatten)]
+    pub header: HeaderJson,
+}
+
+// A txn or rename along with what it cost the block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockItem<T> {
+    pub hash: String,
+    pub size: usize,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockResponse {
+    #[serde(flatten)]
+    pub header: HeaderResponse,
+    pub size: usize,
+    // What the coinbase actually paid, which can be less than it was allowed to
+    pub coinbase_value: u64,
+    // Txn fees only. Rename fees aren't paid to the miner.
+    pub fees: u64,
+    // Only genesis and malformed side chain blocks have no coinbase
+    pub coinbase: Option<BlockItem<TxnJson>>,
+    pub txns: Vec<BlockItem<TxnJson>>,
+    pub name_changes: Vec<BlockItem<RenameJson>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeadersQuery {
+    #[serde(default)]
+    pub from: usize,
+    pub count: Option<usize>,
+}
+
+fn not_found(what: String) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "not_found",
+        format!("{what} is not known"),
+    )
+}
+
+pub fn txn_item(txn: &Txn) -> BlockItem<TxnJson> {
+    BlockItem {
+        hash: hex::encode(txn_hash(txn)),
+        size: encode_txn(txn).len(),
+        item: txn.into(),
+    }
+}
+
+pub fn rename_item(op: &RenameOp) -> BlockItem<RenameJson> {
+    BlockItem {
+        hash: hex::encode(name_change_hash(op)),
+        size: encode_name_change(op).len(),
+        item: op.into(),
+    }
+}
+
+pub fn header_response(chain: &Chain, hash: &[u8; 32]) -> Option<HeaderResponse> {
+    Some(HeaderResponse {
+        hash: hex::encode(hash),
+        height: chain.block_height(hash)?,
+        main_chain: chain.is_main_chain(hash),
+        target: hex::encode(chain.difficulty(hash)?),
+        header: chain.header(hash)?.into(),
+    })
+}
+
+pub fn block_response(chain: &Chain, hash: &[u8; 32]) -> Option<BlockResponse> {
+    let header = header_response(chain, hash)?;
+
+    let Some(block) = chain.block(hash) else {
+        // Genesis
+        return Some(BlockResponse {
+            header,
+            size: 0,
+            coinbase_value: 0,
+            fees: 0,
+            coinbase: None,
+            txns: vec![],
+            name_changes: vec![],
+        });
+    };
+
+    // Side chain blocks are stored before they're validated, so the coinbase might be missing and the amounts might
+    // not add up. Sums saturate instead of overflowing.
+    let (coinbase, txns) = match block.txns.split_first() {
+        Some((coinbase, txns)) => (Some(coinbase), txns),
+        None => (None, &[][..]),
+    };
+
+    Some(BlockResponse {
+        header,
+        size: block_size(block),
+        coinbase_value: coinbase.map_or(0, |coinbase| {
+            coinbase
+                .recievers
+                .iter()
+                .fold(0, |total: u64, (_, amount)| total.saturating_add(*amount))
+        }),
+        fees: txns
+            .iter()
+            .fold(0, |total: u64, txn| total.saturating_add(txn.fee)),
+        coinbase: coinbase.map(txn_item),
+        txns: txns.iter().map(txn_item).collect(),
+        name_changes: block.name_changes.iter().map(rename_item).collect(),
+    })
+}
+
+pub async fn get_tip(State(node): State<SharedNode>) -> Json<HeaderResponse> {
+    // The tip is always in the store
+    Json(node.read(|node| header_response(&node.chain, &node.chain.tip_hash()).unwrap()))
+}
+
+pub async fn get_headers(
+    State(node): State<SharedNode>,
+    Query(query): Query<HeadersQuery>,
+) -> Result<Json<Vec<HeaderResponse>>, ApiError> {
+    let count = query.count.unwrap_or(MAX_HEADERS);
+    if count > MAX_HEADERS {
+        return Err(ApiError::bad_request(format!(
+            "at most {MAX_HEADERS} headers can be requested at once"
+        )));
+    }
+
+    let headers = node.read(|node| {
+        (query.from..query.from.saturating_add(count))
+            .map_while(|height| node.chain.hash_at(height))
+            .filter_map(|hash| header_response(&node.chain, &hash))
+            .collect()
+    });
+
+    Ok(Json(headers))
+}
+
+pub async fn get_block(
+    State(node): State<SharedNode>,
+    Path(hash): Path<String>,
+) -> Result<Json<BlockResponse>, ApiError> {
+    let hash = parse_hash(&hash)?;
+
+    node.read(|node| block_response(&node.chain, &hash))
+        .map(Json)
+        .ok_or_else(|| not_found(format!("Block {}", hex::encode(hash))))
+}
+
+pub async fn get_block_at(
+    State(node): State<SharedNode>,
+    Path(height): Path<String>,
+) -> Result<Json<BlockResponse>, ApiError> {
+    let height: usize = height
+        .parse()
+        .map_err(|_| ApiError::bad_request(format!("{height} is not a height")))?;
+
+    node.read(|node| {
+        let hash = node.chain.hash_at(height)?;
+        block_response(&node.chain, &hash)
+    })
+    .map(Json)
+    .ok_or_else(|| not_found(format!("Height {height}")))
+}
@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+
+use axum::extract::{Query, State};
+use axum::response::sse::{self, KeepAlive, Sse};
+use axum::routing::get;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::api::blocks::{rename_item, txn_item};
+use crate::api::json::HeaderJson;
+use crate::api::{parse_key, ApiError};
+use crate::context::SharedNode;
+use crate::events::{Event, EventFilter};
+use crate::mempool::EvictionReason;
+use crate::*;
+
+// GET /events streams what the node is doing as server-sent events, named:
+//
+//   block_connected, block_disconnected   a BlockEventJson. A reorg disconnects from the old tip down, then
+//                                         connects from the fork up.
+//   txn, rename                           an item accepted into the pending pools, shaped as in GET /blocks
+//   txn_evicted                           an EvictionJson, for a txn dropped from the mempool without being mined
+//   name_owner                            a NameOwnerJson, sent after the blocks that caused it
+//   lagged                                the number of events this stream fell too far behind to be sent
+//
+// `keys` and `names` take comma separated lists and narrow the pool and name events down to those touching them.
+// Evictions only say who sent the txn, so they're matched on that key alone.
+// Block events are always sent.
+
+pub fn routes() -> Router<SharedNode> {
+    Router::new().route("/events", get(get_events))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventsQuery {
+    pub keys: Option<String>,
+    pub names: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockEventJson {
+    pub hash: String,
+    pub height: usize,
+    #[serde(flatten)]
+    pub header: HeaderJson,
+    // Hashes, coinbase first
+    pub txns: Vec<String>,
+    pub name_changes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NameOwnerJson {
+    pub name: String,
+    pub old_owner: Option<String>,
+    pub new_owner: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvictionJson {
+    pub hash: String,
+    pub sender: String,
+    // full, expired, replaced or invalidated
+    pub reason: String,
+}
+
+fn list(param: &Option<String>) -> impl Iterator<Item = &str> {
+    param
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|item| !item.is_empty())
+}
+
+fn block_event(block: &Block, height: usize) -> BlockEventJson {
+    BlockEventJson {
+        hash: hex::encode(hash_header(&block.header)),
+        height,
+        header: (&block.header).into(),
+        txns: block
+            .txns
+            .iter()
+            .map(|t| hex::encode(txn_hash(t)))
+            .collect(),
+        name_changes: block
+            .name_changes
+            .iter()
+            .map(|op| hex::encode(name_change_hash(op)))
+            .collect(),
+    }
+}
+
+pub fn sse_event(event: &Event) -> sse::Event {
+    let named = |name| sse::Event::default().event(name);
+
+    // None of these can fail to serialize
+    match event {
+        Event::BlockConnected { block, height } => {
+            named("block_connected").json_data(block_event(block, *height))
+        }
+        Event::BlockDisconnected { block, height } => {
+            named("block_disconnected").json_data(block_event(block, *height))
+        }
+        Event::Txn { txn, .. } => named("txn").json_data(txn_item(txn)),
+        Event::TxnEvicted(eviction) => named("txn_evicted").json_data(EvictionJson {
+            hash: hex::encode(eviction.hash),
+            sender: hex::encode(eviction.sender),
+            reason: match eviction.reason {
+                EvictionReason::Full => "full",
+                EvictionReason::Expired => "expired",
+                EvictionReason::Replaced => "replaced",
+                EvictionReason::Invalidated => "invalidated",
+            }
+            .into(),
+        }),
+        Event::Rename { op, .. } => named("rename").json_data(rename_item(op)),
+        Event::NameOwner {
+            name,
+            old_owner,
+            new_owner,
+        } => named("name_owner").json_data(NameOwnerJson {
+            name: name.clone(),
+            old_owner: old_owner.map(hex::encode),
+            new_owner: new_owner.map(hex::encode),
+        }),
+    }
+    .unwrap()
+}
+
+pub async fn get_events(
+    State(node): State<SharedNode>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, ApiError> {
+    let filter = EventFilter {
+        keys: list(&query.keys)
+            .map(parse_key)
+            .collect::<Result<HashSet<_>, _>>()?,
+        names: list(&query.names).map(String::from).collect(),
+    };
+
+    let stream =
+        BroadcastStream::new(node.subscribe_events()).filter_map(move |event| match event {
+            Ok(event) => filter.matches(&event).then(|| Ok(sse_event(&event))),
+            Err(BroadcastStreamRecvError::Lagged(missed)) => Some(Ok(sse::Event::default()
+                .event("lagged")
+                .data(missed.to_string()))),
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
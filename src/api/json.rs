@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+
+use crate::api::{parse_hash, parse_hex, ApiError};
+use crate::*;
+
+// JSON forms of the consensus types. Fields keep the names the structs use, byte arrays become hex, and addresses
+// are tagged so a name can never be mistaken for a key.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressJson {
+    Key(String),
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecieverJson {
+    pub address: AddressJson,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxnJson {
+    pub sender: AddressJson,
+    pub recievers: Vec<RecieverJson>,
+    pub signature: String,
+    pub fee: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenameJson {
+    pub pk: String,
+    pub sig: String,
+    pub new_name: String,
+    pub fee: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeaderJson {
+    pub prev_block_hash: String,
+    pub merkle_root: String,
+    pub time: u64,
+    pub nonce: u64,
+}
+
+// Request bodies can carry an item either as its binary encoding in hex, or spelled out as JSON
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Encoded<T> {
+    Hex { hex: String },
+    Json(T),
+}
+
+impl From<&Address> for AddressJson {
+    fn from(address: &Address) -> Self {
+        match address {
+            Address::Key(key) => AddressJson::Key(hex::encode(key)),
+            Address::Name(name) => AddressJson::Name(name.clone()),
+        }
+    }
+}
+
+impl TryFrom<AddressJson> for Address {
+    type Error = ApiError;
+
+    fn try_from(address: AddressJson) -> Result<Self, ApiError> {
+        match address {
+            AddressJson::Key(key) => Ok(Address::Key(parse_hash(&key)?)),
+            AddressJson::Name(name) => Ok(Address::Name(name)),
+        }
+    }
+}
+
+impl From<&Header> for HeaderJson {
+    fn from(header: &Header) -> Self {
+        HeaderJson {
+            prev_block_hash: hex::encode(header.prev_block_hash),
+            merkle_root: hex::encode(header.merkle_root),
+            time: header.time,
+            nonce: header.nonce,
+        }
+    }
+}
+
+impl From<&Txn> for TxnJson {
+    fn from(txn: &Txn) -> Self {
+        TxnJson {
+            sender: (&txn.sender).into(),
+            recievers: txn
+                .recievers
+                .iter()
+                .map(|(address, amount)| RecieverJson {
+                    address: address.into(),
+                    amount: *amount,
+                })
+                .collect(),
+            signature: hex::encode(txn.signature),
+            fee: txn.fee,
+        }
+    }
+}
+
+impl TryFrom<TxnJson> for Txn {
+    type Error = ApiError;
+
+    fn try_from(txn: TxnJson) -> Result<Self, ApiError> {
+        Ok(Txn {
+            sender: txn.sender.try_into()?,
+            recievers: txn
+                .recievers
+                .into_iter()
+                .map(|r| Ok((r.address.try_into()?, r.amount)))
+                .collect::<Result<_, ApiError>>()?,
+            signature: parse_signature(&txn.signature)?,
+            fee: txn.fee,
+        })
+    }
+}
+
+impl From<&RenameOp> for RenameJson {
+    fn from(op: &RenameOp) -> Self {
+        RenameJson {
+            pk: hex::encode(op.pk),
+            sig: hex::encode(op.sig),
+            new_name: op.new_name.clone(),
+            fee: op.fee,
+        }
+    }
+}
+
+impl TryFrom<RenameJson> for RenameOp {
+    type Error = ApiError;
+
+    fn try_from(op: RenameJson) -> Result<Self, ApiError> {
+        Ok(RenameOp {
+            pk: parse_hash(&op.pk)?,
+            sig: parse_signature(&op.sig)?,
+            new_name: op.new_name,
+            fee: op.fee,
+        })
+    }
+}
+
+pub fn parse_signature(hex_str: &str) -> Result<[u8; 64], ApiError> {
+    let mut out = [0; 64];
+    hex::decode_to_slice(hex_str, &mut out).map_err(|e| {
+        ApiError::bad_request(format!("{hex_str} is not a 64 byte hex string: {e}"))
+    })?;
+    Ok(out)
+}
+
+// Runs `decode` over the whole of `hex_str`, rejecting anything left over
+pub fn decode_hex<T>(
+    hex_str: &str,
+    decode: impl FnOnce(&mut &[u8]) -> Result<T, Error>,
+) -> Result<T, ApiError> {
+    let data = parse_hex(hex_str)?;
+    let mut slice = data.as_slice();
+    let item = decode(&mut slice)?;
+
+    if !slice.is_empty() {
+        return Err(Error::DecodeError(format!("{} trailing bytes", slice.len())).into());
+    }
+
+    Ok(item)
+}
+
+impl Encoded<TxnJson> {
+    pub fn into_txn(self) -> Result<Txn, ApiError> {
+        match self {
+            Encoded::Hex { hex } => decode_hex(&hex, decode_txn),
+            Encoded::Json(txn) => txn.try_into(),
+        }
+    }
+}
+
+impl Encoded<RenameJson> {
+    pub fn into_rename(self) -> Result<RenameOp, ApiError> {
+        match self {
+            Encoded::Hex { hex } => decode_hex(&hex, decode_name_change),
+            Encoded::Json(op) => op.try_into(),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::api::json::decode_hex;
+use crate::api::{body, parse_key, unix_millis, ApiError};
+use crate::chain::BlockStatus;
+use crate::context::SharedNode;
+use crate::*;
+
+// Work for external miners.
+//
+// GET /mining/template hands out a block on top of our tip, ready to mine apart from the nonce. The whole block is
+// included encoded, so a miner can grind `nonce` (and the extra nonce in the coinbase signature) without having to
+// know how blocks are put together. POST /mining/submit takes the solved block back.
+
+pub fn routes() -> Router<SharedNode> {
+    Router::new()
+        .route("/mining/template", get(get_template))
+        .route("/mining/submit", post(submit_block))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateQuery {
+    // The key the coinbase pays
+    pub miner: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateItem {
+    pub hash: String,
+    // The encoded txn or rename
+    pub data: String,
+    pub fee: u64,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateResponse {
+    // The height the block will have once connected
+    pub height: usize,
+    pub prev_block_hash: String,
+    pub merkle_root: String,
+    pub time: u64,
+    pub nonce: u64,
+    // The header hash must be at or below this
+    pub target: String,
+    // calc_coinbase for the block's size, plus every txn fee
+    pub coinbase_value: u64,
+    pub reward: u64,
+    pub fees: u64,
+    pub size: usize,
+    pub max_size: usize,
+    pub coinbase: String,
+    // Not including the coinbase
+    pub txns: Vec<TemplateItem>,
+    pub name_changes: Vec<TemplateItem>,
+    pub block: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SubmitBlockRequest {
+    // The encoded block
+    pub block: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubmitBlockResponse {
+    pub hash: String,
+    // "connected", "side_chain" or "already_known"
+    pub status: String,
+}
+
+pub async fn get_template(
+    State(node): State<SharedNode>,
+    Query(query): Query<TemplateQuery>,
+) -> Result<Json<TemplateResponse>, ApiError> {
+    let miner = parse_key(&query.miner)?;
+    let (template, height, difficulty, max_size) = node.read(|node| {
+        let state = &node.chain.state;
+        (
+            node.block_template(miner, unix_millis() / 1_000),
+            state.height + 1,
+            state.difficulty,
+            max_block_size(median_block_size(&state.last_100_block_sizes)),
+        )
+    });
+    let block = &template.block;
+
+    let txns = block.txns[1..]
+        .iter()
+        .map(|txn| {
+            let data = encode_txn(txn);
+            TemplateItem {
+                hash: hex::encode(txn_hash(txn)),
+                fee: txn.fee,
+                size: data.len(),
+                data: hex::encode(data),
+            }
+        })
+        .collect();
+
+    let name_changes = block
+        .name_changes
+        .iter()
+        .map(|op| {
+            let data = encode_name_change(op);
+            TemplateItem {
+                hash: hex::encode(name_change_hash(op)),
+                fee: op.fee,
+                size: data.len(),
+                data: hex::encode(data),
+            }
+        })
+        .collect();
+
+    Ok(Json(TemplateResponse {
+        height,
+        prev_block_hash: hex::encode(block.header.prev_block_hash),
+        merkle_root: hex::encode(block.header.merkle_root),
+        time: block.header.time,
+        nonce: block.header.nonce,
+        target: hex::encode(difficulty),
+        coinbase_value: template.reward + template.fees,
+        reward: template.reward,
+        fees: template.fees,
+        size: template.size,
+        max_size,
+        coinbase: hex::encode(encode_txn(&block.txns[0])),
+        txns,
+        name_changes,
+        block: hex::encode(encode_block(block)),
+    }))
+}
+
+// A block on our tip gets the full validate_block treatment up front, so the miner is told exactly what was wrong
+// with it. Blocks on other branches go straight to the chain, which only validates them if they become the tip.
+pub async fn submit_block(
+    State(node): State<SharedNode>,
+    payload: Result<Json<SubmitBlockRequest>, JsonRejection>,
+) -> Result<Json<SubmitBlockResponse>, ApiError> {
+    let block = decode_hex(&body(payload)?.block, decode_block)?;
+
+    let hash = hash_header(&block.header);
+    // A block that made it onto the main chain is announced to peers like any other new tip
+    let (status, announcement) = node.write(|node| {
+        if block.header.prev_block_hash == node.chain.tip_hash() {
+            validate_block(&block, &node.chain.state)?;
+        }
+        let status = node.add_block(block, unix_millis())?;
+        let announcement = match status {
+            BlockStatus::Connected { .. } => node.announce_tip(),
+            _ => None,
+        };
+        Ok::<_, Error>((status, announcement))
+    })?;
+
+    if let Some(message) = announcement {
+        node.relay(message);
+    }
+
+    let status = match status {
+        BlockStatus::Connected { .. } => "connected",
+        BlockStatus::SideChain => "side_chain",
+        BlockStatus::AlreadyKnown => "already_known",
+        BlockStatus::Orphan => {
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                "unknown_parent",
+                "the block's parent is not known",
+            ))
+        }
+    };
+
+    Ok(Json(SubmitBlockResponse {
+        hash: hex::encode(hash),
+        status: status.into(),
+    }))
+}
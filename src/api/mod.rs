@@ -0,0 +1,124 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::rejection::JsonRejection;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::context::SharedNode;
+use crate::*;
+
+// The HTTP API served by the node binary. Hashes, keys and encoded items all travel as lowercase hex.
+// Every failure comes back as an ApiError, so clients can match on `kind` instead of parsing messages.
+
+pub mod accounts;
+pub mod blocks;
+pub mod events;
+pub mod json;
+pub mod mining;
+pub mod names;
+pub mod rpc;
+pub mod stats;
+pub mod txns;
+
+pub fn router(node: SharedNode) -> Router {
+    Router::new()
+        .merge(accounts::routes())
+        .merge(blocks::routes())
+        .merge(events::routes())
+        .merge(mining::routes())
+        .merge(names::routes())
+        .merge(rpc::routes())
+        .merge(stats::routes())
+        .merge(txns::routes())
+        .with_state(node)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub kind: String,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, kind: &str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            kind: kind.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+}
+
+// Stable names for each error variant
+pub fn error_kind(error: &Error) -> &'static str {
+    match error {
+        Error::BlockValidationError(_) => "block_validation",
+        Error::TxnValidationError(_) => "txn_validation",
+        Error::MissingDataError => "missing_data",
+        Error::DecodeError(_) => "decode",
+        Error::PeerLimitError(_) => "peer_limit",
+        Error::IoError(_) => "io",
+        Error::TransportError(_) => "transport",
+        Error::MempoolError(_) => "mempool",
+        Error::ShareError(_) => "share",
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        let status = match error {
+            Error::IoError(_) | Error::TransportError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        ApiError::new(status, error_kind(&error), error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+pub fn parse_hash(hex_str: &str) -> Result<[u8; 32], ApiError> {
+    let mut out = [0; 32];
+    hex::decode_to_slice(hex_str, &mut out).map_err(|e| {
+        ApiError::bad_request(format!("{hex_str} is not a 32 byte hex string: {e}"))
+    })?;
+    Ok(out)
+}
+
+// A hash which is also a valid x-only public key, since nothing else can hold funds or names
+pub fn parse_key(hex_str: &str) -> Result<[u8; 32], ApiError> {
+    let key = parse_hash(hex_str)?;
+    XOnlyPublicKey::from_byte_array(&key)
+        .map_err(|_| ApiError::bad_request(format!("{hex_str} is not a valid public key")))?;
+    Ok(key)
+}
+
+// Malformed bodies get the same error shape as everything else
+pub fn body<T>(payload: Result<Json<T>, JsonRejection>) -> Result<T, ApiError> {
+    payload
+        .map(|Json(body)| body)
+        .map_err(|e| ApiError::bad_request(e.body_text()))
+}
+
+pub fn parse_hex(hex_str: &str) -> Result<Vec<u8>, ApiError> {
+    hex::decode(hex_str).map_err(|e| ApiError::bad_request(format!("invalid hex: {e}")))
+}
+
+// Wall clock time in milliseconds, which is what the node's pools expect
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
@@ -0,0 +1,85 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::api::{parse_key, ApiError};
+use crate::context::SharedNode;
+use crate::*;
+
+// Name lookups in both directions. Both only need the chain state, so they're answered from the tip snapshot.
+
+pub fn routes() -> Router<SharedNode> {
+    Router::new()
+        .route("/names/{name}", get(get_name))
+        .route("/accounts/{pk}/names", get(get_owned_names))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NameResponse {
+    pub name: String,
+    pub owner: String,
+    pub height: usize,
+    pub tip: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedNamesResponse {
+    pub pk: String,
+    // Sorted
+    pub names: Vec<String>,
+    pub height: usize,
+    pub tip: String,
+}
+
+pub fn owned_names(blockchain_state: &BlockchainState, key: &[u8; 32]) -> Vec<String> {
+    blockchain_state
+        .owned_names
+        .get(key)
+        .map_or(vec![], |names| names.iter().cloned().collect())
+}
+
+pub async fn get_name(
+    State(node): State<SharedNode>,
+    Path(name): Path<String>,
+) -> Result<Json<NameResponse>, ApiError> {
+    if name.is_empty() || name.len() > MAX_NAME_SIZE {
+        return Err(ApiError::bad_request(format!(
+            "names must be between 1 and {MAX_NAME_SIZE} bytes"
+        )));
+    }
+
+    let tip = node.tip();
+    // Resolved the same way a txn paying the name would be
+    let owner =
+        address_to_key(&Address::Name(name.clone()), &tip.state.name_set).map_err(|_| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                format!("{name} is not owned by anyone"),
+            )
+        })?;
+
+    Ok(Json(NameResponse {
+        name,
+        owner: hex::encode(owner),
+        height: tip.state.height,
+        tip: hex::encode(tip.hash),
+    }))
+}
+
+pub async fn get_owned_names(
+    State(node): State<SharedNode>,
+    Path(pk): Path<String>,
+) -> Result<Json<OwnedNamesResponse>, ApiError> {
+    let key = parse_key(&pk)?;
+    let tip = node.tip();
+
+    Ok(Json(OwnedNamesResponse {
+        pk: hex::encode(key),
+        names: owned_names(&tip.state, &key),
+        height: tip.state.height,
+        tip: hex::encode(tip.hash),
+    }))
+}
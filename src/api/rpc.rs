@@ -0,0 +1,317 @@
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::api::blocks::HeadersQuery;
+use crate::api::json::{Encoded, RenameJson, TxnJson};
+use crate::api::mining::{SubmitBlockRequest, TemplateQuery};
+use crate::api::{accounts, blocks, mining, names, parse_key, stats, txns, ApiError};
+use crate::context::SharedNode;
+
+// JSON-RPC 2.0 at POST /rpc, for tooling that doesn't speak REST. Each method answers the same as its REST
+// endpoint does, and takes its parameters either by name or in the order listed here:
+//
+//   get_chain_info                       get_block        [hash]
+//   get_balance          [pk]            get_block_at     [height]
+//   get_account          [pk]            get_headers      [from, count]
+//   get_name             [name]          get_tip
+//   get_owned_names      [pk]            get_template     [miner]
+//   submit_txn           [hex]           submit_block     [block]
+//   submit_rename        [hex]           get_mining_stats
+//
+// submit_txn and submit_rename also take the item spelled out as JSON, by name only.
+//
+// Batches are run in order and hold at most MAX_BATCH_SIZE requests. A bigger batch is turned away whole.
+//
+// Errors use the standard codes where one fits, and a code from the server range for each crate error otherwise.
+// `data.kind` always carries the same kind a REST call would have returned.
+
+pub const PARSE_ERROR: i64 = -32_700;
+pub const INVALID_REQUEST: i64 = -32_600;
+pub const METHOD_NOT_FOUND: i64 = -32_601;
+pub const INVALID_PARAMS: i64 = -32_602;
+pub const INTERNAL_ERROR: i64 = -32_603;
+
+pub const NOT_FOUND: i64 = -32_001;
+pub const UNKNOWN_PARENT: i64 = -32_002;
+pub const BLOCK_VALIDATION: i64 = -32_010;
+pub const TXN_VALIDATION: i64 = -32_011;
+pub const MISSING_DATA: i64 = -32_012;
+pub const MEMPOOL_REJECTED: i64 = -32_013;
+pub const PEER_LIMIT: i64 = -32_014;
+pub const SHARE_REJECTED: i64 = -32_015;
+
+// Each request in a batch can take the node lock, so one HTTP request can't be allowed to queue up too many
+pub const MAX_BATCH_SIZE: usize = 100;
+
+pub fn routes() -> Router<SharedNode> {
+    Router::new().route("/rpc", post(handle_rpc))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    pub id: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainInfo {
+    pub network: String,
+    pub genesis: String,
+    pub height: usize,
+    pub tip: String,
+    pub target: String,
+    pub pending_txns: usize,
+    pub pending_renames: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct KeyParams {
+    pk: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NameParams {
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HashParams {
+    hash: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HeightParams {
+    height: usize,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+// The codes for everything error_kind can return, plus the kinds the API adds itself
+pub fn error_code(kind: &str) -> i64 {
+    match kind {
+        "bad_request" | "decode" => INVALID_PARAMS,
+        "io" | "transport" => INTERNAL_ERROR,
+        "not_found" => NOT_FOUND,
+        "unknown_parent" => UNKNOWN_PARENT,
+        "block_validation" => BLOCK_VALIDATION,
+        "txn_validation" => TXN_VALIDATION,
+        "missing_data" => MISSING_DATA,
+        "mempool" => MEMPOOL_REJECTED,
+        "peer_limit" => PEER_LIMIT,
+        "share" => SHARE_REJECTED,
+        _ => INTERNAL_ERROR,
+    }
+}
+
+impl From<ApiError> for RpcError {
+    fn from(error: ApiError) -> Self {
+        RpcError {
+            code: error_code(&error.kind),
+            message: error.message,
+            data: Some(serde_json::json!({ "kind": error.kind })),
+        }
+    }
+}
+
+// Positional params are given the names in `names`, in order
+fn params<T: DeserializeOwned>(params: Option<Value>, names: &[&str]) -> Result<T, RpcError> {
+    let params = match params {
+        None => Value::Object(Map::new()),
+        Some(Value::Array(values)) => {
+            if values.len() > names.len() {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("expected at most {} params", names.len()),
+                ));
+            }
+            let named = names.iter().map(|name| name.to_string()).zip(values);
+            Value::Object(named.collect())
+        }
+        Some(params @ Value::Object(_)) => params,
+        Some(_) => {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                "params must be an array or an object",
+            ))
+        }
+    };
+
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn result<T: Serialize>(response: Result<Json<T>, ApiError>) -> Result<Value, RpcError> {
+    Ok(serde_json::to_value(response?.0).unwrap())
+}
+
+fn chain_info(node: &SharedNode) -> ChainInfo {
+    node.read(|node| {
+        let state = &node.chain.state;
+        ChainInfo {
+            network: state.network.name().into(),
+            genesis: hex::encode(node.chain.genesis_hash()),
+            height: state.height,
+            tip: hex::encode(node.chain.tip_hash()),
+            target: hex::encode(state.difficulty),
+            pending_txns: node.mempool.len(),
+            pending_renames: node.renames.len(),
+        }
+    })
+}
+
+pub async fn call(node: SharedNode, method: &str, p: Option<Value>) -> Result<Value, RpcError> {
+    let state = State(node.clone());
+
+    match method {
+        "get_chain_info" => result(Ok(Json(chain_info(&node)))),
+        "get_balance" => {
+            let KeyParams { pk } = params(p, &["pk"])?;
+            let key = parse_key(&pk)?;
+            let balance = node.tip().state.account_set.get(&key).copied();
+            Ok(balance.unwrap_or(0).into())
+        }
+        "get_account" => {
+            let KeyParams { pk } = params(p, &["pk"])?;
+            result(accounts::get_account(state, Path(pk)).await)
+        }
+        "get_name" => {
+            let NameParams { name } = params(p, &["name"])?;
+            result(names::get_name(state, Path(name)).await)
+        }
+        "get_owned_names" => {
+            let KeyParams { pk } = params(p, &["pk"])?;
+            result(names::get_owned_names(state, Path(pk)).await)
+        }
+        "get_block" => {
+            let HashParams { hash } = params(p, &["hash"])?;
+            result(blocks::get_block(state, Path(hash)).await)
+        }
+        "get_block_at" => {
+            let HeightParams { height } = params(p, &["height"])?;
+            result(blocks::get_block_at(state, Path(height.to_string())).await)
+        }
+        "get_headers" => {
+            let query: HeadersQuery = params(p, &["from", "count"])?;
+            result(blocks::get_headers(state, Query(query)).await)
+        }
+        "get_tip" => result(Ok(blocks::get_tip(state).await)),
+        "submit_txn" => {
+            let txn: Encoded<TxnJson> = params(p, &["hex"])?;
+            result(txns::submit_txn(state, Ok(Json(txn))).await)
+        }
+        "submit_rename" => {
+            let op: Encoded<RenameJson> = params(p, &["hex"])?;
+            result(txns::submit_rename(state, Ok(Json(op))).await)
+        }
+        "get_template" => {
+            let query: TemplateQuery = params(p, &["miner"])?;
+            result(mining::get_template(state, Query(query)).await)
+        }
+        "submit_block" => {
+            let request: SubmitBlockRequest = params(p, &["block"])?;
+            result(mining::submit_block(state, Ok(Json(request))).await)
+        }
+        "get_mining_stats" => result(Ok(stats::get_mining_stats(state).await)),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("{method} is not a method"),
+        )),
+    }
+}
+
+// None for notifications, which get no response even when they fail
+async fn handle_one(node: &SharedNode, request: Value) -> Option<RpcResponse> {
+    let id = request.get("id").cloned();
+
+    let response = |outcome: Result<Value, RpcError>, id: Value| {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        RpcResponse {
+            jsonrpc: "2.0".into(),
+            result,
+            error,
+            id,
+        }
+    };
+
+    let valid = request.get("jsonrpc") == Some(&Value::from("2.0"))
+        && matches!(
+            id,
+            None | Some(Value::Null | Value::Number(_) | Value::String(_))
+        );
+    let method = request.get("method").and_then(Value::as_str);
+
+    let (true, Some(method)) = (valid, method) else {
+        let error = RpcError::new(INVALID_REQUEST, "not a JSON-RPC 2.0 request");
+        return Some(response(Err(error), Value::Null));
+    };
+
+    let outcome = call(node.clone(), method, request.get("params").cloned()).await;
+    id.map(|id| response(outcome, id))
+}
+
+pub async fn handle_rpc(State(node): State<SharedNode>, body: Bytes) -> Response {
+    let failed = |error: RpcError| {
+        let response = RpcResponse {
+            jsonrpc: "2.0".into(),
+            result: None,
+            error: Some(error),
+            id: Value::Null,
+        };
+        Json(response).into_response()
+    };
+
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => return failed(RpcError::new(PARSE_ERROR, e.to_string())),
+    };
+
+    match request {
+        Value::Array(batch) if batch.len() > MAX_BATCH_SIZE => failed(RpcError::new(
+            INVALID_REQUEST,
+            format!("batches can hold at most {MAX_BATCH_SIZE} requests"),
+        )),
+        Value::Array(batch) if !batch.is_empty() => {
+            let mut responses = vec![];
+            for request in batch {
+                responses.extend(handle_one(&node, request).await);
+            }
+
+            if responses.is_empty() {
+                return StatusCode::NO_CONTENT.into_response();
+            }
+            Json(responses).into_response()
+        }
+        request => match handle_one(&node, request).await {
+            Some(response) => Json(response).into_response(),
+            None => StatusCode::NO_CONTENT.into_response(),
+        },
+    }
+}
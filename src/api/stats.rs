@@ -0,0 +1,82 @@
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiError;
+use crate::context::SharedNode;
+use crate::stats::{chain_stats, difficulty_history, expected_hashes};
+
+// Hashrate and difficulty figures for dashboards. Floats are estimates, everything else is exact.
+
+// The most heights a single history request returns
+pub const MAX_HISTORY: usize = 1_000;
+
+pub fn routes() -> Router<SharedNode> {
+    Router::new()
+        .route("/stats/mining", get(get_mining_stats))
+        .route("/stats/difficulty", get(get_difficulty_history))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MiningStatsResponse {
+    pub height: usize,
+    pub difficulty: String,
+    pub expected_hashes: f64,
+    pub hashrate: Option<f64>,
+    pub average_interval: Option<f64>,
+    pub next_retarget_height: usize,
+    pub seconds_to_retarget: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    pub from: usize,
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyPoint {
+    pub height: usize,
+    pub difficulty: String,
+    pub expected_hashes: f64,
+}
+
+pub async fn get_mining_stats(State(node): State<SharedNode>) -> Json<MiningStatsResponse> {
+    let stats = chain_stats(&node.tip().state);
+
+    Json(MiningStatsResponse {
+        height: stats.height,
+        difficulty: hex::encode(stats.difficulty),
+        expected_hashes: stats.expected_hashes,
+        hashrate: stats.hashrate,
+        average_interval: stats.average_interval,
+        next_retarget_height: stats.next_retarget_height,
+        seconds_to_retarget: stats.seconds_to_retarget,
+    })
+}
+
+pub async fn get_difficulty_history(
+    State(node): State<SharedNode>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<DifficultyPoint>>, ApiError> {
+    let count = query.count.unwrap_or(MAX_HISTORY);
+    if count > MAX_HISTORY {
+        return Err(ApiError::bad_request(format!(
+            "at most {MAX_HISTORY} heights can be requested at once"
+        )));
+    }
+
+    let points = node
+        .read(|node| difficulty_history(&node.chain, query.from, count))
+        .into_iter()
+        .map(|(height, difficulty)| DifficultyPoint {
+            height,
+            difficulty: hex::encode(difficulty),
+            expected_hashes: expected_hashes(&difficulty),
+        })
+        .collect();
+
+    Ok(Json(points))
+}
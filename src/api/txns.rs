@@ -0,0 +1,54 @@
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::api::json::{Encoded, RenameJson, TxnJson};
+use crate::api::{body, unix_millis, ApiError};
+use crate::context::SharedNode;
+use crate::*;
+
+// Submitting txns and renames. Each is checked against the tip and everything already pending, the same as one
+// from a peer would be, then passed on to peers once it's in the pool.
+
+pub fn routes() -> Router<SharedNode> {
+    Router::new()
+        .route("/txns", post(submit_txn))
+        .route("/renames", post(submit_rename))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubmitResponse {
+    pub hash: String,
+}
+
+pub async fn submit_txn(
+    State(node): State<SharedNode>,
+    payload: Result<Json<Encoded<TxnJson>>, JsonRejection>,
+) -> Result<Json<SubmitResponse>, ApiError> {
+    let txn = body(payload)?.into_txn()?;
+    let hash = txn_hash(&txn);
+
+    let message = node.write(|node| node.submit_txn(txn, unix_millis()))?;
+    node.relay(message);
+
+    Ok(Json(SubmitResponse {
+        hash: hex::encode(hash),
+    }))
+}
+
+pub async fn submit_rename(
+    State(node): State<SharedNode>,
+    payload: Result<Json<Encoded<RenameJson>>, JsonRejection>,
+) -> Result<Json<SubmitResponse>, ApiError> {
+    let op = body(payload)?.into_rename()?;
+    let hash = name_change_hash(&op);
+
+    let message = node.write(|node| node.submit_rename(op, unix_millis()))?;
+    node.relay(message);
+
+    Ok(Json(SubmitResponse {
+        hash: hex::encode(hash),
+    }))
+}
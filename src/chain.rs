@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use crate::*;
+
+// Stores every block that connects to our genesis and keeps `state` on the longest branch.
+// Difficulty doesn't adjust yet, so the longest branch is also the one with the most work.
+pub struct Chain {
+    pub state: BlockchainState,
+    genesis_header: Header,
+    genesis_hash: [u8; 32],
+    genesis_difficulty: [u8; 32],
+    blocks: HashMap<[u8; 32], StoredBlock>,
+    // Hashes of the blocks on the main branch. The block at height h is at index h - 1.
+    main_chain: Vec<[u8; 32]>,
+    undo_blocks: Vec<UndoBlock>,
+}
+
+struct StoredBlock {
+    block: Block,
+    height: usize,
+    // What the block was checked against. Set again when it's connected, since side chain blocks only had to meet
+    // the difficulty at our tip.
+    difficulty: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockStatus {
+    AlreadyKnown,
+    // The parent isn't known yet. The caller should go and fetch it.
+    Orphan,
+    // Stored, but its branch isn't longer than the main one
+    SideChain,
+    // The block is now on the main branch. `disconnected` is ordered from the old tip down,
+    // `connected` from the fork point up.
+    Connected {
+        disconnected: Vec<Block>,
+        connected: Vec<Block>,
+    },
+}
+
+impl Chain {
+    // `genesis_state` is the state after the genesis block has been applied
+    pub fn new(genesis_state: BlockchainState) -> Self {
+        Chain {
+            genesis_header: genesis_state.previous_block_header.clone(),
+            genesis_hash: hash_header(&genesis_state.previous_block_header),
+            genesis_difficulty: genesis_state.difficulty,
+            state: genesis_state,
+            blocks: HashMap::new(),
+            main_chain: vec![],
+            undo_blocks: vec![],
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.main_chain.len()
+    }
+
+    pub fn genesis_hash(&self) -> [u8; 32] {
+        self.genesis_hash
+    }
+
+    pub fn tip_hash(&self) -> [u8; 32] {
+        self.main_chain.last().copied().unwrap_or(self.genesis_hash)
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        *hash == self.genesis_hash || self.blocks.contains_key(hash)
+    }
+
+    pub fn block(&self, hash: &[u8; 32]) -> Option<&Block> {
+        self.blocks.get(hash).map(|stored| &stored.block)
+    }
+
+    // Unlike `block`, this also knows the genesis header
+    pub fn header(&self, hash: &[u8; 32]) -> Option<&Header> {
+        if *hash == self.genesis_hash {
+            return Some(&self.genesis_header);
+        }
+        self.block(hash).map(|block| &block.header)
+    }
+
+    // Only looks at the main branch. Height 0 is genesis, which isn't stored as a block.
+    pub fn block_at(&self, height: usize) -> Option<&Block> {
+        let hash = self.main_chain.get(height.checked_sub(1)?)?;
+        self.block(hash)
+    }
+
+    // Main branch only, including genesis at height 0
+    pub fn hash_at(&self, height: usize) -> Option<[u8; 32]> {
+        match height {
+            0 => Some(self.genesis_hash),
+            height => self.main_chain.get(height - 1).copied(),
+        }
+    }
+
+    // The difficulty the main branch block at `height` had to meet. Genesis reports the starting difficulty.
+    pub fn difficulty_at(&self, height: usize) -> Option<[u8; 32]> {
+        if height == 0 {
+            return Some(self.genesis_difficulty);
+        }
+        self.difficulty(self.main_chain.get(height - 1)?)
+    }
+
+    // The same for any stored block, on the main branch or not
+    pub fn difficulty(&self, hash: &[u8; 32]) -> Option<[u8; 32]> {
+        if *hash == self.genesis_hash {
+            return Some(self.genesis_difficulty);
+        }
+        self.blocks.get(hash).map(|stored| stored.difficulty)
+    }
+
+    pub fn block_height(&self, hash: &[u8; 32]) -> Option<usize> {
+        if *hash == self.genesis_hash {
+            return Some(0);
+        }
+        self.blocks.get(hash).map(|stored| stored.height)
+    }
+
+    pub fn is_main_chain(&self, hash: &[u8; 32]) -> bool {
+        match self.block_height(hash) {
+            Some(0) => true,
+            Some(height) => self.main_chain.get(height - 1) == Some(hash),
+            None => false,
+        }
+    }
+
+    // Full validation only happens when a block is connected, since that's the only time we have the state it
+    // builds on. Anything else only has to show proof of work to be stored.
+    pub fn add_block(&mut self, block: Block) -> Result<BlockStatus, Error> {
+        let hash = hash_header(&block.header);
+
+        if self.contains(&hash) {
+            return Ok(BlockStatus::AlreadyKnown);
+        }
+
+        if !meets_difficulty(&hash, &self.state.difficulty) {
+            block_validation_error!("Header hash does not meet required difficulty");
+        }
+
+        let Some(parent_height) = self.block_height(&block.header.prev_block_hash) else {
+            return Ok(BlockStatus::Orphan);
+        };
+
+        let height = parent_height + 1;
+        let difficulty = self.state.difficulty;
+        self.blocks.insert(
+            hash,
+            StoredBlock {
+                block,
+                height,
+                difficulty,
+            },
+        );
+
+        if height <= self.height() {
+            return Ok(BlockStatus::SideChain);
+        }
+
+        self.reorganize_to(hash)
+    }
+
+    // Moves the main branch onto the branch ending at `new_tip`. If any block on the way fails to validate,
+    // the old branch is put back.
+    fn reorganize_to(&mut self, new_tip: [u8; 32]) -> Result<BlockStatus, Error> {
+        let mut branch = vec![];
+        let mut hash = new_tip;
+
+        while !self.is_main_chain(&hash) {
+            branch.push(hash);
+            hash = self.blocks[&hash].block.header.prev_block_hash;
+        }
+        branch.reverse();
+
+        let fork_height = self.block_height(&hash).unwrap();
+        let mut disconnected = vec![];
+
+        while self.height() > fork_height {
+            disconnected.push(self.disconnect_tip());
+        }
+
+        for (i, hash) in branch.iter().enumerate() {
+            let block = self.blocks[hash].block.clone();
+
+            if let Err(e) = validate_block(&block, &self.state) {
+                // Forget the bad block and everything built on it so the branch can't be retried over and over
+                for hash in branch[i..].iter() {
+                    self.blocks.remove(hash);
+                }
+                for _ in 0..i {
+                    self.disconnect_tip();
+                }
+                for block in disconnected.iter().rev() {
+                    self.connect(block.clone());
+                }
+                return Err(e);
+            }
+
+            self.connect(block);
+        }
+
+        Ok(BlockStatus::Connected {
+            disconnected,
+            connected: branch
+                .iter()
+                .map(|h| self.blocks[h].block.clone())
+                .collect(),
+        })
+    }
+
+    fn connect(&mut self, block: Block) {
+        let hash = hash_header(&block.header);
+        self.blocks.get_mut(&hash).unwrap().difficulty = self.state.difficulty;
+        self.main_chain.push(hash);
+        // Only blocks that have passed validate_block against this state are connected
+        let undo_block = push_block(block, &mut self.state).expect("connected blocks are valid");
+        self.undo_blocks.push(undo_block);
+    }
+
+    fn disconnect_tip(&mut self) -> Block {
+        let hash = self.main_chain.pop().unwrap();
+        let undo_block = self.undo_blocks.pop().unwrap();
+        pop_block(&undo_block, &mut self.state);
+        self.blocks[&hash].block.clone()
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use tokio::sync::broadcast;
+
+use crate::events::{owner_changes, Event};
+use crate::net::node::Node;
+use crate::net::Message;
+use crate::*;
+
+// Everything the server's tasks share about the node.
+//
+// The node sits behind a mutex, since connecting a block touches the chain and both pools together. Reads which only
+// need the chain state go through a snapshot of the tip instead. The snapshot is replaced whole, while the node is
+// still locked, once a write has moved the tip. Those reads never wait on block validation and never see a state
+// halfway through being updated.
+//
+// Whatever carries messages to peers subscribes to the relay channel, and gets everything the node's own users
+// submitted. Services following the node subscribe to the event channel, which gets everything the node recorded
+// during each write, followed by the name ownership changes the write made.
+
+pub type SharedNode = Arc<NodeContext>;
+
+// Relay messages a slow subscriber can fall behind by before it starts missing them
+pub const RELAY_CAPACITY: usize = 1_024;
+
+// The same for events. A reorg sends one per block moved.
+pub const EVENT_CAPACITY: usize = 4_096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TipSnapshot {
+    pub hash: [u8; 32],
+    pub state: BlockchainState,
+}
+
+pub struct NodeContext {
+    node: Mutex<Node>,
+    tip: RwLock<Arc<TipSnapshot>>,
+    relay: broadcast::Sender<Message>,
+    events: broadcast::Sender<Event>,
+}
+
+impl NodeContext {
+    pub fn new(mut node: Node) -> SharedNode {
+        node.record_events();
+        let tip = TipSnapshot {
+            hash: node.chain.tip_hash(),
+            state: node.chain.state.clone(),
+        };
+
+        Arc::new(NodeContext {
+            node: Mutex::new(node),
+            tip: RwLock::new(Arc::new(tip)),
+            relay: broadcast::channel(RELAY_CAPACITY).0,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        })
+    }
+
+    // The chain state at the tip. Holding on to it doesn't hold anything else up.
+    pub fn tip(&self) -> Arc<TipSnapshot> {
+        self.tip.read().unwrap().clone()
+    }
+
+    // For reads which need the pools or the block store too. `f` holds up block validation, so keep it short.
+    pub fn read<R>(&self, f: impl FnOnce(&Node) -> R) -> R {
+        f(&self.node.lock().unwrap())
+    }
+
+    // Sends `message` to every peer. Dropped if nothing is connected.
+    pub fn relay(&self, message: Message) {
+        let _ = self.relay.send(message);
+    }
+
+    pub fn subscribe_relay(&self) -> broadcast::Receiver<Message> {
+        self.relay.subscribe()
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    pub fn write<R>(&self, f: impl FnOnce(&mut Node) -> R) -> R {
+        let mut node = self.node.lock().unwrap();
+        let result = f(&mut node);
+        let mut events = node.take_events();
+
+        let hash = node.chain.tip_hash();
+        let before = self.tip();
+        if hash != before.hash {
+            let names = &node.chain.state.name_set;
+            events.extend(owner_changes(&before.state.name_set, names, &events));
+
+            *self.tip.write().unwrap() = Arc::new(TipSnapshot {
+                hash,
+                state: node.chain.state.clone(),
+            });
+        }
+
+        // Sent under the lock, so subscribers see events in the order they happened
+        for event in events {
+            let _ = self.events.send(event);
+        }
+
+        result
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{BTreeSet, HashSet};
+
+use crate::mempool::Eviction;
+use crate::*;
+
+// Things happening to the node that services outside it want to hear about as they happen, instead of polling.
+//
+// The node records block and pool events while it works. Name ownership changes can't be told from the blocks
+// alone, since a disconnected block doesn't say who owned a name before it, so they're found afterwards by comparing
+// the name sets from either side of the change (see NodeContext::write).
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    BlockConnected {
+        block: Block,
+        height: usize,
+    },
+    BlockDisconnected {
+        block: Block,
+        height: usize,
+    },
+    // Accepted into the mempool, from a peer or one of our own. `keys` are the sender's and the recievers', with
+    // names resolved as they were when it arrived.
+    Txn {
+        txn: Txn,
+        keys: Vec<[u8; 32]>,
+    },
+    // Dropped from the mempool without being mined
+    TxnEvicted(Eviction),
+    // `owner` is who held the name when the rename arrived. They had to sign it.
+    Rename {
+        op: RenameOp,
+        owner: Option<[u8; 32]>,
+    },
+    NameOwner {
+        name: String,
+        old_owner: Option<[u8; 32]>,
+        new_owner: Option<[u8; 32]>,
+    },
+}
+
+pub fn txn_event(txn: &Txn, blockchain_state: &BlockchainState) -> Event {
+    let keys = std::iter::once(&txn.sender)
+        .chain(txn.recievers.iter().map(|(address, _)| address))
+        .filter_map(|address| address_to_key(address, &blockchain_state.name_set).ok())
+        .collect();
+
+    Event::Txn {
+        txn: txn.clone(),
+        keys,
+    }
+}
+
+pub fn rename_event(op: &RenameOp, blockchain_state: &BlockchainState) -> Event {
+    Event::Rename {
+        op: op.clone(),
+        owner: blockchain_state.name_set.get(&op.new_name).copied(),
+    }
+}
+
+// Every name renamed by a block in `events` whose owner differs between `before` and `after`, sorted by name
+pub fn owner_changes(before: &Names, after: &Names, events: &[Event]) -> Vec<Event> {
+    let renamed: BTreeSet<&String> = events
+        .iter()
+        .flat_map(|event| match event {
+            Event::BlockConnected { block, .. } | Event::BlockDisconnected { block, .. } => {
+                block.name_changes.as_slice()
+            }
+            _ => &[],
+        })
+        .map(|op| &op.new_name)
+        .collect();
+
+    renamed
+        .into_iter()
+        .filter_map(|name| {
+            let old_owner = before.get(name).copied();
+            let new_owner = after.get(name).copied();
+            (old_owner != new_owner).then(|| Event::NameOwner {
+                name: name.clone(),
+                old_owner,
+                new_owner,
+            })
+        })
+        .collect()
+}
+
+// Narrows a stream down to the events touching some accounts or names. An empty filter lets everything through.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    pub keys: HashSet<[u8; 32]>,
+    pub names: HashSet<String>,
+}
+
+impl EventFilter {
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.names.is_empty()
+    }
+
+    // Block events always pass, since whoever is filtering still needs to know when the chain moves under them
+    pub fn matches(&self, event: &Event) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        let has_key = |key: &Option<[u8; 32]>| key.is_some_and(|key| self.keys.contains(&key));
+
+        match event {
+            Event::BlockConnected { .. } | Event::BlockDisconnected { .. } => true,
+            Event::Txn { txn, keys } => {
+                keys.iter().any(|key| self.keys.contains(key))
+                    || std::iter::once(&txn.sender)
+                        .chain(txn.recievers.iter().map(|(address, _)| address))
+                        .any(|address| {
+                            matches!(address, Address::Name(name) if self.names.contains(name))
+                        })
+            }
+            Event::TxnEvicted(eviction) => self.keys.contains(&eviction.sender),
+            Event::Rename { op, owner } => {
+                self.keys.contains(&op.pk) || has_key(owner) || self.names.contains(&op.new_name)
+            }
+            Event::NameOwner {
+                name,
+                old_owner,
+                new_owner,
+            } => self.names.contains(name) || has_key(old_owner) || has_key(new_owner),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+
+use crate::mempool::{fee_rate, Mempool};
+use crate::*;
+
+// Recommends a fee per byte for getting a txn confirmed within some number of blocks.
+//
+// Two things are looked at, and whichever asks for more wins:
+// 1. What recent blocks actually included. The sooner the target, the higher up the spread of included rates we go.
+// 2. The mempool queue. If the txns paying more than some rate already fill the blocks until the target, a new txn
+//    has to pay more than that rate to get in ahead of them.
+
+// How many recent blocks are remembered
+pub const FEE_HISTORY_BLOCKS: usize = 100;
+
+#[derive(Debug, Clone, Default)]
+pub struct FeeEstimator {
+    // The fee rates of each recent block's txns, sorted. Oldest block first.
+    blocks: VecDeque<Vec<u64>>,
+}
+
+impl FeeEstimator {
+    pub fn new() -> Self {
+        FeeEstimator::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn block_connected(&mut self, block: &Block) {
+        // Skipping the coinbase, which pays no fee
+        let mut rates: Vec<u64> = block
+            .txns
+            .iter()
+            .skip(1)
+            .map(|t| fee_rate(t.fee, encode_txn(t).len()))
+            .collect();
+        rates.sort();
+
+        self.blocks.push_back(rates);
+        if self.blocks.len() > FEE_HISTORY_BLOCKS {
+            self.blocks.pop_front();
+        }
+    }
+
+    // Blocks are only ever disconnected from the tip, so this forgets the newest one
+    pub fn block_disconnected(&mut self) {
+        self.blocks.pop_back();
+    }
+
+    // The fee rate which recent blocks suggest is needed. A target of n blocks takes the rate 1/(n+1) of the way up
+    // the included rates, so a target of 1 takes the median.
+    pub fn history_estimate(&self, target: usize) -> u64 {
+        let mut rates: Vec<u64> = self.blocks.iter().flatten().copied().collect();
+
+        if rates.is_empty() {
+            return TXN_FEES_PER_BYTE;
+        }
+
+        rates.sort();
+        let index = rates.len() / (target.max(1) + 1);
+        rates[index].max(TXN_FEES_PER_BYTE)
+    }
+
+    // The fee rate needed to get ahead of enough of the mempool to fit in the next `target` blocks.
+    // Only the space miners can fill without losing any coinbase is counted.
+    pub fn mempool_estimate(
+        &self,
+        mempool: &Mempool,
+        target: usize,
+        blockchain_state: &BlockchainState,
+    ) -> u64 {
+        let median = median_block_size(&blockchain_state.last_100_block_sizes);
+        let space = target.max(1) * (penalty_free_block_size(median) - HEADER_SIZE);
+
+        let mut ahead = 0;
+        for entry in mempool.by_fee_rate() {
+            ahead += entry.size;
+
+            if ahead > space {
+                return entry.fee_rate() + 1;
+            }
+        }
+
+        TXN_FEES_PER_BYTE
+    }
+
+    pub fn estimate(
+        &self,
+        mempool: &Mempool,
+        target: usize,
+        blockchain_state: &BlockchainState,
+    ) -> u64 {
+        self.history_estimate(target)
+            .max(self.mempool_estimate(mempool, target, blockchain_state))
+    }
+}
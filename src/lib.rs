@@ -1,16 +1,16 @@
 use secp256k1::{schnorr::Signature, Keypair, Secp256k1, XOnlyPublicKey};
 use sha2::{Digest, Sha256};
-use std::{collections::HashMap, env::consts::OS};
+use std::collections::{BTreeSet, HashMap};
 use thiserror::Error;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Block {
     pub header: Header,
     pub txns: Vec<Txn>,
     pub name_changes: Vec<RenameOp>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Txn {
     pub sender: Address,
     pub recievers: Vec<(Address, u64)>,
@@ -18,7 +18,7 @@ pub struct Txn {
     pub fee: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Address {
     Key([u8; 32]),
     Name(String),
@@ -26,7 +26,7 @@ pub enum Address {
 
 // In a rename operation, the fee is always paid by the new pk.
 // If a person already owns the name, their pk must be the one that signs this txn. Otherwise, the new pk signs it.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct RenameOp {
     pub pk: [u8; 32],
     pub sig: [u8; 64],
@@ -46,11 +46,14 @@ pub struct Header {
 pub struct BlockchainState {
     pub account_set: Accounts,
     pub name_set: Names,
+    // The reverse of name_set. Accounts without names aren't in it.
+    pub owned_names: OwnedNames,
     pub difficulty: [u8; 32],
     pub height: usize,
     pub last_720_times: [u64; 720],
     pub last_100_block_sizes: [usize; 100],
     pub previous_block_header: Header,
+    pub network: Network,
 }
 
 pub struct UndoBlock {
@@ -69,6 +72,7 @@ pub struct RenameOpUndo {
 
 pub type Accounts = HashMap<[u8; 32], u64>;
 pub type Names = HashMap<String, [u8; 32]>;
+pub type OwnedNames = HashMap<[u8; 32], BTreeSet<String>>;
 
 pub const HEADER_SIZE: usize = 80;
 
@@ -77,6 +81,15 @@ pub const NAME_CHANGE_FEES_PER_BYTE: u64 = 100_000_000;
 
 pub const DEFAULT_COINBASE: u64 = 200_000_000_000;
 
+// Names are length prefixed with a single byte
+pub const MAX_NAME_SIZE: usize = 255;
+
+// Space above the median which a block can use without its coinbase being penalized
+pub const FREE_BLOCK_SPACE: usize = 10_000;
+
+// Blocks up to this size are always allowed, no matter how small the median is
+pub const MIN_MAX_BLOCK_SIZE: usize = 20_000;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("The block failed to validate because {0}")]
@@ -85,6 +98,18 @@ pub enum Error {
     TxnValidationError(String),
     #[error("A transaction referenced a name that is not in the name set")]
     MissingDataError,
+    #[error("Data could not be decoded because {0}")]
+    DecodeError(String),
+    #[error("A peer broke a network limit because {0}")]
+    PeerLimitError(String),
+    #[error("An io operation failed: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("The peer connection failed because {0}")]
+    TransportError(String),
+    #[error("The mempool rejected an item because {0}")]
+    MempoolError(String),
+    #[error("A share was rejected because {0}")]
+    ShareError(String),
 }
 
 macro_rules! block_validation_error {
@@ -93,38 +118,158 @@ macro_rules! block_validation_error {
     };
 }
 
+macro_rules! decode_error {
+    ($x:expr) => {
+        return Err(Error::DecodeError($x.into()))
+    };
+}
+
 macro_rules! txn_validation_error {
     ($x:expr) => {
         return Err(Error::TxnValidationError($x.into()))
     };
 }
 
+macro_rules! peer_limit_error {
+    ($x:expr) => {
+        return Err(Error::PeerLimitError($x.into()))
+    };
+}
+
+macro_rules! transport_error {
+    ($x:expr) => {
+        return Err(Error::TransportError($x.into()))
+    };
+}
+
+macro_rules! mempool_error {
+    ($x:expr) => {
+        return Err(Error::MempoolError($x.into()))
+    };
+}
+
+macro_rules! share_error {
+    ($x:expr) => {
+        return Err(Error::ShareError($x.into()))
+    };
+}
+
+// Declared below the error macros so the submodules can use them
+pub mod api;
+pub mod chain;
+pub mod context;
+pub mod events;
+pub mod fees;
+pub mod mempool;
+pub mod miner;
+pub mod net;
+pub mod params;
+pub mod pool;
+pub mod stats;
+pub mod template;
+
+pub use params::Network;
+
+// What each txn in a block moves: the sender, their total spend, and the key behind each reciever
+type ResolvedTxn = ([u8; 32], u64, Vec<[u8; 32]>);
+
+// Plays the block's payments and rename fees against a scratch copy of the balances they touch, so push_block can
+// turn away a block it can't apply before changing anything. Names are resolved as they stand before the block, the
+// same as push_block does, since renames only take effect after every txn.
+fn resolve_block(
+    block: &Block,
+    blockchain_state: &BlockchainState,
+) -> Result<Vec<ResolvedTxn>, Error> {
+    let account_set = &blockchain_state.account_set;
+    let name_set = &blockchain_state.name_set;
+    let mut balances: HashMap<[u8; 32], u64> = HashMap::new();
+    let mut resolved = vec![];
+
+    // Missing accounts hold 0
+    let balance_of = |balances: &HashMap<[u8; 32], u64>, key: &[u8; 32]| {
+        balances
+            .get(key)
+            .or(account_set.get(key))
+            .copied()
+            .unwrap_or(0)
+    };
+
+    let debit = |balances: &mut HashMap<[u8; 32], u64>, key: [u8; 32], amount: u64| {
+        let balance = balance_of(balances, &key);
+        match balance.checked_sub(amount) {
+            Some(left) if balance > 0 => {
+                balances.insert(key, left);
+                Ok(())
+            }
+            _ => Err(Error::BlockValidationError(
+                "Block spends more than an account holds".into(),
+            )),
+        }
+    };
+
+    for txn in block.txns.iter() {
+        let total_spend = txn_total_spend(txn)?;
+        let sender = address_to_key(&txn.sender, name_set)?;
+
+        if sender != [0; 32] {
+            debit(&mut balances, sender, total_spend)?;
+        }
+
+        let mut recievers = vec![];
+        for reciever in txn.recievers.iter() {
+            let account = address_to_key(&reciever.0, name_set)?;
+
+            if XOnlyPublicKey::from_byte_array(&account).is_ok() {
+                let Some(balance) = balance_of(&balances, &account).checked_add(reciever.1) else {
+                    block_validation_error!("Block overflows an account's balance");
+                };
+                balances.insert(account, balance);
+            }
+
+            recievers.push(account);
+        }
+
+        resolved.push((sender, total_spend, recievers));
+    }
+
+    for op in block.name_changes.iter() {
+        debit(&mut balances, op.pk, op.fee)?;
+    }
+
+    Ok(resolved)
+}
+
 // ! TODO Add difficulty adjustment
 // Takes a validated block and updates the account set
-pub fn push_block(block: Block, blockchain_state: &mut BlockchainState) -> UndoBlock {
+// Errors without changing anything if the block spends money that isn't there or pays a name nobody owns, which
+// validate_block rules out.
+pub fn push_block(
+    block: Block,
+    blockchain_state: &mut BlockchainState,
+) -> Result<UndoBlock, Error> {
+    let resolved = resolve_block(&block, blockchain_state)?;
+
     let account_set = &mut blockchain_state.account_set;
     let name_set = &mut blockchain_state.name_set;
+    let owned_names = &mut blockchain_state.owned_names;
     let prev_block_header = blockchain_state.previous_block_header.clone();
 
     // Any time a name change occurs, the data must be stored in case of an undo. This is later stored in the undo block.
     let mut name_undos = vec![];
 
-    // Execute transactions
-    for txn in block.txns.iter() {
-        let total_spend = txn_total_spend(txn);
-        let sender = address_to_key_unchecked(&txn.sender, name_set);
-        println!("SPENDING: {:?}, {}", txn.sender, total_spend);
-
+    // Execute transactions. resolve_block has checked every balance these touch.
+    for (txn, (sender, total_spend, recievers)) in block.txns.iter().zip(resolved) {
         // if sender is all 0's, it's a coinbase txn
+        // Accounts which are emptied are removed, so the account set never holds a balance of 0
         if sender != [0; 32] {
-            account_set
-                .entry(address_to_key_unchecked(&txn.sender, name_set))
-                .and_modify(|a| *a -= total_spend);
+            if account_set[&sender] == total_spend {
+                account_set.remove(&sender);
+            } else {
+                account_set.entry(sender).and_modify(|a| *a -= total_spend);
+            }
         }
 
-        for reciever in txn.recievers.iter() {
-            let account = address_to_key_unchecked(&reciever.0, name_set);
-
+        for (reciever, account) in txn.recievers.iter().zip(recievers) {
             // If money is sent to an invalid address, it can never be spent. This is considered a burn and is allowed.
             if XOnlyPublicKey::from_byte_array(&account).is_err() {
                 continue;
@@ -140,12 +285,12 @@ pub fn push_block(block: Block, blockchain_state: &mut BlockchainState) -> UndoB
     // Execute name changes
     for op in block.name_changes.iter() {
         name_undos.push(RenameOpUndo {
-            old_pk: name_set.get(&op.new_name.clone()).map(|v| *v),
+            old_pk: name_set.get(&op.new_name).copied(),
             name: op.new_name.clone(),
             fee: op.fee,
         });
 
-        name_set.insert(op.new_name.clone(), op.pk);
+        set_name_owner(name_set, owned_names, &op.new_name, Some(op.pk));
 
         if account_set[&op.pk] == op.fee {
             account_set.remove(&op.pk);
@@ -162,14 +307,15 @@ pub fn push_block(block: Block, blockchain_state: &mut BlockchainState) -> UndoB
     );
 
     blockchain_state.previous_block_header = block.header;
+    blockchain_state.height += 1;
 
-    UndoBlock {
+    Ok(UndoBlock {
         removed_time,
         removed_block_size,
         txns: block.txns,
         name_changes: name_undos,
         prev_block_header,
-    }
+    })
 }
 
 // ! TODO Add difficulty adjustment
@@ -178,20 +324,23 @@ pub fn push_block(block: Block, blockchain_state: &mut BlockchainState) -> UndoB
 pub fn pop_block(undo_block: &UndoBlock, blockchain_state: &mut BlockchainState) {
     let account_set = &mut blockchain_state.account_set;
     let name_set = &mut blockchain_state.name_set;
+    let owned_names = &mut blockchain_state.owned_names;
 
-    for name_change in undo_block.name_changes.iter() {
-        if name_change.old_pk.is_some() {
-            name_set.insert(name_change.name.clone(), name_change.old_pk.unwrap());
-        }
-    }
-
-    for txn in undo_block.txns.iter() {
-        let total_spend = txn_total_spend(txn);
+    // Renames are undone newest first so a name claimed twice in one block ends up with its original owner
+    for name_change in undo_block.name_changes.iter().rev() {
+        // The fee was paid by whoever the name was given to
+        let new_pk = name_set[&name_change.name];
 
         account_set
-            .entry(address_to_key_unchecked(&txn.sender, name_set))
-            .and_modify(|a| *a += total_spend);
+            .entry(new_pk)
+            .and_modify(|a| *a += name_change.fee)
+            .or_insert(name_change.fee);
 
+        set_name_owner(name_set, owned_names, &name_change.name, name_change.old_pk);
+    }
+
+    // Txns are undone in the reverse of the order push_block applied them, recievers before senders
+    for txn in undo_block.txns.iter().rev() {
         for reciever in txn.recievers.iter() {
             // if the reciever has a balance equal to as much as they were sent in this txn, their balance will be 0 after. Remove from the account set.
             let key = address_to_key_unchecked(&reciever.0, name_set);
@@ -206,6 +355,17 @@ pub fn pop_block(undo_block: &UndoBlock, blockchain_state: &mut BlockchainState)
                 account_set.entry(key).and_modify(|a| *a -= reciever.1);
             }
         }
+
+        let sender = address_to_key_unchecked(&txn.sender, name_set);
+        // push_block already summed this without overflowing
+        let total_spend = txn_total_spend(txn).unwrap();
+
+        if sender != [0; 32] {
+            account_set
+                .entry(sender)
+                .and_modify(|a| *a += total_spend)
+                .or_insert(total_spend);
+        }
     }
 
     push_to_back(
@@ -219,13 +379,14 @@ pub fn pop_block(undo_block: &UndoBlock, blockchain_state: &mut BlockchainState)
     );
 
     blockchain_state.previous_block_header = undo_block.prev_block_header.clone();
+    blockchain_state.height -= 1;
 }
 
 // Takes a block and ensures that it meets all required rules
 pub fn validate_block(block: &Block, blockchain_state: &BlockchainState) -> Result<(), Error> {
     // validate header
 
-    if block.txns.len() < 1 {
+    if block.txns.is_empty() {
         block_validation_error!("The block contains no transactions (coinbase txn is mandatory)")
     }
 
@@ -250,19 +411,19 @@ pub fn validate_block(block: &Block, blockchain_state: &BlockchainState) -> Resu
     // validate other qualities
 
     let median_block_size = median_block_size(&blockchain_state.last_100_block_sizes);
-    let block_size = block_size(&block);
+    let block_size = block_size(block);
 
-    if block_size > 20_000 && block_size > 2 * median_block_size {
+    if block_size > max_block_size(median_block_size) {
         block_validation_error!("Block is bigger than twice the median block size")
     }
 
-    check_txns(
+    let txn_spend = check_txns(
         &block.txns,
         blockchain_state,
         calc_coinbase(block_size, median_block_size),
     )?;
 
-    check_name_changes(&block.name_changes, &blockchain_state.name_set)?;
+    check_name_changes(&block.name_changes, blockchain_state, &txn_spend)?;
 
     Ok(())
 }
@@ -271,34 +432,54 @@ pub fn validate_block(block: &Block, blockchain_state: &BlockchainState) -> Resu
 // --- NAME CHANGE VALIDATION FUNCTIONS
 //
 
-pub fn check_name_changes(op_list: &Vec<RenameOp>, name_set: &Names) -> Result<(), Error> {
+// `txn_spend` is what each key spent on the block's txns. Rename fees have to come out of what's left of the payer's
+// balance at the start of the block, so money recieved in the same block can't pay for them.
+pub fn check_name_changes(
+    op_list: &[RenameOp],
+    blockchain_state: &BlockchainState,
+    txn_spend: &HashMap<[u8; 32], u64>,
+) -> Result<(), Error> {
+    let mut total_spend = txn_spend.clone();
+
     for op in op_list.iter() {
-        check_name_change(&op, &name_set)?;
+        check_name_change(op, blockchain_state)?;
+
+        let Some(balance) = blockchain_state.account_set.get(&op.pk).copied() else {
+            txn_validation_error!("Rename payer has no account");
+        };
+        let spent = total_spend.entry(op.pk).or_insert(0);
+
+        if op.fee > balance - *spent {
+            txn_validation_error!("Rename payer can't afford the fee");
+        }
+
+        *spent += op.fee;
     }
     Ok(())
 }
 
-pub fn check_name_change(op: &RenameOp, name_set: &Names) -> Result<(), Error> {
+pub fn check_name_change(op: &RenameOp, blockchain_state: &BlockchainState) -> Result<(), Error> {
     let pk = XOnlyPublicKey::from_byte_array(&op.pk).map_err(|_| {
         Error::TxnValidationError(
             "Rename operation used a pk that isn't a point on the curve".into(),
         )
     })?;
 
-    let signer;
-
-    if name_set.contains_key(&op.new_name) {
-        signer = XOnlyPublicKey::from_byte_array(name_set.get(&op.new_name).unwrap()).unwrap();
-    } else {
-        signer = pk;
-    }
+    let signer = match blockchain_state.name_set.get(&op.new_name) {
+        Some(owner) => XOnlyPublicKey::from_byte_array(owner).unwrap(),
+        None => pk,
+    };
 
     let encoded_op = encode_name_change(op);
     let sig = Signature::from_byte_array(op.sig);
     let secp = Secp256k1::new();
 
-    secp.verify_schnorr(&sig, &hash(encoded_op.as_slice()), &pk)
-        .map_err(|_| Error::TxnValidationError("Name-change signature was invalid".into()))?;
+    secp.verify_schnorr(
+        &sig,
+        &name_change_signing_data(op, blockchain_state.network),
+        &signer,
+    )
+    .map_err(|_| Error::TxnValidationError("Name-change signature was invalid".into()))?;
 
     let fee = (encoded_op.len() as u64) * NAME_CHANGE_FEES_PER_BYTE;
 
@@ -306,7 +487,7 @@ pub fn check_name_change(op: &RenameOp, name_set: &Names) -> Result<(), Error> {
         txn_validation_error!("Rename does not pay enough in fees");
     }
 
-    if op.new_name.bytes().len() > 255 {
+    if op.new_name.len() > MAX_NAME_SIZE {
         txn_validation_error!("New name was greater than 255 bytes");
     }
 
@@ -318,20 +499,35 @@ pub fn check_name_change(op: &RenameOp, name_set: &Names) -> Result<(), Error> {
 //
 
 pub fn check_txns(
-    txn_list: &Vec<Txn>,
+    txn_list: &[Txn],
     blockchain_state: &BlockchainState,
     coinbase: u64,
-) -> Result<(), Error> {
+) -> Result<HashMap<[u8; 32], u64>, Error> {
     let mut fees = 0;
     // The cumulative amount each user has spent in the block. Used for making sure multiple transactions don't add up to more than the users total balance
     let mut total_spend: HashMap<[u8; 32], u64> = HashMap::new();
 
+    // Checked up front, so the coinbase can be indexed below
+    let Some(coinbase_txn) = txn_list.first() else {
+        block_validation_error!("The block contains no transactions (coinbase txn is mandatory)");
+    };
+
+    if coinbase_txn.sender != Address::Key([0; 32]) {
+        block_validation_error!("First txn in the block isn't a coinbase txn");
+    }
+
+    if coinbase_txn.recievers.len() != 1 {
+        block_validation_error!("Coinbase txn must have exactly 1 reciever");
+    }
+
+    check_recievers(coinbase_txn, &blockchain_state.name_set)?;
+
     for (i, txn) in txn_list.iter().enumerate() {
         if i == 0 {
             continue;
         }
 
-        check_txn(&txn, blockchain_state)?;
+        check_txn(txn, blockchain_state)?;
         let sender_key = address_to_key_unchecked(&txn.sender, &blockchain_state.name_set);
 
         // check_txn verifies the account is in the set, so this will always unwrap properly
@@ -341,32 +537,33 @@ pub fn check_txns(
             .copied()
             .unwrap();
         let current_spend = total_spend.get(&sender_key).copied().unwrap_or(0);
-        let spend = txn_total_spend(&txn);
+        let spend = txn_total_spend(txn)?;
 
-        if (spend + current_spend) > balance {
+        if spend
+            .checked_add(current_spend)
+            .is_none_or(|total| total > balance)
+        {
             txn_validation_error!("Sender tried to spend more than their balance");
         }
 
-        total_spend.entry(sender_key).and_modify(|a| *a += spend);
+        *total_spend.entry(sender_key).or_insert(0) += spend;
 
         fees += txn.fee;
     }
 
-    if txn_list[0].recievers.len() > 1 {
-        txn_validation_error!("Coinbase txn had more than 1 reciever")
-    }
-
-    if txn_list[0].recievers[0].1 > coinbase + fees {
+    if coinbase_txn.recievers[0].1 > coinbase + fees {
         txn_validation_error!("Coinbase amount is invalid")
     }
 
-    Ok(())
+    Ok(total_spend)
 }
 
 // checks the data is valid, the fee matches the txn size, but doesn't check if the amount they're trying to spend is valid
 pub fn check_txn(txn: &Txn, blockchain_state: &BlockchainState) -> Result<(), Error> {
     let sender_key = address_to_key(&txn.sender, &blockchain_state.name_set)?;
 
+    check_recievers(txn, &blockchain_state.name_set)?;
+
     let key = XOnlyPublicKey::from_byte_array(&sender_key).map_err(|_| {
         Error::TxnValidationError("The sender's public key isn't a point on the curve".into())
     })?;
@@ -374,11 +571,8 @@ pub fn check_txn(txn: &Txn, blockchain_state: &BlockchainState) -> Result<(), Er
     let curve = secp256k1::Secp256k1::new();
     let sig = Signature::from_byte_array(txn.signature);
 
-    let mut txn = txn.clone();
-    txn.signature = [0; 64];
-
     curve
-        .verify_schnorr(&sig, &encode_txn(&txn), &key)
+        .verify_schnorr(&sig, &txn_signing_data(txn, blockchain_state.network), &key)
         .map_err(|e| Error::TxnValidationError(e.to_string()))?;
 
     blockchain_state
@@ -388,13 +582,25 @@ pub fn check_txn(txn: &Txn, blockchain_state: &BlockchainState) -> Result<(), Er
             "The sender's pk isn't in the account set".into(),
         ))?;
 
-    let size = encode_txn(&txn).len() as u64;
+    let size = encode_txn(txn).len() as u64;
     let min_fee = TXN_FEES_PER_BYTE * size;
 
     if txn.fee < min_fee {
         txn_validation_error!("Txn doesn't pay enough in fees");
     }
 
+    txn_total_spend(txn)?;
+
+    Ok(())
+}
+
+// A name nobody owns has no account behind it to credit, so paying one is invalid rather than a burn
+pub fn check_recievers(txn: &Txn, names: &Names) -> Result<(), Error> {
+    for (address, _) in txn.recievers.iter() {
+        if address_to_key(address, names).is_err() {
+            txn_validation_error!("Txn pays a name that nobody owns");
+        }
+    }
     Ok(())
 }
 
@@ -402,17 +608,17 @@ pub fn check_txn(txn: &Txn, blockchain_state: &BlockchainState) -> Result<(), Er
 // --- HEADER VALIDATION FUNCTIONS ---
 //
 
-pub fn merkle_root(txn_list: &Vec<Txn>, name_changes: &Vec<RenameOp>) -> [u8; 32] {
-    if txn_list.len() == 0 && name_changes.len() == 0 {
+pub fn merkle_root(txn_list: &[Txn], name_changes: &[RenameOp]) -> [u8; 32] {
+    if txn_list.is_empty() && name_changes.is_empty() {
         return [0; 32];
     }
 
-    let mut hashes: Vec<[u8; 32]> = txn_list.iter().map(|txn| txn_hash(&txn)).collect();
+    let mut hashes: Vec<[u8; 32]> = txn_list.iter().map(txn_hash).collect();
 
     hashes.extend(
         name_changes
             .iter()
-            .map(|op| name_change_hash(&op))
+            .map(name_change_hash)
             .collect::<Vec<[u8; 32]>>()
             .iter(),
     );
@@ -430,7 +636,7 @@ pub fn merkle_root(txn_list: &Vec<Txn>, name_changes: &Vec<RenameOp>) -> [u8; 32
                 data[32..64].copy_from_slice(&hashes[i]);
             }
 
-            new_hashes.push(sha2::Sha256::digest(data).try_into().unwrap());
+            new_hashes.push(sha2::Sha256::digest(data).into());
         }
 
         hashes = new_hashes;
@@ -451,7 +657,7 @@ pub fn meets_difficulty(value: &[u8; 32], difficulty: &[u8; 32]) -> bool {
         }
     }
 
-    return true;
+    true
 }
 
 // --- RANDOM UTILITY FUNCTIONS
@@ -507,6 +713,22 @@ pub fn encode_address(address: &Address, data: &mut Vec<u8>) {
     }
 }
 
+pub fn encode_block(block: &Block) -> Vec<u8> {
+    let mut data = encode_header(&block.header).to_vec();
+
+    data.extend((block.txns.len() as u32).to_le_bytes());
+    for txn in block.txns.iter() {
+        data.extend(encode_txn(txn));
+    }
+
+    data.extend((block.name_changes.len() as u32).to_le_bytes());
+    for rename in block.name_changes.iter() {
+        data.extend(encode_name_change(rename));
+    }
+
+    data
+}
+
 pub fn block_size(block: &Block) -> usize {
     let mut size = HEADER_SIZE;
 
@@ -514,7 +736,7 @@ pub fn block_size(block: &Block) -> usize {
     size += 4;
 
     for txn in block.txns.iter() {
-        size += encode_txn(&txn).len();
+        size += encode_txn(txn).len();
     }
 
     // 32 bit unsigned int representing the num of name-changes in the block
@@ -524,30 +746,39 @@ pub fn block_size(block: &Block) -> usize {
         size += encode_name_change(rename).len();
     }
 
-    return size;
+    size
+}
+
+pub fn max_block_size(median_block_size: usize) -> usize {
+    (2 * median_block_size).max(MIN_MAX_BLOCK_SIZE)
+}
+
+// The largest a block can be before calc_coinbase starts shrinking the reward
+pub fn penalty_free_block_size(median_block_size: usize) -> usize {
+    median_block_size + FREE_BLOCK_SPACE
 }
 
 pub fn calc_coinbase(block_size: usize, median_block_size: usize) -> u64 {
     let block_size = block_size as f64;
     let median_block_size = median_block_size as f64;
+    let free = FREE_BLOCK_SPACE as f64;
 
-    if block_size - median_block_size > 10_000f64 && block_size - 10_000f64 > median_block_size {
+    if block_size - median_block_size > free && block_size - free > median_block_size {
         // The first 10kb of any block is given for free. This is equal to about 70 transactions, or 1 transaction every 2 seconds.
         // This was chosen purposefully. If blocks remain exactly 10kb, that fixes blockchain growth at 2GB/yr. This is negligible.
-        let percent = 1f64 - ((block_size - 10_000f64 - median_block_size) / median_block_size);
-        println!("{percent}");
+        let percent = 1f64 - ((block_size - free - median_block_size) / median_block_size);
         ((DEFAULT_COINBASE as f64) / 1000_f64 * percent.powi(2)) as u64 * 1_000
     } else {
         DEFAULT_COINBASE
     }
 }
 
-pub fn txn_total_spend(txn: &Txn) -> u64 {
-    let mut sum = 0;
-    for output in txn.recievers.iter() {
-        sum += output.1;
-    }
-    sum + txn.fee
+// Errors if the amounts add up to more than a u64 holds, which no balance could cover anyway
+pub fn txn_total_spend(txn: &Txn) -> Result<u64, Error> {
+    txn.recievers
+        .iter()
+        .try_fold(txn.fee, |sum, output| sum.checked_add(output.1))
+        .ok_or_else(|| Error::TxnValidationError("Txn amounts overflow".into()))
 }
 
 pub fn name_change_hash(change: &RenameOp) -> [u8; 32] {
@@ -573,7 +804,7 @@ pub fn hash_header(header: &Header) -> [u8; 32] {
 }
 
 pub fn median_block_size(values: &[usize; 100]) -> usize {
-    let mut block_sizes = values.clone();
+    let mut block_sizes = *values;
     block_sizes.sort_unstable();
     block_sizes[50]
 }
@@ -588,13 +819,49 @@ pub fn address_to_key_unchecked(address: &Address, names: &Names) -> [u8; 32] {
 
 pub fn address_to_key(address: &Address, names: &Names) -> Result<[u8; 32], Error> {
     match address {
-        Address::Name(n) => names.get(n).map(|k| *k).ok_or(Error::MissingDataError),
+        Address::Name(n) => names.get(n).copied().ok_or(Error::MissingDataError),
         Address::Key(k) => Ok(*k),
     }
 }
 
+// Gives `name` to `owner`, or frees it if there's no owner, keeping the reverse index in step
+pub fn set_name_owner(
+    name_set: &mut Names,
+    owned_names: &mut OwnedNames,
+    name: &str,
+    owner: Option<[u8; 32]>,
+) {
+    let old_owner = match owner {
+        Some(pk) => name_set.insert(name.into(), pk),
+        None => name_set.remove(name),
+    };
+
+    if let Some(old_owner) = old_owner {
+        if let Some(names) = owned_names.get_mut(&old_owner) {
+            names.remove(name);
+            if names.is_empty() {
+                owned_names.remove(&old_owner);
+            }
+        }
+    }
+
+    if let Some(pk) = owner {
+        owned_names.entry(pk).or_default().insert(name.into());
+    }
+}
+
+// Builds the reverse index from scratch, for states that weren't built up block by block
+pub fn index_names(names: &Names) -> OwnedNames {
+    let mut owned_names = OwnedNames::new();
+    for (name, pk) in names.iter() {
+        owned_names.entry(*pk).or_default().insert(name.clone());
+    }
+    owned_names
+}
+
+// The inverse of push_to_front. Shifts everything towards the end, dropping the last item.
 pub fn push_to_back<T: Copy + Default>(arr: &mut [T], item: T) {
-    for i in 1..(arr.len() - 1) {
+    for i in (0..(arr.len() - 1)).rev() {
         arr[i + 1] = arr[i];
     }
 
@@ -614,12 +881,150 @@ pub fn push_to_front<T: Copy + Default>(arr: &mut [T], item: T) -> T {
     output
 }
 
+// Signatures cover the network's magic as well, so a txn or rename signed for one network is never valid on another.
+// The signature itself is zeroed, since it can't sign over itself.
+pub fn txn_signing_data(txn: &Txn, network: Network) -> Vec<u8> {
+    let mut txn = txn.clone();
+    txn.signature = [0; 64];
+
+    let mut data = network.magic().to_vec();
+    data.extend(encode_txn(&txn));
+    data
+}
+
+pub fn name_change_signing_data(op: &RenameOp, network: Network) -> [u8; 32] {
+    let mut op = op.clone();
+    op.sig = [0; 64];
+
+    let mut data = network.magic().to_vec();
+    data.extend(encode_name_change(&op));
+    hash(&data)
+}
+
 // Signs a transaction and sets appropriate fees
-pub fn finalize_txn(txn: &mut Txn, signer_keypair: &Keypair) {
+pub fn finalize_txn(txn: &mut Txn, signer_keypair: &Keypair, network: Network) {
     let secp = Secp256k1::new();
-    let txn_size = encode_txn(&txn).len();
+    let txn_size = encode_txn(txn).len();
     txn.fee = txn_size as u64 * TXN_FEES_PER_BYTE;
     txn.signature = *secp
-        .sign_schnorr(&encode_txn(txn), signer_keypair)
+        .sign_schnorr(&txn_signing_data(txn, network), signer_keypair)
+        .as_byte_array();
+}
+
+// Signs a rename and sets the minimum fee. If the name is already owned, `signer_keypair` must be the owner's.
+pub fn finalize_name_change(op: &mut RenameOp, signer_keypair: &Keypair, network: Network) {
+    let secp = Secp256k1::new();
+    op.fee = encode_name_change(op).len() as u64 * NAME_CHANGE_FEES_PER_BYTE;
+    op.sig = *secp
+        .sign_schnorr(&name_change_signing_data(op, network), signer_keypair)
         .as_byte_array();
 }
+
+//
+// --- DECODING FUNCTIONS ---
+//
+
+// Each decode function reads one item from the front of `data` and advances the slice past it,
+// so items can be decoded back to back out of a single buffer.
+
+pub fn take_bytes<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if data.len() < len {
+        decode_error!(format!(
+            "expected {len} more bytes but only {} remain",
+            data.len()
+        ));
+    }
+
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+    Ok(taken)
+}
+
+pub fn take_array<const N: usize>(data: &mut &[u8]) -> Result<[u8; N], Error> {
+    Ok(take_bytes(data, N)?.try_into().unwrap())
+}
+
+pub fn take_u8(data: &mut &[u8]) -> Result<u8, Error> {
+    Ok(take_array::<1>(data)?[0])
+}
+
+pub fn take_u32(data: &mut &[u8]) -> Result<u32, Error> {
+    Ok(u32::from_le_bytes(take_array(data)?))
+}
+
+pub fn take_u64(data: &mut &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_le_bytes(take_array(data)?))
+}
+
+// Names are length prefixed with a single byte, which is where the 255 byte name limit comes from
+pub fn take_name(data: &mut &[u8]) -> Result<String, Error> {
+    let len = take_u8(data)? as usize;
+    String::from_utf8(take_bytes(data, len)?.to_vec())
+        .map_err(|_| Error::DecodeError("a name was not valid utf-8".into()))
+}
+
+pub fn decode_header(data: &mut &[u8]) -> Result<Header, Error> {
+    Ok(Header {
+        prev_block_hash: take_array(data)?,
+        merkle_root: take_array(data)?,
+        time: take_u64(data)?,
+        nonce: take_u64(data)?,
+    })
+}
+
+pub fn decode_address(data: &mut &[u8]) -> Result<Address, Error> {
+    match take_u8(data)? {
+        0 => Ok(Address::Key(take_array(data)?)),
+        1 => Ok(Address::Name(take_name(data)?)),
+        tag => decode_error!(format!("{tag} is not a valid address tag")),
+    }
+}
+
+pub fn decode_txn(data: &mut &[u8]) -> Result<Txn, Error> {
+    let sender = decode_address(data)?;
+    let reciever_count = take_u8(data)?;
+    let mut recievers = Vec::with_capacity(reciever_count as usize);
+
+    for _ in 0..reciever_count {
+        recievers.push((decode_address(data)?, take_u64(data)?));
+    }
+
+    Ok(Txn {
+        sender,
+        recievers,
+        signature: take_array(data)?,
+        fee: take_u64(data)?,
+    })
+}
+
+pub fn decode_name_change(data: &mut &[u8]) -> Result<RenameOp, Error> {
+    Ok(RenameOp {
+        pk: take_array(data)?,
+        sig: take_array(data)?,
+        new_name: take_name(data)?,
+        fee: take_u64(data)?,
+    })
+}
+
+pub fn decode_block(data: &mut &[u8]) -> Result<Block, Error> {
+    let header = decode_header(data)?;
+
+    // Counts come off the wire, so they aren't trusted for preallocation
+    let txn_count = take_u32(data)?;
+    let mut txns = vec![];
+    for _ in 0..txn_count {
+        txns.push(decode_txn(data)?);
+    }
+
+    let rename_count = take_u32(data)?;
+    let mut name_changes = vec![];
+    for _ in 0..rename_count {
+        name_changes.push(decode_name_change(data)?);
+    }
+
+    Ok(Block {
+        header,
+        txns,
+        name_changes,
+    })
+}
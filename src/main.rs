@@ -1,14 +1,6 @@
 // Server Imports
 
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    routing::{get, patch},
-    Json, Router,
-};
-
-use serde::{Deserialize, Serialize};
-use serde_json::json;
+use axum::Router;
 
 // Runtime imports
 
@@ -16,14 +8,59 @@ use tokio::net::TcpListener;
 
 // Functional Imports
 
+use gold_2::api;
+use gold_2::chain::Chain;
+use gold_2::context::NodeContext;
+use gold_2::net::node::Node;
+use gold_2::pool::{
+    self, Pool, PoolConfig, DEFAULT_JOB_INTERVAL, DEFAULT_POOL_ADDR, DEFAULT_SHARE_DIFFICULTY,
+};
 use gold_2::*;
-use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Where the pending pools are kept between runs, unless GOLD_MEMPOOL_PATH or GOLD_RENAMES_PATH say otherwise
+const DEFAULT_MEMPOOL_PATH: &str = "mempool.dat";
+const DEFAULT_RENAMES_PATH: &str = "renames.dat";
 
 #[tokio::main]
 async fn main() {
-    // Set up blockchain state
+    // Set up the node. Every task below shares it through the context.
+
+    let mut node = Node::new(Chain::new(Network::Mainnet.genesis_state()));
+
+    // Pick up whatever was pending when we last shut down. There's nothing to load on a first run, and a damaged
+    // file only costs us what was in it, so neither stops the node starting.
 
-    let accounts: Accounts = HashMap::new();
+    let mempool_path =
+        std::env::var("GOLD_MEMPOOL_PATH").unwrap_or_else(|_| DEFAULT_MEMPOOL_PATH.into());
+    let renames_path =
+        std::env::var("GOLD_RENAMES_PATH").unwrap_or_else(|_| DEFAULT_RENAMES_PATH.into());
+    let now = api::unix_millis();
+    let _ = node.mempool.load(&mempool_path, &node.chain.state, now);
+    let _ = node.renames.load(&renames_path, &node.chain.state, now);
+
+    let node = NodeContext::new(node);
+
+    // Serve our own mining machines if there's a key to pay
+
+    if let Ok(payout) = std::env::var("GOLD_POOL_PAYOUT") {
+        let payout = api::parse_key(&payout).expect("GOLD_POOL_PAYOUT is not a valid key");
+        let pool = Pool::new(PoolConfig {
+            payout,
+            share_difficulty: DEFAULT_SHARE_DIFFICULTY,
+            job_interval: DEFAULT_JOB_INTERVAL,
+        });
+
+        let addr = std::env::var("GOLD_POOL_ADDR").unwrap_or_else(|_| DEFAULT_POOL_ADDR.into());
+        let listener = TcpListener::bind(addr)
+            .await
+            .expect("Could not create pool TCP Listener");
+        tokio::spawn(pool::serve(
+            listener,
+            node.clone(),
+            Arc::new(Mutex::new(pool)),
+        ));
+    }
 
     // Set up tcp connection
 
@@ -33,11 +70,23 @@ async fn main() {
 
     // Compose routes
 
-    let app = Router::new().route("/", get(|| async { "Hello, World!" }));
+    let app = Router::new().merge(api::router(node.clone()));
 
-    // Serve the application
+    // Serve the application until ctrl-c, then save the pending pools for next time
 
     axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
         .await
-        .expect("Error serving application")
+        .expect("Error serving application");
+
+    node.read(|node| {
+        node.mempool
+            .save(&mempool_path)
+            .expect("Could not save the mempool");
+        node.renames
+            .save(&renames_path)
+            .expect("Could not save the rename pool");
+    });
 }
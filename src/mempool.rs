@@ -0,0 +1,885 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::*;
+
+// Holds txns which are valid against the current tip but not yet in a block.
+// Like check_txns, it tracks how much each sender has pending so that a run of txns which together spend more
+// than the sender's balance can't get in. Balances received from pending txns can't be spent until they confirm.
+//
+// A stuck txn can be fee-bumped by sending the same payment again with a higher fee. Only one txn per payment is
+// kept, and a replacement has to pay a strictly higher fee rate, plus at least the minimum relay fee for its own
+// bytes on top of the fee it replaces. Without that, someone could make every node relay the same payment over and
+// over for next to nothing.
+//
+// The pool is capped in bytes. When it's full the worst paying txns are evicted, and the minimum fee rate for getting
+// in rises above theirs, then halves every MIN_FEE_HALF_LIFE until it's back at TXN_FEES_PER_BYTE.
+// Txns which sit in the pool for longer than its max age are dropped.
+//
+// Times in here are milliseconds.
+
+// What a replacement pays per byte on top of the fee of the txn it replaces
+pub const MIN_REPLACEMENT_FEE_PER_BYTE: u64 = TXN_FEES_PER_BYTE;
+
+pub const DEFAULT_MAX_MEMPOOL_BYTES: usize = 32_000_000;
+
+pub const DEFAULT_MEMPOOL_EXPIRY: u64 = 14 * 24 * 60 * 60 * 1_000;
+
+pub const MIN_FEE_HALF_LIFE: u64 = 12 * 60 * 60 * 1_000;
+
+// Who handed us a txn, so they can be told if it's evicted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxnSource {
+    Local,
+    Peer(usize),
+    // Put back after the block holding it was disconnected
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    // Pushed out by better paying txns
+    Full,
+    Expired,
+    // A fee-bump for the same payment took its place
+    Replaced,
+    // The new tip made it invalid
+    Invalidated,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Eviction {
+    pub hash: [u8; 32],
+    pub sender: [u8; 32],
+    pub source: TxnSource,
+    pub reason: EvictionReason,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MempoolEntry {
+    pub txn: Txn,
+    pub hash: [u8; 32],
+    pub sender: [u8; 32],
+    // Size of `encode_txn`
+    pub size: usize,
+    pub spend: u64,
+    pub payment: [u8; 32],
+    pub source: TxnSource,
+    pub added: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    entries: HashMap<[u8; 32], MempoolEntry>,
+    // (fee rate, hash), so iterating backwards goes from best to worst paying
+    by_fee_rate: BTreeSet<(u64, [u8; 32])>,
+    // (time added, hash), oldest first
+    by_age: BTreeSet<(u64, [u8; 32])>,
+    pending_spend: HashMap<[u8; 32], u64>,
+    // Payment id to the hash of the txn making it
+    by_payment: HashMap<[u8; 32], [u8; 32]>,
+    bytes: usize,
+    max_bytes: usize,
+    max_age: u64,
+    // The minimum fee rate as of the last eviction, before any decay
+    rolling_min_fee: u64,
+    last_min_fee_bump: u64,
+    evictions: Vec<Eviction>,
+}
+
+// Fee per byte, rounded down
+pub fn fee_rate(fee: u64, size: usize) -> u64 {
+    fee / size.max(1) as u64
+}
+
+// Identifies a payment regardless of its fee. Two txns from the same sender paying the same recievers the same
+// amounts are the same payment.
+pub fn payment_id(sender: &[u8; 32], txn: &Txn) -> [u8; 32] {
+    let mut data = sender.to_vec();
+
+    for reciever in txn.recievers.iter() {
+        encode_address(&reciever.0, &mut data);
+        data.extend(reciever.1.to_le_bytes());
+    }
+
+    hash(&data)
+}
+
+impl MempoolEntry {
+    pub fn fee_rate(&self) -> u64 {
+        fee_rate(self.txn.fee, self.size)
+    }
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Mempool::with_limits(DEFAULT_MAX_MEMPOOL_BYTES, DEFAULT_MEMPOOL_EXPIRY)
+    }
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Mempool::default()
+    }
+
+    pub fn with_limits(max_bytes: usize, max_age: u64) -> Self {
+        Mempool {
+            entries: HashMap::new(),
+            by_fee_rate: BTreeSet::new(),
+            by_age: BTreeSet::new(),
+            pending_spend: HashMap::new(),
+            by_payment: HashMap::new(),
+            bytes: 0,
+            max_bytes,
+            max_age,
+            rolling_min_fee: 0,
+            last_min_fee_bump: 0,
+            evictions: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &[u8; 32]) -> Option<&MempoolEntry> {
+        self.entries.get(hash)
+    }
+
+    // Total size of every pending txn
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    pub fn pending_spend(&self, sender: &[u8; 32]) -> u64 {
+        self.pending_spend.get(sender).copied().unwrap_or(0)
+    }
+
+    // What pending txns would pay `key`, with names resolved against `names`. Saturates rather than overflowing, since
+    // payments to the same key can add up to more than any one txn could send.
+    pub fn pending_receipts(&self, key: &[u8; 32], names: &Names) -> u64 {
+        self.txns()
+            .flat_map(|txn| txn.recievers.iter())
+            .filter(|(address, _)| address_to_key(address, names).is_ok_and(|k| k == *key))
+            .fold(0, |total: u64, (_, amount)| total.saturating_add(*amount))
+    }
+
+    // Highest fee rate first. Ties go by hash so the order is always the same.
+    pub fn by_fee_rate(&self) -> impl Iterator<Item = &MempoolEntry> {
+        self.by_fee_rate
+            .iter()
+            .rev()
+            .map(|(_, hash)| &self.entries[hash])
+    }
+
+    pub fn txns(&self) -> impl Iterator<Item = &Txn> {
+        self.entries.values().map(|e| &e.txn)
+    }
+
+    // The lowest fee rate a new txn can pay and still get in
+    pub fn min_fee_rate(&self, now: u64) -> u64 {
+        let halvings = now.saturating_sub(self.last_min_fee_bump) / MIN_FEE_HALF_LIFE;
+        let rolling = self
+            .rolling_min_fee
+            .checked_shr(halvings.min(64) as u32)
+            .unwrap_or(0);
+
+        rolling.max(TXN_FEES_PER_BYTE)
+    }
+
+    // Everything evicted since the last call, for passing on to whoever submitted it
+    pub fn take_evictions(&mut self) -> Vec<Eviction> {
+        std::mem::take(&mut self.evictions)
+    }
+
+    pub fn add_txn(
+        &mut self,
+        txn: Txn,
+        source: TxnSource,
+        blockchain_state: &BlockchainState,
+        now: u64,
+    ) -> Result<[u8; 32], Error> {
+        self.add_txn_at(txn, source, now, blockchain_state, now)
+    }
+
+    // Like add_txn, but for a txn which first arrived at `added`
+    fn add_txn_at(
+        &mut self,
+        txn: Txn,
+        source: TxnSource,
+        added: u64,
+        blockchain_state: &BlockchainState,
+        now: u64,
+    ) -> Result<[u8; 32], Error> {
+        self.expire(now);
+
+        if now.saturating_sub(added) > self.max_age {
+            mempool_error!("the txn is past the mempool's max age");
+        }
+
+        let hash = txn_hash(&txn);
+
+        if self.entries.contains_key(&hash) {
+            mempool_error!("the txn is already in the mempool");
+        }
+
+        let size = encode_txn(&txn).len();
+        if fee_rate(txn.fee, size) < self.min_fee_rate(now) {
+            mempool_error!("the txn's fee rate is below the mempool's minimum");
+        }
+
+        self.admit(txn, hash, size, source, added, blockchain_state)?;
+
+        // If the new txn is the worst paying one in a full pool it goes straight back out. Its submitter hears
+        // about that through the error, so it isn't reported as an eviction.
+        let mut evicted_self = false;
+        for entry in self.trim(now) {
+            if entry.hash == hash {
+                evicted_self = true;
+            } else {
+                self.report(&entry, EvictionReason::Full);
+            }
+        }
+
+        if evicted_self {
+            mempool_error!("the mempool is full");
+        }
+
+        Ok(hash)
+    }
+
+    pub fn remove_txn(&mut self, hash: &[u8; 32]) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(hash)?;
+
+        self.by_fee_rate.remove(&(entry.fee_rate(), entry.hash));
+        self.by_age.remove(&(entry.added, entry.hash));
+        self.by_payment.remove(&entry.payment);
+        self.bytes -= entry.size;
+
+        let pending = self.pending_spend.get_mut(&entry.sender).unwrap();
+        *pending -= entry.spend;
+        if *pending == 0 {
+            self.pending_spend.remove(&entry.sender);
+        }
+
+        Some(entry)
+    }
+
+    // Written oldest first as a u32 count, then each txn's time added followed by the txn
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut data = (self.entries.len() as u32).to_le_bytes().to_vec();
+
+        for (added, hash) in self.by_age.iter() {
+            data.extend(added.to_le_bytes());
+            data.extend(encode_txn(&self.entries[hash].txn));
+        }
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    // Adds back the txns from a saved mempool. Each one is checked against the current tip like any new txn, so
+    // anything which became invalid or expired while we were down is left out. Returns how many were added.
+    pub fn load(
+        &mut self,
+        path: impl AsRef<Path>,
+        blockchain_state: &BlockchainState,
+        now: u64,
+    ) -> Result<usize, Error> {
+        let data = fs::read(path)?;
+        let mut data = &data[..];
+
+        let count = take_u32(&mut data)?;
+        let mut saved = vec![];
+        for _ in 0..count {
+            saved.push((take_u64(&mut data)?, decode_txn(&mut data)?));
+        }
+
+        if !data.is_empty() {
+            decode_error!("the saved mempool has trailing bytes");
+        }
+
+        let mut added = 0;
+        for (time, txn) in saved {
+            if self
+                .add_txn_at(txn, TxnSource::Local, time, blockchain_state, now)
+                .is_ok()
+            {
+                added += 1;
+            }
+        }
+
+        Ok(added)
+    }
+
+    // Drops any txns the block confirmed. Follow up with `revalidate` once the block is connected, since the new
+    // balances may make some of what's left invalid.
+    pub fn remove_block(&mut self, block: &Block) {
+        for txn in block.txns.iter() {
+            self.remove_txn(&txn_hash(txn));
+        }
+    }
+
+    // Drops txns which have been waiting longer than the max age
+    pub fn expire(&mut self, now: u64) {
+        while let Some(&(added, hash)) = self.by_age.first() {
+            if now.saturating_sub(added) <= self.max_age {
+                break;
+            }
+
+            let entry = self.remove_txn(&hash).unwrap();
+            self.report(&entry, EvictionReason::Expired);
+        }
+    }
+
+    // Brings the mempool in line with a new main chain. Txns from the disconnected blocks go back in first if they're
+    // still valid on the new tip, unless the new branch confirmed them too, so they win over pending txns that
+    // conflict with them. Then the rest of the pool is checked against the new tip. Returns the hashes of pending
+    // txns which were dropped.
+    pub fn reorganize(
+        &mut self,
+        disconnected: &[Block],
+        connected: &[Block],
+        blockchain_state: &BlockchainState,
+        now: u64,
+    ) -> Vec<[u8; 32]> {
+        let confirmed: HashSet<[u8; 32]> = connected
+            .iter()
+            .flat_map(|b| b.txns.iter().map(txn_hash))
+            .collect();
+
+        for block in connected.iter() {
+            self.remove_block(block);
+        }
+        let pending = self.take_entries();
+
+        // Disconnected blocks come tip first, so go backwards to put txns back in the order they were confirmed
+        for block in disconnected.iter().rev() {
+            // Skipping the coinbase
+            for txn in block.txns.iter().skip(1) {
+                if !confirmed.contains(&txn_hash(txn)) {
+                    let _ = self.add_txn(txn.clone(), TxnSource::Block, blockchain_state, now);
+                }
+            }
+        }
+
+        let removed = self.readmit(pending, blockchain_state);
+
+        // Readmitting doesn't check the byte cap
+        for entry in self.trim(now) {
+            self.report(&entry, EvictionReason::Full);
+        }
+
+        removed
+    }
+
+    // Checks every entry against `blockchain_state` again, best paying first, and removes whatever no longer fits.
+    // Returns the hashes of the removed txns.
+    pub fn revalidate(&mut self, blockchain_state: &BlockchainState) -> Vec<[u8; 32]> {
+        let entries = self.take_entries();
+        self.readmit(entries, blockchain_state)
+    }
+
+    // Empties the pool, handing back its entries best paying first
+    fn take_entries(&mut self) -> Vec<MempoolEntry> {
+        let entries: Vec<MempoolEntry> = self.by_fee_rate().cloned().collect();
+
+        self.entries.clear();
+        self.by_fee_rate.clear();
+        self.by_age.clear();
+        self.pending_spend.clear();
+        self.by_payment.clear();
+        self.bytes = 0;
+
+        entries
+    }
+
+    // Puts taken entries back in on top of whatever the pool now holds, returning the hashes of those that no longer fit
+    fn readmit(
+        &mut self,
+        entries: Vec<MempoolEntry>,
+        blockchain_state: &BlockchainState,
+    ) -> Vec<[u8; 32]> {
+        let mut removed = vec![];
+
+        for entry in entries {
+            let result = self.admit(
+                entry.txn.clone(),
+                entry.hash,
+                entry.size,
+                entry.source,
+                entry.added,
+                blockchain_state,
+            );
+
+            if result.is_err() {
+                self.report(&entry, EvictionReason::Invalidated);
+                removed.push(entry.hash);
+            }
+        }
+
+        removed
+    }
+
+    // Validates the txn against the tip and the rest of the pool, then inserts it, replacing the earlier txn for the
+    // same payment if there is one
+    fn admit(
+        &mut self,
+        txn: Txn,
+        hash: [u8; 32],
+        size: usize,
+        source: TxnSource,
+        added: u64,
+        blockchain_state: &BlockchainState,
+    ) -> Result<(), Error> {
+        check_txn(&txn, blockchain_state)?;
+
+        let sender = address_to_key(&txn.sender, &blockchain_state.name_set)?;
+        let spend = txn_total_spend(&txn)?;
+        let balance = blockchain_state.account_set[&sender];
+        let payment = payment_id(&sender, &txn);
+
+        let replaced = self.by_payment.get(&payment).map(|h| &self.entries[h]);
+        if let Some(replaced) = replaced {
+            check_replacement(replaced, &txn, size)?;
+        }
+
+        // The replaced txn's spend is freed up for the replacement
+        let freed = replaced.map(|e| e.spend).unwrap_or(0);
+        let total = (self.pending_spend(&sender) - freed).checked_add(spend);
+        if total.is_none_or(|total| total > balance) {
+            txn_validation_error!("Sender tried to spend more than their balance");
+        }
+
+        if let Some(replaced) = replaced.map(|e| e.hash) {
+            let replaced = self.remove_txn(&replaced).unwrap();
+            self.report(&replaced, EvictionReason::Replaced);
+        }
+
+        self.insert(MempoolEntry {
+            txn,
+            hash,
+            sender,
+            size,
+            spend,
+            payment,
+            source,
+            added,
+        });
+
+        Ok(())
+    }
+
+    fn insert(&mut self, entry: MempoolEntry) {
+        *self.pending_spend.entry(entry.sender).or_insert(0) += entry.spend;
+        self.by_fee_rate.insert((entry.fee_rate(), entry.hash));
+        self.by_age.insert((entry.added, entry.hash));
+        self.by_payment.insert(entry.payment, entry.hash);
+        self.bytes += entry.size;
+        self.entries.insert(entry.hash, entry);
+    }
+
+    // Evicts the worst paying txns until the pool is back under its byte cap, raising the minimum fee rate above
+    // each evicted one
+    fn trim(&mut self, now: u64) -> Vec<MempoolEntry> {
+        let mut evicted = vec![];
+
+        while self.bytes > self.max_bytes {
+            let &(rate, hash) = self.by_fee_rate.first().unwrap();
+
+            self.rolling_min_fee = self.min_fee_rate(now).max(rate + TXN_FEES_PER_BYTE);
+            self.last_min_fee_bump = now;
+
+            evicted.push(self.remove_txn(&hash).unwrap());
+        }
+
+        evicted
+    }
+
+    fn report(&mut self, entry: &MempoolEntry, reason: EvictionReason) {
+        self.evictions.push(Eviction {
+            hash: entry.hash,
+            sender: entry.sender,
+            source: entry.source,
+            reason,
+        });
+    }
+}
+
+fn check_replacement(replaced: &MempoolEntry, txn: &Txn, size: usize) -> Result<(), Error> {
+    if fee_rate(txn.fee, size) <= replaced.fee_rate() {
+        mempool_error!("a replacement must pay a higher fee rate than the txn it replaces");
+    }
+
+    if txn.fee < replaced.txn.fee + MIN_REPLACEMENT_FEE_PER_BYTE * size as u64 {
+        mempool_error!(
+            "a replacement must pay the minimum relay fee on top of the fee it replaces"
+        );
+    }
+
+    Ok(())
+}
+
+//
+// --- RENAME POOL ---
+//
+
+// Pending renames. Only one claim per name is kept, and a new claim on a pending name has to pay a higher fee than
+// the one it replaces. Whoever is given the name pays the fee, so their balance has to cover the fees of every
+// rename pending to them.
+//
+// Like the mempool, the pool is capped in bytes. The worst paying renames are evicted when it's full, and the minimum
+// fee rate rises above theirs before decaying back to NAME_CHANGE_FEES_PER_BYTE.
+
+pub const DEFAULT_MAX_RENAME_POOL_BYTES: usize = 4_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameEntry {
+    pub op: RenameOp,
+    pub hash: [u8; 32],
+    // Size of `encode_name_change`
+    pub size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenamePool {
+    by_name: HashMap<String, RenameEntry>,
+    by_hash: HashMap<[u8; 32], String>,
+    // (fee rate, hash), so iterating backwards goes from best to worst paying
+    by_fee_rate: BTreeSet<(u64, [u8; 32])>,
+    pending_fees: HashMap<[u8; 32], u64>,
+    bytes: usize,
+    max_bytes: usize,
+    // The minimum fee rate as of the last eviction, before any decay
+    rolling_min_fee: u64,
+    last_min_fee_bump: u64,
+}
+
+impl RenameEntry {
+    pub fn fee_rate(&self) -> u64 {
+        fee_rate(self.op.fee, self.size)
+    }
+}
+
+impl Default for RenamePool {
+    fn default() -> Self {
+        RenamePool::with_max_bytes(DEFAULT_MAX_RENAME_POOL_BYTES)
+    }
+}
+
+impl RenamePool {
+    pub fn new() -> Self {
+        RenamePool::default()
+    }
+
+    pub fn with_max_bytes(max_bytes: usize) -> Self {
+        RenamePool {
+            by_name: HashMap::new(),
+            by_hash: HashMap::new(),
+            by_fee_rate: BTreeSet::new(),
+            pending_fees: HashMap::new(),
+            bytes: 0,
+            max_bytes,
+            rolling_min_fee: 0,
+            last_min_fee_bump: 0,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // Like Mempool::min_fee_rate, for renames
+    pub fn min_fee_rate(&self, now: u64) -> u64 {
+        let halvings = now.saturating_sub(self.last_min_fee_bump) / MIN_FEE_HALF_LIFE;
+        let rolling = self
+            .rolling_min_fee
+            .checked_shr(halvings.min(64) as u32)
+            .unwrap_or(0);
+
+        rolling.max(NAME_CHANGE_FEES_PER_BYTE)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.by_hash.contains_key(hash)
+    }
+
+    // The pending claim on a name
+    pub fn get(&self, name: &str) -> Option<&RenameEntry> {
+        self.by_name.get(name)
+    }
+
+    pub fn pending_fees(&self, pk: &[u8; 32]) -> u64 {
+        self.pending_fees.get(pk).copied().unwrap_or(0)
+    }
+
+    pub fn ops(&self) -> impl Iterator<Item = &RenameOp> {
+        self.by_name.values().map(|e| &e.op)
+    }
+
+    // Highest fee rate first. Ties go by hash so the order is always the same.
+    pub fn by_fee_rate(&self) -> Vec<&RenameEntry> {
+        self.by_fee_rate
+            .iter()
+            .rev()
+            .map(|(_, hash)| &self.by_name[&self.by_hash[hash]])
+            .collect()
+    }
+
+    pub fn add_op(
+        &mut self,
+        op: RenameOp,
+        blockchain_state: &BlockchainState,
+        now: u64,
+    ) -> Result<[u8; 32], Error> {
+        let hash = name_change_hash(&op);
+
+        if self.by_hash.contains_key(&hash) {
+            mempool_error!("the rename is already pending");
+        }
+
+        let size = encode_name_change(&op).len();
+        if fee_rate(op.fee, size) < self.min_fee_rate(now) {
+            mempool_error!("the rename's fee rate is below the pool's minimum");
+        }
+
+        self.admit(op, hash, size, blockchain_state)?;
+
+        // As in Mempool::add_txn, a new rename that would be the first to go is turned away
+        let evicted = self.trim(now);
+        if evicted.iter().any(|e| e.hash == hash) {
+            mempool_error!("the rename pool is full");
+        }
+
+        Ok(hash)
+    }
+
+    // Validates the rename against the tip and the rest of the pool, then inserts it, replacing the pending claim on
+    // the same name if there is one
+    fn admit(
+        &mut self,
+        op: RenameOp,
+        hash: [u8; 32],
+        size: usize,
+        blockchain_state: &BlockchainState,
+    ) -> Result<(), Error> {
+        check_name_change(&op, blockchain_state)?;
+
+        // Otherwise a confirmed claim could be replayed forever, since the owner's signature stays valid
+        if blockchain_state.name_set.get(&op.new_name) == Some(&op.pk) {
+            mempool_error!("the name already belongs to that key");
+        }
+
+        let replaced = self.by_name.get(&op.new_name);
+        if let Some(replaced) = replaced {
+            if op.fee <= replaced.op.fee {
+                mempool_error!("a rename paying as much or more is already pending for the name");
+            }
+        }
+
+        // If the replaced claim was to the same key, its fee is freed up for this one
+        let freed = replaced
+            .filter(|e| e.op.pk == op.pk)
+            .map(|e| e.op.fee)
+            .unwrap_or(0);
+        let balance = blockchain_state
+            .account_set
+            .get(&op.pk)
+            .copied()
+            .unwrap_or(0);
+
+        let total = (self.pending_fees(&op.pk) - freed).checked_add(op.fee);
+        if total.is_none_or(|total| total > balance) {
+            txn_validation_error!("The new owner can't afford the rename fees");
+        }
+
+        if let Some(replaced) = replaced.map(|e| e.hash) {
+            self.remove_op(&replaced);
+        }
+
+        let entry = RenameEntry { op, hash, size };
+        *self.pending_fees.entry(entry.op.pk).or_insert(0) += entry.op.fee;
+        self.by_fee_rate.insert((entry.fee_rate(), hash));
+        self.by_hash.insert(hash, entry.op.new_name.clone());
+        self.bytes += size;
+        self.by_name.insert(entry.op.new_name.clone(), entry);
+
+        Ok(())
+    }
+
+    // Evicts the worst paying renames until the pool is back under its byte cap, raising the minimum fee rate above
+    // each evicted one
+    fn trim(&mut self, now: u64) -> Vec<RenameEntry> {
+        let mut evicted = vec![];
+
+        while self.bytes > self.max_bytes {
+            let &(rate, hash) = self.by_fee_rate.first().unwrap();
+
+            self.rolling_min_fee = self.min_fee_rate(now).max(rate + NAME_CHANGE_FEES_PER_BYTE);
+            self.last_min_fee_bump = now;
+
+            evicted.push(self.remove_op(&hash).unwrap());
+        }
+
+        evicted
+    }
+
+    pub fn remove_op(&mut self, hash: &[u8; 32]) -> Option<RenameEntry> {
+        let name = self.by_hash.remove(hash)?;
+        let entry = self.by_name.remove(&name).unwrap();
+
+        self.by_fee_rate.remove(&(entry.fee_rate(), entry.hash));
+        self.bytes -= entry.size;
+
+        let pending = self.pending_fees.get_mut(&entry.op.pk).unwrap();
+        *pending -= entry.op.fee;
+        if *pending == 0 {
+            self.pending_fees.remove(&entry.op.pk);
+        }
+
+        Some(entry)
+    }
+
+    // Drops any renames the block confirmed. Follow up with `revalidate`, since renames of the same names by
+    // anyone else are now signed by the wrong owner.
+    pub fn remove_block(&mut self, block: &Block) {
+        for op in block.name_changes.iter() {
+            self.remove_op(&name_change_hash(op));
+        }
+    }
+
+    // Checks every pending rename against `blockchain_state` again, and removes any that no longer pass.
+    // Returns the hashes of the removed renames.
+    pub fn revalidate(&mut self, blockchain_state: &BlockchainState) -> Vec<[u8; 32]> {
+        let entries = self.take_entries();
+        self.readmit(entries, blockchain_state)
+    }
+
+    // Empties the pool, handing back its entries highest fee first
+    fn take_entries(&mut self) -> Vec<RenameEntry> {
+        let mut entries: Vec<RenameEntry> = self.by_name.drain().map(|(_, e)| e).collect();
+        entries.sort_by_key(|e| (std::cmp::Reverse(e.op.fee), e.hash));
+
+        self.by_hash.clear();
+        self.by_fee_rate.clear();
+        self.pending_fees.clear();
+        self.bytes = 0;
+
+        entries
+    }
+
+    // Puts taken entries back in on top of whatever the pool now holds, returning the hashes of those that no longer fit
+    fn readmit(
+        &mut self,
+        entries: Vec<RenameEntry>,
+        blockchain_state: &BlockchainState,
+    ) -> Vec<[u8; 32]> {
+        let mut removed = vec![];
+
+        for entry in entries {
+            let result = self.admit(entry.op, entry.hash, entry.size, blockchain_state);
+
+            if result.is_err() {
+                removed.push(entry.hash);
+            }
+        }
+
+        removed
+    }
+
+    // Written as a u32 count followed by each rename
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut entries: Vec<&RenameEntry> = self.by_name.values().collect();
+        entries.sort_by(|a, b| a.op.new_name.cmp(&b.op.new_name));
+
+        let mut data = (entries.len() as u32).to_le_bytes().to_vec();
+        for entry in entries {
+            data.extend(encode_name_change(&entry.op));
+        }
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    // Like Mempool::load, renames which are no longer valid on the current tip are left out
+    pub fn load(
+        &mut self,
+        path: impl AsRef<Path>,
+        blockchain_state: &BlockchainState,
+        now: u64,
+    ) -> Result<usize, Error> {
+        let data = fs::read(path)?;
+        let mut data = &data[..];
+
+        let count = take_u32(&mut data)?;
+        let mut saved = vec![];
+        for _ in 0..count {
+            saved.push(decode_name_change(&mut data)?);
+        }
+
+        if !data.is_empty() {
+            decode_error!("the saved rename pool has trailing bytes");
+        }
+
+        Ok(saved
+            .into_iter()
+            .filter(|op| self.add_op(op.clone(), blockchain_state, now).is_ok())
+            .count())
+    }
+
+    // Like Mempool::reorganize. Renames from the disconnected blocks go back in first if they're still valid on the
+    // new tip and the new branch didn't confirm them, then the rest of the pool is checked against the new tip.
+    pub fn reorganize(
+        &mut self,
+        disconnected: &[Block],
+        connected: &[Block],
+        blockchain_state: &BlockchainState,
+        now: u64,
+    ) -> Vec<[u8; 32]> {
+        let confirmed: HashSet<[u8; 32]> = connected
+            .iter()
+            .flat_map(|b| b.name_changes.iter().map(name_change_hash))
+            .collect();
+
+        for block in connected.iter() {
+            self.remove_block(block);
+        }
+        let pending = self.take_entries();
+
+        for block in disconnected.iter().rev() {
+            for op in block.name_changes.iter() {
+                if !confirmed.contains(&name_change_hash(op)) {
+                    let _ = self.add_op(op.clone(), blockchain_state, now);
+                }
+            }
+        }
+
+        let removed = self.readmit(pending, blockchain_state);
+
+        // Readmitting doesn't check the byte cap
+        self.trim(now);
+
+        removed
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::*;
+
+// Grinds block templates on the CPU.
+//
+// Each worker thread gets its own slice of the nonce range. Once a worker has tried every nonce in its slice it bumps
+// the extra nonce, which changes the merkle root and so gives it a fresh set of headers to try. Since the slices never
+// overlap, no two workers ever hash the same header.
+//
+// The extra nonce lives in the first 8 bytes of the coinbase signature. Coinbase signatures are never checked, and
+// the field is a fixed size, so rolling it leaves the block's size and coinbase reward alone. The header time is
+// brought up to the wall clock on each roll too, so a long search doesn't end in a stale timestamp.
+
+// How many hashes a worker does between checking whether it should stop
+pub const CHECK_INTERVAL: u64 = 1_024;
+
+#[derive(Debug, Clone)]
+pub struct MinerConfig {
+    pub threads: usize,
+    // How many nonces are tried before rolling the extra nonce. Split evenly between the threads.
+    pub nonces_per_extra_nonce: u64,
+}
+
+impl Default for MinerConfig {
+    fn default() -> Self {
+        MinerConfig {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            nonces_per_extra_nonce: u64::MAX,
+        }
+    }
+}
+
+// Cheap to clone, and every clone shares the same state, so one can mine while another reports new tips
+#[derive(Debug, Clone)]
+pub struct Miner {
+    config: MinerConfig,
+    // Bumped on every new tip. Work started under an older generation is abandoned.
+    generation: Arc<AtomicU64>,
+    hashes: Arc<AtomicU64>,
+}
+
+pub fn extra_nonce(block: &Block) -> u64 {
+    u64::from_le_bytes(block.txns[0].signature[0..8].try_into().unwrap())
+}
+
+// Unix seconds, like build_template takes
+fn unix_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn set_extra_nonce(block: &mut Block, extra_nonce: u64) {
+    block.txns[0].signature[0..8].copy_from_slice(&extra_nonce.to_le_bytes());
+    block.header.merkle_root = merkle_root(&block.txns, &block.name_changes);
+}
+
+impl Miner {
+    pub fn new(config: MinerConfig) -> Self {
+        Miner {
+            config,
+            generation: Arc::new(AtomicU64::new(0)),
+            hashes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    // Every header hashed so far, by every clone
+    pub fn hashes(&self) -> u64 {
+        self.hashes.load(Ordering::Relaxed)
+    }
+
+    // Stops whatever is being mined, since it no longer builds on the tip
+    pub fn tip_changed(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // Blocks until a header meeting `difficulty` is found, or `tip_changed` is called. `template` should have its
+    // merkle root set already, as build_template does.
+    pub fn mine(&self, template: &Block, difficulty: &[u8; 32]) -> Option<Block> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let threads = self.config.threads.max(1) as u64;
+        let slice = (self.config.nonces_per_extra_nonce / threads).max(1);
+        let found = AtomicBool::new(false);
+
+        let stopped = || {
+            found.load(Ordering::Relaxed) || self.generation.load(Ordering::Relaxed) != generation
+        };
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    let stopped = &stopped;
+                    let found = &found;
+
+                    scope.spawn(move || {
+                        let mut block = template.clone();
+                        let start = worker * slice;
+
+                        for extra in extra_nonce(template).. {
+                            set_extra_nonce(&mut block, extra);
+                            // Never earlier than the template, which build_template kept at or after the tip
+                            block.header.time = template.header.time.max(unix_seconds());
+
+                            for nonce in start..start.saturating_add(slice) {
+                                block.header.nonce = nonce;
+
+                                if meets_difficulty(&hash_header(&block.header), difficulty) {
+                                    found.store(true, Ordering::Relaxed);
+                                    self.hashes.fetch_add(
+                                        (nonce - start + 1) % CHECK_INTERVAL,
+                                        Ordering::Relaxed,
+                                    );
+                                    return Some(block);
+                                }
+
+                                // Checking in every hash would have the threads fighting over the counters
+                                if (nonce - start + 1).is_multiple_of(CHECK_INTERVAL) {
+                                    self.hashes.fetch_add(CHECK_INTERVAL, Ordering::Relaxed);
+
+                                    if stopped() {
+                                        return None;
+                                    }
+                                }
+                            }
+
+                            self.hashes
+                                .fetch_add(slice % CHECK_INTERVAL, Ordering::Relaxed);
+
+                            if stopped() {
+                                return None;
+                            }
+                        }
+
+                        None
+                    })
+                })
+                .collect();
+
+            workers.into_iter().filter_map(|w| w.join().unwrap()).next()
+        })
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+
+use crate::*;
+
+// Most of a block's txns and renames will already be sitting in a peer's mempool, so a compact block only sends the
+// header, the coinbase (which never goes through the mempool) and a short id for everything else.
+// The receiver rebuilds what it can, then asks for whatever it's missing.
+
+pub const SHORT_ID_SIZE: usize = 6;
+
+pub type ShortId = [u8; SHORT_ID_SIZE];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactBlock {
+    pub header: Header,
+    // Mixed into every short id so that nobody can grind out txns which collide for every block
+    pub salt: u64,
+    pub coinbase: Txn,
+    pub txn_ids: Vec<ShortId>,
+    pub rename_ids: Vec<ShortId>,
+}
+
+// Indexes are positions in the compact block's `txn_ids` and `rename_ids`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTxnsRequest {
+    pub block_hash: [u8; 32],
+    pub txn_indexes: Vec<u32>,
+    pub rename_indexes: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTxns {
+    pub block_hash: [u8; 32],
+    pub txns: Vec<Txn>,
+    pub renames: Vec<RenameOp>,
+}
+
+// A compact block which is being filled in on the receiving end
+#[derive(Debug, Clone)]
+pub struct PartialBlock {
+    header: Header,
+    key: [u8; 32],
+    coinbase: Txn,
+    txn_ids: Vec<ShortId>,
+    rename_ids: Vec<ShortId>,
+    txns: Vec<Option<Txn>>,
+    renames: Vec<Option<RenameOp>>,
+}
+
+pub fn short_id_key(header: &Header, salt: u64) -> [u8; 32] {
+    let mut data = encode_header(header).to_vec();
+    data.extend(salt.to_le_bytes());
+    hash(&data)
+}
+
+// `item_hash` is either a `txn_hash` or a `name_change_hash`
+pub fn short_id(key: &[u8; 32], item_hash: &[u8; 32]) -> ShortId {
+    let mut data = [0; 64];
+    data[0..32].copy_from_slice(key);
+    data[32..64].copy_from_slice(item_hash);
+    hash(&data)[0..SHORT_ID_SIZE].try_into().unwrap()
+}
+
+impl CompactBlock {
+    pub fn new(block: &Block, salt: u64) -> Self {
+        let key = short_id_key(&block.header, salt);
+
+        CompactBlock {
+            header: block.header.clone(),
+            salt,
+            coinbase: block.txns[0].clone(),
+            txn_ids: block.txns[1..]
+                .iter()
+                .map(|txn| short_id(&key, &txn_hash(txn)))
+                .collect(),
+            rename_ids: block
+                .name_changes
+                .iter()
+                .map(|op| short_id(&key, &name_change_hash(op)))
+                .collect(),
+        }
+    }
+}
+
+// Answers a peer's request for the items it couldn't find in its mempool
+pub fn block_txns(block: &Block, request: &BlockTxnsRequest) -> Result<BlockTxns, Error> {
+    let mut txns = vec![];
+    let mut renames = vec![];
+
+    // txn ids skip the coinbase, so they are offset by one from the block's txn list
+    for i in request.txn_indexes.iter() {
+        match block.txns.get(*i as usize + 1) {
+            Some(txn) => txns.push(txn.clone()),
+            None => decode_error!(format!("requested txn {i} is out of range")),
+        }
+    }
+
+    for i in request.rename_indexes.iter() {
+        match block.name_changes.get(*i as usize) {
+            Some(op) => renames.push(op.clone()),
+            None => decode_error!(format!("requested rename {i} is out of range")),
+        }
+    }
+
+    Ok(BlockTxns {
+        block_hash: request.block_hash,
+        txns,
+        renames,
+    })
+}
+
+impl PartialBlock {
+    pub fn new(compact: &CompactBlock) -> Self {
+        PartialBlock {
+            header: compact.header.clone(),
+            key: short_id_key(&compact.header, compact.salt),
+            coinbase: compact.coinbase.clone(),
+            txn_ids: compact.txn_ids.clone(),
+            rename_ids: compact.rename_ids.clone(),
+            txns: vec![None; compact.txn_ids.len()],
+            renames: vec![None; compact.rename_ids.len()],
+        }
+    }
+
+    pub fn block_hash(&self) -> [u8; 32] {
+        hash_header(&self.header)
+    }
+
+    // Fills every slot whose short id matches one of the given items. Normally these come from the mempool.
+    pub fn fill_from<'a>(
+        &mut self,
+        txns: impl IntoIterator<Item = &'a Txn>,
+        renames: impl IntoIterator<Item = &'a RenameOp>,
+    ) {
+        let txn_slots = slots_by_id(&self.txn_ids);
+        for txn in txns {
+            if let Some(slots) = txn_slots.get(&short_id(&self.key, &txn_hash(txn))) {
+                for slot in slots.iter() {
+                    self.txns[*slot].get_or_insert_with(|| txn.clone());
+                }
+            }
+        }
+
+        let rename_slots = slots_by_id(&self.rename_ids);
+        for op in renames {
+            if let Some(slots) = rename_slots.get(&short_id(&self.key, &name_change_hash(op))) {
+                for slot in slots.iter() {
+                    self.renames[*slot].get_or_insert_with(|| op.clone());
+                }
+            }
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.txns.iter().all(Option::is_some) && self.renames.iter().all(Option::is_some)
+    }
+
+    // The request to send back to the peer, or None if nothing is missing
+    pub fn missing(&self) -> Option<BlockTxnsRequest> {
+        if self.is_complete() {
+            return None;
+        }
+
+        Some(BlockTxnsRequest {
+            block_hash: self.block_hash(),
+            txn_indexes: missing_indexes(&self.txns),
+            rename_indexes: missing_indexes(&self.renames),
+        })
+    }
+
+    // Fills the gaps with a peer's response. Each item must match the short id of the slot it is going into.
+    pub fn fill_missing(&mut self, response: BlockTxns) -> Result<(), Error> {
+        if response.block_hash != self.block_hash() {
+            decode_error!("block txns response was for a different block");
+        }
+
+        let txn_indexes = missing_indexes(&self.txns);
+        let rename_indexes = missing_indexes(&self.renames);
+
+        if response.txns.len() != txn_indexes.len()
+            || response.renames.len() != rename_indexes.len()
+        {
+            decode_error!("block txns response did not contain every missing item");
+        }
+
+        for (i, txn) in txn_indexes.into_iter().zip(response.txns) {
+            if short_id(&self.key, &txn_hash(&txn)) != self.txn_ids[i as usize] {
+                decode_error!(format!(
+                    "txn {i} in block txns response has the wrong short id"
+                ));
+            }
+            self.txns[i as usize] = Some(txn);
+        }
+
+        for (i, op) in rename_indexes.into_iter().zip(response.renames) {
+            if short_id(&self.key, &name_change_hash(&op)) != self.rename_ids[i as usize] {
+                decode_error!(format!(
+                    "rename {i} in block txns response has the wrong short id"
+                ));
+            }
+            self.renames[i as usize] = Some(op);
+        }
+
+        Ok(())
+    }
+
+    // A merkle root mismatch means a short id collided with the wrong mempool item.
+    // When that happens the caller should fall back to requesting the full block.
+    pub fn finish(self) -> Result<Block, Error> {
+        if !self.is_complete() {
+            decode_error!("compact block is still missing items");
+        }
+
+        let mut txns = vec![self.coinbase];
+        txns.extend(self.txns.into_iter().flatten());
+        let name_changes: Vec<RenameOp> = self.renames.into_iter().flatten().collect();
+
+        if merkle_root(&txns, &name_changes) != self.header.merkle_root {
+            decode_error!("reconstructed block does not match its merkle root");
+        }
+
+        Ok(Block {
+            header: self.header,
+            txns,
+            name_changes,
+        })
+    }
+}
+
+fn slots_by_id(ids: &[ShortId]) -> HashMap<ShortId, Vec<usize>> {
+    let mut slots: HashMap<ShortId, Vec<usize>> = HashMap::new();
+    for (i, id) in ids.iter().enumerate() {
+        slots.entry(*id).or_default().push(i);
+    }
+    slots
+}
+
+fn missing_indexes<T>(slots: &[Option<T>]) -> Vec<u32> {
+    slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.is_none())
+        .map(|(i, _)| i as u32)
+        .collect()
+}
+
+//
+// --- ENCODING FUNCTIONS ---
+//
+
+fn encode_short_ids(ids: &[ShortId], data: &mut Vec<u8>) {
+    data.extend((ids.len() as u32).to_le_bytes());
+    for id in ids.iter() {
+        data.extend(id);
+    }
+}
+
+fn decode_short_ids(data: &mut &[u8]) -> Result<Vec<ShortId>, Error> {
+    let count = take_u32(data)?;
+    let mut ids = vec![];
+    for _ in 0..count {
+        ids.push(take_array(data)?);
+    }
+    Ok(ids)
+}
+
+fn encode_indexes(indexes: &[u32], data: &mut Vec<u8>) {
+    data.extend((indexes.len() as u32).to_le_bytes());
+    for i in indexes.iter() {
+        data.extend(i.to_le_bytes());
+    }
+}
+
+fn decode_indexes(data: &mut &[u8]) -> Result<Vec<u32>, Error> {
+    let count = take_u32(data)?;
+    let mut indexes = vec![];
+    for _ in 0..count {
+        indexes.push(take_u32(data)?);
+    }
+    Ok(indexes)
+}
+
+pub fn encode_compact_block(compact: &CompactBlock, data: &mut Vec<u8>) {
+    data.extend(encode_header(&compact.header));
+    data.extend(compact.salt.to_le_bytes());
+    data.extend(encode_txn(&compact.coinbase));
+    encode_short_ids(&compact.txn_ids, data);
+    encode_short_ids(&compact.rename_ids, data);
+}
+
+pub fn decode_compact_block(data: &mut &[u8]) -> Result<CompactBlock, Error> {
+    Ok(CompactBlock {
+        header: decode_header(data)?,
+        salt: take_u64(data)?,
+        coinbase: decode_txn(data)?,
+        txn_ids: decode_short_ids(data)?,
+        rename_ids: decode_short_ids(data)?,
+    })
+}
+
+pub fn encode_block_txns_request(request: &BlockTxnsRequest, data: &mut Vec<u8>) {
+    data.extend(request.block_hash);
+    encode_indexes(&request.txn_indexes, data);
+    encode_indexes(&request.rename_indexes, data);
+}
+
+pub fn decode_block_txns_request(data: &mut &[u8]) -> Result<BlockTxnsRequest, Error> {
+    Ok(BlockTxnsRequest {
+        block_hash: take_array(data)?,
+        txn_indexes: decode_indexes(data)?,
+        rename_indexes: decode_indexes(data)?,
+    })
+}
+
+pub fn encode_block_txns(response: &BlockTxns, data: &mut Vec<u8>) {
+    data.extend(response.block_hash);
+
+    data.extend((response.txns.len() as u32).to_le_bytes());
+    for txn in response.txns.iter() {
+        data.extend(encode_txn(txn));
+    }
+
+    data.extend((response.renames.len() as u32).to_le_bytes());
+    for op in response.renames.iter() {
+        data.extend(encode_name_change(op));
+    }
+}
+
+pub fn decode_block_txns(data: &mut &[u8]) -> Result<BlockTxns, Error> {
+    let block_hash = take_array(data)?;
+
+    let txn_count = take_u32(data)?;
+    let mut txns = vec![];
+    for _ in 0..txn_count {
+        txns.push(decode_txn(data)?);
+    }
+
+    let rename_count = take_u32(data)?;
+    let mut renames = vec![];
+    for _ in 0..rename_count {
+        renames.push(decode_name_change(data)?);
+    }
+
+    Ok(BlockTxns {
+        block_hash,
+        txns,
+        renames,
+    })
+}
@@ -0,0 +1,365 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+use crate::*;
+
+// Keeps track of every peer address we've heard of, and decides who to connect to next.
+// Times in here are unix seconds.
+
+// The most addresses a single addr message may carry
+pub const MAX_ADDRS_PER_MESSAGE: usize = 1_000;
+
+pub const MAX_ADDRESS_BOOK_SIZE: usize = 10_000;
+
+pub const DEFAULT_TARGET_OUTBOUND: usize = 8;
+
+// Addresses which haven't been seen for this long are no longer shared or kept
+pub const ADDRESS_EXPIRY: u64 = 30 * 24 * 60 * 60;
+
+// A peer claiming to have seen an address in the future gets this much slack before we correct it
+pub const MAX_CLOCK_DRIFT: u64 = 10 * 60;
+
+// Addresses which failed this many connection attempts in a row are forgotten
+pub const MAX_FAILED_ATTEMPTS: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressEntry {
+    pub last_seen: u64,
+    pub failed_attempts: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AddressBook {
+    entries: HashMap<SocketAddr, AddressEntry>,
+}
+
+// Reads a seed list. Each line holds one `ip:port`, and anything after a `#` is a comment.
+pub fn load_seed_list(path: impl AsRef<Path>) -> Result<Vec<SocketAddr>, Error> {
+    let mut seeds = vec![];
+
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.split('#').next().unwrap().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        match line.parse() {
+            Ok(addr) => seeds.push(addr),
+            Err(_) => decode_error!(format!("{line} in the seed list is not an ip:port")),
+        }
+    }
+
+    Ok(seeds)
+}
+
+// Peers in the same group are likely to be run by the same operator, so we try not to pick more than one.
+// IPv4 addresses are grouped by /16, IPv6 by /32.
+pub fn address_group(addr: &SocketAddr) -> Vec<u8> {
+    match addr.ip() {
+        IpAddr::V4(ip) => ip.octets()[0..2].to_vec(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => ip.octets()[0..2].to_vec(),
+            None => ip.octets()[0..4].to_vec(),
+        },
+    }
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        AddressBook::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, addr: &SocketAddr) -> Option<&AddressEntry> {
+        self.entries.get(addr)
+    }
+
+    // Seeds go in as though they were seen a long time ago, so anything learned from a real peer is preferred
+    pub fn add_seeds(&mut self, seeds: &[SocketAddr], now: u64) {
+        for seed in seeds.iter() {
+            self.add(*seed, now.saturating_sub(ADDRESS_EXPIRY / 2), now);
+        }
+    }
+
+    // Records an address some peer told us about. A newer `last_seen` replaces an older one.
+    pub fn add(&mut self, addr: SocketAddr, last_seen: u64, now: u64) {
+        if addr.port() == 0
+            || addr.ip().is_unspecified()
+            || now.saturating_sub(last_seen) > ADDRESS_EXPIRY
+        {
+            return;
+        }
+
+        // Nobody can have seen a peer in the future, so a peer that claims to is pushed back in time
+        let last_seen = if last_seen > now + MAX_CLOCK_DRIFT {
+            now.saturating_sub(ADDRESS_EXPIRY / 2)
+        } else {
+            last_seen.min(now)
+        };
+
+        self.entries
+            .entry(addr)
+            .and_modify(|e| e.last_seen = e.last_seen.max(last_seen))
+            .or_insert(AddressEntry {
+                last_seen,
+                failed_attempts: 0,
+            });
+
+        if self.entries.len() > MAX_ADDRESS_BOOK_SIZE {
+            self.evict_oldest();
+        }
+    }
+
+    // We connected to the address, or heard from it
+    pub fn mark_seen(&mut self, addr: SocketAddr, now: u64) {
+        let entry = self.entries.entry(addr).or_insert(AddressEntry {
+            last_seen: now,
+            failed_attempts: 0,
+        });
+
+        entry.last_seen = now;
+        entry.failed_attempts = 0;
+    }
+
+    pub fn mark_failed(&mut self, addr: &SocketAddr) {
+        if let Some(entry) = self.entries.get_mut(addr) {
+            entry.failed_attempts += 1;
+
+            if entry.failed_attempts >= MAX_FAILED_ATTEMPTS {
+                self.entries.remove(addr);
+            }
+        }
+    }
+
+    pub fn remove_expired(&mut self, now: u64) {
+        self.entries
+            .retain(|_, e| now.saturating_sub(e.last_seen) <= ADDRESS_EXPIRY);
+    }
+
+    // The most recently seen addresses, for answering a getaddr
+    pub fn addresses_to_share(&self, now: u64) -> Vec<(SocketAddr, u64)> {
+        let mut addrs: Vec<(SocketAddr, u64)> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| now.saturating_sub(e.last_seen) <= ADDRESS_EXPIRY)
+            .map(|(addr, e)| (*addr, e.last_seen))
+            .collect();
+
+        addrs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addrs.truncate(MAX_ADDRS_PER_MESSAGE);
+        addrs
+    }
+
+    // Picks up to `count` addresses to dial. Candidates are ranked by how recently they were seen and how few times
+    // they've failed, and each pick has to come from an address group nobody in `connected` is already in.
+    pub fn select_outbound(&self, connected: &[SocketAddr], count: usize) -> Vec<SocketAddr> {
+        let mut used_groups: HashSet<Vec<u8>> = connected.iter().map(address_group).collect();
+
+        let mut candidates: Vec<(&SocketAddr, &AddressEntry)> = self
+            .entries
+            .iter()
+            .filter(|(addr, _)| !connected.contains(addr))
+            .collect();
+
+        candidates.sort_by(|a, b| {
+            a.1.failed_attempts
+                .cmp(&b.1.failed_attempts)
+                .then(b.1.last_seen.cmp(&a.1.last_seen))
+                .then(a.0.cmp(b.0))
+        });
+
+        let mut picked = vec![];
+
+        for (addr, _) in candidates {
+            if picked.len() >= count {
+                break;
+            }
+
+            if used_groups.insert(address_group(addr)) {
+                picked.push(*addr);
+            }
+        }
+
+        picked
+    }
+
+    // Written one address per line as `ip:port last_seen failed_attempts`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut addrs: Vec<(&SocketAddr, &AddressEntry)> = self.entries.iter().collect();
+        addrs.sort_by_key(|(addr, _)| **addr);
+
+        let mut data = String::new();
+        for (addr, entry) in addrs {
+            data += &format!("{addr} {} {}\n", entry.last_seen, entry.failed_attempts);
+        }
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut book = AddressBook::new();
+
+        for line in fs::read_to_string(path)?.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            let [addr, last_seen, failed_attempts] = fields[..] else {
+                decode_error!(format!("{line} in the address book is malformed"));
+            };
+
+            let (Ok(addr), Ok(last_seen), Ok(failed_attempts)) =
+                (addr.parse(), last_seen.parse(), failed_attempts.parse())
+            else {
+                decode_error!(format!("{line} in the address book is malformed"));
+            };
+
+            book.entries.insert(
+                addr,
+                AddressEntry {
+                    last_seen,
+                    failed_attempts,
+                },
+            );
+        }
+
+        Ok(book)
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(addr, e)| (e.last_seen, **addr))
+            .map(|(addr, _)| *addr);
+
+        if let Some(addr) = oldest {
+            self.entries.remove(&addr);
+        }
+    }
+}
+
+// Keeps our outbound connection count at the target, using the address book to find who to dial
+#[derive(Debug, Clone)]
+pub struct PeerManager {
+    pub book: AddressBook,
+    pub target_outbound: usize,
+    outbound: Vec<SocketAddr>,
+    // Dialed, but not yet connected or failed
+    pending: Vec<SocketAddr>,
+}
+
+impl PeerManager {
+    pub fn new(book: AddressBook, target_outbound: usize) -> Self {
+        PeerManager {
+            book,
+            target_outbound,
+            outbound: vec![],
+            pending: vec![],
+        }
+    }
+
+    pub fn outbound(&self) -> &[SocketAddr] {
+        &self.outbound
+    }
+
+    // Addresses to start dialing now. Each one must be reported back through `connected` or `failed`.
+    pub fn next_dials(&mut self) -> Vec<SocketAddr> {
+        let busy = self.outbound.len() + self.pending.len();
+
+        if busy >= self.target_outbound {
+            return vec![];
+        }
+
+        let taken: Vec<SocketAddr> = self
+            .outbound
+            .iter()
+            .chain(self.pending.iter())
+            .copied()
+            .collect();
+        let dials = self
+            .book
+            .select_outbound(&taken, self.target_outbound - busy);
+
+        self.pending.extend(dials.iter().copied());
+        dials
+    }
+
+    pub fn connected(&mut self, addr: SocketAddr, now: u64) {
+        self.pending.retain(|a| *a != addr);
+        self.outbound.push(addr);
+        self.book.mark_seen(addr, now);
+    }
+
+    pub fn failed(&mut self, addr: SocketAddr) {
+        self.pending.retain(|a| *a != addr);
+        self.book.mark_failed(&addr);
+    }
+
+    pub fn disconnected(&mut self, addr: SocketAddr, now: u64) {
+        if self.outbound.contains(&addr) {
+            self.outbound.retain(|a| *a != addr);
+            self.book.mark_seen(addr, now);
+        }
+    }
+}
+
+//
+// --- ENCODING FUNCTIONS ---
+//
+
+// An address is a family byte (4 or 6), the ip, then the port
+pub fn encode_socket_addr(addr: &SocketAddr, data: &mut Vec<u8>) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            data.push(4);
+            data.extend(ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            data.push(6);
+            data.extend(ip.octets());
+        }
+    }
+    data.extend(addr.port().to_le_bytes());
+}
+
+pub fn decode_socket_addr(data: &mut &[u8]) -> Result<SocketAddr, Error> {
+    let ip = match take_u8(data)? {
+        4 => IpAddr::from(take_array::<4>(data)?),
+        6 => IpAddr::from(take_array::<16>(data)?),
+        family => decode_error!(format!("{family} is not a valid address family")),
+    };
+
+    Ok(SocketAddr::new(ip, u16::from_le_bytes(take_array(data)?)))
+}
+
+pub fn encode_addrs(addrs: &[(SocketAddr, u64)], data: &mut Vec<u8>) {
+    data.extend((addrs.len() as u32).to_le_bytes());
+    for (addr, last_seen) in addrs.iter() {
+        encode_socket_addr(addr, data);
+        data.extend(last_seen.to_le_bytes());
+    }
+}
+
+pub fn decode_addrs(data: &mut &[u8]) -> Result<Vec<(SocketAddr, u64)>, Error> {
+    let count = take_u32(data)? as usize;
+
+    if count > MAX_ADDRS_PER_MESSAGE {
+        decode_error!(format!("addr message held {count} addresses"));
+    }
+
+    let mut addrs = Vec::with_capacity(count);
+    for _ in 0..count {
+        addrs.push((decode_socket_addr(data)?, take_u64(data)?));
+    }
+    Ok(addrs)
+}
@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+
+use crate::net::discovery::MAX_ADDRS_PER_MESSAGE;
+use crate::net::{
+    ADDR_TAG, BLOCK_TAG, BLOCK_TXNS_TAG, COMPACT_BLOCK_TAG, GET_ADDR_TAG, GET_BLOCK_TAG,
+    GET_BLOCK_TXNS_TAG, MESSAGE_HEADER_SIZE, RENAME_TAG, TXN_TAG,
+};
+use crate::*;
+
+// Caps on what a single peer can make us do. Size caps come straight from the consensus rules: nothing bigger
+// than the largest valid item can ever be useful, so anything over them is rejected before it is decoded.
+
+// A name address is a tag byte, a length byte and up to 255 bytes of name
+pub const MAX_ADDRESS_SIZE: usize = 2 + MAX_NAME_SIZE;
+
+// The reciever count is a single byte, so a txn can have at most 255 recievers
+pub const MAX_TXN_SIZE: usize = MAX_ADDRESS_SIZE + 1 + 255 * (MAX_ADDRESS_SIZE + 8) + 64 + 8;
+
+pub const MAX_RENAME_SIZE: usize = 32 + 64 + 1 + MAX_NAME_SIZE + 8;
+
+// Every peer gets a bucket holding this many messages, refilled at MESSAGES_PER_SECOND
+pub const MESSAGE_BURST: u64 = 500;
+pub const MESSAGES_PER_SECOND: u64 = 100;
+
+// Byte allowances are measured in maximum sized blocks, so they grow along with the block size cap
+pub const BLOCKS_BURST: u64 = 4;
+pub const BLOCKS_PER_SECOND: u64 = 1;
+
+// Blocks held while waiting on their parent or on missing compact block items. When either table is full the
+// oldest entry makes way, and anything still waiting after its timeout (in milliseconds) is dropped.
+pub const MAX_ORPHAN_BLOCKS: usize = 64;
+pub const MAX_PARTIAL_BLOCKS: usize = 16;
+pub const ORPHAN_BLOCK_TIMEOUT: u64 = 120_000;
+pub const PARTIAL_BLOCK_TIMEOUT: u64 = 30_000;
+
+// How much can be waiting to go out to a single peer before new messages are dropped
+pub const MAX_QUEUED_MESSAGES: usize = 1_000;
+pub const MAX_QUEUED_BLOCKS: usize = 8;
+
+// The largest message that could be carrying something valid, header included
+pub fn max_message_size(tag: u8, blockchain_state: &BlockchainState) -> usize {
+    let max_block_size = max_block_size(median_block_size(&blockchain_state.last_100_block_sizes));
+
+    let payload = match tag {
+        TXN_TAG => MAX_TXN_SIZE.min(max_block_size),
+        RENAME_TAG => MAX_RENAME_SIZE,
+        GET_BLOCK_TAG => 32,
+        GET_ADDR_TAG => 0,
+        // Family byte, ipv6 address, port and last seen time
+        ADDR_TAG => 4 + MAX_ADDRS_PER_MESSAGE * (1 + 16 + 2 + 8),
+        BLOCK_TAG => max_block_size,
+        // A short id or index is always smaller than the item it stands for, so these are never bigger than
+        // the block they belong to plus their own fixed fields
+        COMPACT_BLOCK_TAG | GET_BLOCK_TXNS_TAG | BLOCK_TXNS_TAG => max_block_size + 64,
+        _ => 0,
+    };
+
+    payload + MESSAGE_HEADER_SIZE
+}
+
+pub fn check_message_size(data: &[u8], blockchain_state: &BlockchainState) -> Result<(), Error> {
+    let Some(tag) = data.get(MESSAGE_HEADER_SIZE - 1) else {
+        peer_limit_error!("the message was shorter than a header");
+    };
+
+    let max = max_message_size(*tag, blockchain_state);
+
+    if data.len() > max {
+        peer_limit_error!(format!(
+            "a message with tag {tag} was {} bytes, the limit is {max}",
+            data.len()
+        ));
+    }
+
+    Ok(())
+}
+
+// Times are in milliseconds
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: u64,
+    per_second: u64,
+    // Scaled up by 1000 so that refilling every millisecond doesn't round down to nothing
+    milli_tokens: u64,
+    last_refill: u64,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u64, per_second: u64, now: u64) -> Self {
+        TokenBucket {
+            capacity,
+            per_second,
+            milli_tokens: capacity * 1_000,
+            last_refill: now,
+        }
+    }
+
+    pub fn try_take(&mut self, amount: u64, now: u64) -> bool {
+        let elapsed = now.saturating_sub(self.last_refill);
+        self.milli_tokens =
+            (self.milli_tokens + elapsed * self.per_second).min(self.capacity * 1_000);
+        self.last_refill = self.last_refill.max(now);
+
+        if self.milli_tokens < amount * 1_000 {
+            return false;
+        }
+
+        self.milli_tokens -= amount * 1_000;
+        true
+    }
+}
+
+// Inbound limits for a single peer
+#[derive(Debug, Clone)]
+pub struct PeerLimiter {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+}
+
+impl PeerLimiter {
+    pub fn new(blockchain_state: &BlockchainState, now: u64) -> Self {
+        let max_block_size =
+            max_block_size(median_block_size(&blockchain_state.last_100_block_sizes)) as u64;
+
+        PeerLimiter {
+            messages: TokenBucket::new(MESSAGE_BURST, MESSAGES_PER_SECOND, now),
+            bytes: TokenBucket::new(
+                BLOCKS_BURST * max_block_size,
+                BLOCKS_PER_SECOND * max_block_size,
+                now,
+            ),
+        }
+    }
+
+    // Size is checked first so an oversized message doesn't use up the peer's allowance
+    pub fn check(
+        &mut self,
+        data: &[u8],
+        blockchain_state: &BlockchainState,
+        now: u64,
+    ) -> Result<(), Error> {
+        check_message_size(data, blockchain_state)?;
+
+        // The byte bucket follows the block size cap, which moves as the median does
+        let max_block_size =
+            max_block_size(median_block_size(&blockchain_state.last_100_block_sizes)) as u64;
+        self.bytes.capacity = BLOCKS_BURST * max_block_size;
+        self.bytes.per_second = BLOCKS_PER_SECOND * max_block_size;
+
+        if !self.messages.try_take(1, now) {
+            peer_limit_error!("the peer sent too many messages");
+        }
+
+        if !self.bytes.try_take(data.len() as u64, now) {
+            peer_limit_error!("the peer sent too many bytes");
+        }
+
+        Ok(())
+    }
+}
+
+// A FIFO of encoded messages waiting on a single peer, bounded both by count and total size
+#[derive(Debug, Clone)]
+pub struct PeerQueue {
+    messages: VecDeque<Vec<u8>>,
+    bytes: usize,
+    max_messages: usize,
+    max_bytes: usize,
+}
+
+impl PeerQueue {
+    pub fn new(max_messages: usize, max_bytes: usize) -> Self {
+        PeerQueue {
+            messages: VecDeque::new(),
+            bytes: 0,
+            max_messages,
+            max_bytes,
+        }
+    }
+
+    // Sized from the consensus block size cap
+    pub fn for_state(blockchain_state: &BlockchainState) -> Self {
+        let max_block_size =
+            max_block_size(median_block_size(&blockchain_state.last_100_block_sizes));
+        PeerQueue::new(MAX_QUEUED_MESSAGES, MAX_QUEUED_BLOCKS * max_block_size)
+    }
+
+    pub fn push(&mut self, data: Vec<u8>) -> Result<(), Error> {
+        if self.messages.len() >= self.max_messages || self.bytes + data.len() > self.max_bytes {
+            peer_limit_error!("the peer's queue is full");
+        }
+
+        self.bytes += data.len();
+        self.messages.push_back(data);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let data = self.messages.pop_front()?;
+        self.bytes -= data.len();
+        Some(data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
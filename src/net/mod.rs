@@ -0,0 +1,111 @@
+use crate::*;
+
+pub mod compact;
+pub mod discovery;
+pub mod limits;
+pub mod node;
+pub mod sim;
+pub mod transport;
+
+use std::net::SocketAddr;
+
+use compact::{BlockTxns, BlockTxnsRequest, CompactBlock};
+
+pub const TXN_TAG: u8 = 0;
+pub const RENAME_TAG: u8 = 1;
+pub const BLOCK_TAG: u8 = 2;
+pub const GET_BLOCK_TAG: u8 = 3;
+pub const COMPACT_BLOCK_TAG: u8 = 4;
+pub const GET_BLOCK_TXNS_TAG: u8 = 5;
+pub const BLOCK_TXNS_TAG: u8 = 6;
+pub const GET_ADDR_TAG: u8 = 7;
+pub const ADDR_TAG: u8 = 8;
+
+// Every message starts with the network's magic, then its tag byte
+pub const MESSAGE_HEADER_SIZE: usize = 5;
+
+// Everything peers can say to each other. Each message is sent as a header followed by its payload.
+#[derive(Debug, Clone)]
+pub enum Message {
+    Txn(Txn),
+    Rename(RenameOp),
+    Block(Block),
+    GetBlock([u8; 32]),
+    CompactBlock(CompactBlock),
+    GetBlockTxns(BlockTxnsRequest),
+    BlockTxns(BlockTxns),
+    GetAddr,
+    // Peer addresses along with when they were last seen, in unix seconds
+    Addr(Vec<(SocketAddr, u64)>),
+}
+
+pub fn encode_message(message: &Message, network: Network) -> Vec<u8> {
+    let mut data = network.magic().to_vec();
+
+    match message {
+        Message::Txn(txn) => {
+            data.push(TXN_TAG);
+            data.extend(encode_txn(txn));
+        }
+        Message::Rename(op) => {
+            data.push(RENAME_TAG);
+            data.extend(encode_name_change(op));
+        }
+        Message::Block(block) => {
+            data.push(BLOCK_TAG);
+            data.extend(encode_block(block));
+        }
+        Message::GetBlock(hash) => {
+            data.push(GET_BLOCK_TAG);
+            data.extend(hash);
+        }
+        Message::CompactBlock(compact) => {
+            data.push(COMPACT_BLOCK_TAG);
+            compact::encode_compact_block(compact, &mut data);
+        }
+        Message::GetBlockTxns(request) => {
+            data.push(GET_BLOCK_TXNS_TAG);
+            compact::encode_block_txns_request(request, &mut data);
+        }
+        Message::BlockTxns(response) => {
+            data.push(BLOCK_TXNS_TAG);
+            compact::encode_block_txns(response, &mut data);
+        }
+        Message::GetAddr => data.push(GET_ADDR_TAG),
+        Message::Addr(addrs) => {
+            data.push(ADDR_TAG);
+            discovery::encode_addrs(addrs, &mut data);
+        }
+    }
+
+    data
+}
+
+// Unlike the item decoders, this expects `data` to hold exactly one message.
+// Messages from any other network are rejected.
+pub fn decode_message(mut data: &[u8], network: Network) -> Result<Message, Error> {
+    let data = &mut data;
+
+    if take_array::<4>(data)? != network.magic() {
+        decode_error!(format!("the message was not for {network}"));
+    }
+
+    let message = match take_u8(data)? {
+        TXN_TAG => Message::Txn(decode_txn(data)?),
+        RENAME_TAG => Message::Rename(decode_name_change(data)?),
+        BLOCK_TAG => Message::Block(decode_block(data)?),
+        GET_BLOCK_TAG => Message::GetBlock(take_array(data)?),
+        COMPACT_BLOCK_TAG => Message::CompactBlock(compact::decode_compact_block(data)?),
+        GET_BLOCK_TXNS_TAG => Message::GetBlockTxns(compact::decode_block_txns_request(data)?),
+        BLOCK_TXNS_TAG => Message::BlockTxns(compact::decode_block_txns(data)?),
+        GET_ADDR_TAG => Message::GetAddr,
+        ADDR_TAG => Message::Addr(discovery::decode_addrs(data)?),
+        tag => decode_error!(format!("{tag} is not a valid message tag")),
+    };
+
+    if !data.is_empty() {
+        decode_error!(format!("{} trailing bytes after message", data.len()));
+    }
+
+    Ok(message)
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+
+use crate::chain::{BlockStatus, Chain};
+use crate::events::{rename_event, txn_event, Event};
+use crate::fees::FeeEstimator;
+use crate::mempool::{Mempool, RenamePool, TxnSource};
+use crate::net::compact::{block_txns, BlockTxns, CompactBlock, PartialBlock};
+use crate::net::discovery::AddressBook;
+use crate::net::limits::{
+    PeerLimiter, MAX_ORPHAN_BLOCKS, MAX_PARTIAL_BLOCKS, ORPHAN_BLOCK_TIMEOUT, PARTIAL_BLOCK_TIMEOUT,
+};
+use crate::net::{decode_message, Message};
+use crate::template::{build_template, BlockTemplate};
+use crate::*;
+
+// The protocol logic of a node, without any sockets attached. Whatever carries the messages
+// (the simulator, or a real transport) feeds them in and sends back whatever comes out.
+
+pub type PeerId = usize;
+
+#[derive(Debug, Clone)]
+pub enum Outbound {
+    // Back to the peer the message came from
+    Reply(Message),
+    // To every peer except the one the message came from
+    Relay(Message),
+}
+
+// Something held until a peer follows up on it, remembered with who sent it and when so it can be dropped
+// if they never do
+struct Held<T> {
+    item: T,
+    from: PeerId,
+    received: u64,
+}
+
+pub struct Node {
+    pub chain: Chain,
+    pub addresses: AddressBook,
+    pub mempool: Mempool,
+    pub renames: RenamePool,
+    pub fees: FeeEstimator,
+    // Blocks whose parent we don't have yet, keyed by the parent's hash
+    orphans: HashMap<[u8; 32], Vec<Held<Block>>>,
+    partial_blocks: HashMap<[u8; 32], Held<PartialBlock>>,
+    limiters: HashMap<PeerId, PeerLimiter>,
+    next_salt: u64,
+    // None until something asks to take them, so nodes nobody is listening to don't pile them up
+    events: Option<Vec<Event>>,
+}
+
+impl Node {
+    pub fn new(chain: Chain) -> Self {
+        Node {
+            chain,
+            addresses: AddressBook::new(),
+            mempool: Mempool::new(),
+            renames: RenamePool::new(),
+            fees: FeeEstimator::new(),
+            orphans: HashMap::new(),
+            partial_blocks: HashMap::new(),
+            limiters: HashMap::new(),
+            next_salt: 0,
+            events: None,
+        }
+    }
+
+    // Starts keeping events for take_events
+    pub fn record_events(&mut self) {
+        self.events.get_or_insert_with(Vec::new);
+    }
+
+    // Everything that happened since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn emit(&mut self, event: impl FnOnce(&BlockchainState) -> Event) {
+        if let Some(events) = self.events.as_mut() {
+            events.push(event(&self.chain.state));
+        }
+    }
+
+    // Called after anything that changes the mempool, so its evictions are passed on or dropped instead of
+    // piling up
+    fn emit_evictions(&mut self) {
+        for eviction in self.mempool.take_evictions() {
+            self.emit(|_| Event::TxnEvicted(eviction));
+        }
+    }
+
+    // Entry point for raw bytes off the wire. `now` is in milliseconds.
+    // An error means the peer broke a limit or sent garbage, and should be disconnected.
+    pub fn receive(&mut self, from: PeerId, data: &[u8], now: u64) -> Result<Vec<Outbound>, Error> {
+        self.limiters
+            .entry(from)
+            .or_insert_with(|| PeerLimiter::new(&self.chain.state, now))
+            .check(data, &self.chain.state, now)?;
+
+        let message = decode_message(data, self.chain.state.network)?;
+        Ok(self.handle_message(from, message, now))
+    }
+
+    // Whatever the peer left waiting on a follow up will never get one
+    pub fn remove_peer(&mut self, peer: PeerId) {
+        self.limiters.remove(&peer);
+        self.partial_blocks.retain(|_, held| held.from != peer);
+        for children in self.orphans.values_mut() {
+            children.retain(|held| held.from != peer);
+        }
+        self.orphans.retain(|_, children| !children.is_empty());
+    }
+
+    pub fn handle_message(&mut self, from: PeerId, message: Message, now: u64) -> Vec<Outbound> {
+        match message {
+            Message::Block(block) => self.process_block(from, block, now),
+            Message::GetBlock(hash) => match self.chain.block(&hash) {
+                Some(block) => vec![Outbound::Reply(Message::Block(block.clone()))],
+                None => vec![],
+            },
+            Message::CompactBlock(compact) => self.process_compact_block(from, compact, now),
+            Message::GetBlockTxns(request) => match self.chain.block(&request.block_hash) {
+                Some(block) => match block_txns(block, &request) {
+                    Ok(response) => vec![Outbound::Reply(Message::BlockTxns(response))],
+                    Err(_) => vec![],
+                },
+                None => vec![],
+            },
+            Message::BlockTxns(response) => self.process_block_txns(from, response, now),
+            // Only txns we hadn't seen and which are still valid get passed on
+            Message::Txn(txn) => {
+                match self.mempool.add_txn(
+                    txn.clone(),
+                    TxnSource::Peer(from),
+                    &self.chain.state,
+                    now,
+                ) {
+                    Ok(_) => {
+                        self.emit(|state| txn_event(&txn, state));
+                        self.emit_evictions();
+                        vec![Outbound::Relay(Message::Txn(txn))]
+                    }
+                    Err(_) => {
+                        self.emit_evictions();
+                        vec![]
+                    }
+                }
+            }
+            Message::Rename(op) => match self.renames.add_op(op.clone(), &self.chain.state, now) {
+                Ok(_) => {
+                    self.emit(|state| rename_event(&op, state));
+                    vec![Outbound::Relay(Message::Rename(op))]
+                }
+                Err(_) => vec![],
+            },
+            // The address book works in seconds
+            Message::GetAddr => vec![Outbound::Reply(Message::Addr(
+                self.addresses.addresses_to_share(now / 1_000),
+            ))],
+            Message::Addr(addrs) => {
+                for (addr, last_seen) in addrs {
+                    self.addresses.add(addr, last_seen, now / 1_000);
+                }
+                vec![]
+            }
+        }
+    }
+
+    // Announces our tip so peers that have fallen behind can catch up. Nothing is sent while we're still at genesis.
+    pub fn announce_tip(&mut self) -> Option<Message> {
+        let tip = self.chain.block(&self.chain.tip_hash())?.clone();
+        Some(self.compact(&tip))
+    }
+
+    // The fee per byte a txn should pay to be confirmed within `target` blocks
+    pub fn estimate_fee(&self, target: usize) -> u64 {
+        self.fees.estimate(&self.mempool, target, &self.chain.state)
+    }
+
+    // A block on top of our tip paying `miner`, filled from the pending pools. `time` is in unix seconds.
+    pub fn block_template(&self, miner: [u8; 32], time: u64) -> BlockTemplate {
+        build_template(&self.chain.state, &self.mempool, &self.renames, miner, time)
+    }
+
+    // Adds a txn of our own, returning the message to send every peer
+    pub fn submit_txn(&mut self, txn: Txn, now: u64) -> Result<Message, Error> {
+        let added = self
+            .mempool
+            .add_txn(txn.clone(), TxnSource::Local, &self.chain.state, now);
+        if added.is_ok() {
+            self.emit(|state| txn_event(&txn, state));
+        }
+        self.emit_evictions();
+
+        added?;
+        Ok(Message::Txn(txn))
+    }
+
+    // Adds a rename of our own, returning the message to send every peer
+    pub fn submit_rename(&mut self, op: RenameOp, now: u64) -> Result<Message, Error> {
+        self.renames.add_op(op.clone(), &self.chain.state, now)?;
+        self.emit(|state| rename_event(&op, state));
+        Ok(Message::Rename(op))
+    }
+
+    // Adds a block to the chain, keeping the pending pools in step with whatever becomes the main chain
+    pub fn add_block(&mut self, block: Block, now: u64) -> Result<BlockStatus, Error> {
+        let status = self.chain.add_block(block)?;
+
+        if let BlockStatus::Connected {
+            disconnected,
+            connected,
+        } = &status
+        {
+            self.mempool
+                .reorganize(disconnected, connected, &self.chain.state, now);
+            self.renames
+                .reorganize(disconnected, connected, &self.chain.state, now);
+
+            for _ in disconnected.iter() {
+                self.fees.block_disconnected();
+            }
+            for block in connected.iter() {
+                self.fees.block_connected(block);
+            }
+
+            // Stored blocks keep their height whichever branch they're on
+            for block in disconnected.iter() {
+                let height = self
+                    .chain
+                    .block_height(&hash_header(&block.header))
+                    .unwrap();
+                self.emit(|_| Event::BlockDisconnected {
+                    block: block.clone(),
+                    height,
+                });
+            }
+            for block in connected.iter() {
+                let height = self
+                    .chain
+                    .block_height(&hash_header(&block.header))
+                    .unwrap();
+                self.emit(|_| Event::BlockConnected {
+                    block: block.clone(),
+                    height,
+                });
+            }
+            self.emit_evictions();
+        }
+
+        Ok(status)
+    }
+
+    pub fn process_block(&mut self, from: PeerId, block: Block, now: u64) -> Vec<Outbound> {
+        self.expire_held(now);
+
+        let mut outbound = vec![];
+        let mut pending = vec![block];
+
+        while let Some(block) = pending.pop() {
+            let hash = hash_header(&block.header);
+            let parent = block.header.prev_block_hash;
+
+            match self.add_block(block.clone(), now) {
+                Ok(BlockStatus::Orphan) => {
+                    self.make_room_for_orphan();
+                    self.orphans.entry(parent).or_default().push(Held {
+                        item: block,
+                        from,
+                        received: now,
+                    });
+                    outbound.push(Outbound::Reply(Message::GetBlock(parent)));
+                    continue;
+                }
+                Ok(BlockStatus::Connected { .. }) => {
+                    outbound.retain(|o| !matches!(o, Outbound::Relay(_)));
+                    outbound.push(Outbound::Relay(self.compact(&block)));
+                }
+                Ok(BlockStatus::SideChain) => {}
+                Ok(BlockStatus::AlreadyKnown) | Err(_) => continue,
+            }
+
+            // Anything that was waiting on this block can now be stored as well
+            self.partial_blocks.remove(&hash);
+            if let Some(children) = self.orphans.remove(&hash) {
+                pending.extend(children.into_iter().map(|held| held.item));
+            }
+        }
+
+        outbound
+    }
+
+    // The header has to show the same proof of work the chain asks of any block before we hold anything for it
+    fn process_compact_block(
+        &mut self,
+        from: PeerId,
+        compact: CompactBlock,
+        now: u64,
+    ) -> Vec<Outbound> {
+        self.expire_held(now);
+        let hash = hash_header(&compact.header);
+
+        if self.chain.contains(&hash)
+            || self.partial_blocks.contains_key(&hash)
+            || !meets_difficulty(&hash, &self.chain.state.difficulty)
+        {
+            return vec![];
+        }
+
+        let mut partial = PartialBlock::new(&compact);
+        partial.fill_from(self.mempool.txns(), self.renames.ops());
+
+        match partial.missing() {
+            Some(request) => {
+                self.make_room_for_partial();
+                self.partial_blocks.insert(
+                    hash,
+                    Held {
+                        item: partial,
+                        from,
+                        received: now,
+                    },
+                );
+                vec![Outbound::Reply(Message::GetBlockTxns(request))]
+            }
+            None => self.finish_partial(from, partial, now),
+        }
+    }
+
+    fn process_block_txns(&mut self, from: PeerId, response: BlockTxns, now: u64) -> Vec<Outbound> {
+        let Some(Held {
+            item: mut partial, ..
+        }) = self.partial_blocks.remove(&response.block_hash)
+        else {
+            return vec![];
+        };
+
+        match partial.fill_missing(response) {
+            Ok(()) => self.finish_partial(from, partial, now),
+            Err(_) => vec![Outbound::Reply(Message::GetBlock(partial.block_hash()))],
+        }
+    }
+
+    // If the rebuilt block doesn't match its header, a short id collided and we fall back to the full block
+    fn finish_partial(&mut self, from: PeerId, partial: PartialBlock, now: u64) -> Vec<Outbound> {
+        let hash = partial.block_hash();
+
+        match partial.finish() {
+            Ok(block) => self.process_block(from, block, now),
+            Err(_) => vec![Outbound::Reply(Message::GetBlock(hash))],
+        }
+    }
+
+    // Drops whatever has waited past its timeout for a follow up that never came
+    fn expire_held(&mut self, now: u64) {
+        self.partial_blocks
+            .retain(|_, held| now.saturating_sub(held.received) < PARTIAL_BLOCK_TIMEOUT);
+
+        for children in self.orphans.values_mut() {
+            children.retain(|held| now.saturating_sub(held.received) < ORPHAN_BLOCK_TIMEOUT);
+        }
+        self.orphans.retain(|_, children| !children.is_empty());
+    }
+
+    // When a table is full, the entry that has waited longest makes way
+    fn make_room_for_partial(&mut self) {
+        if self.partial_blocks.len() >= MAX_PARTIAL_BLOCKS {
+            let oldest = self
+                .partial_blocks
+                .iter()
+                .min_by_key(|(_, held)| held.received)
+                .map(|(hash, _)| *hash);
+            if let Some(oldest) = oldest {
+                self.partial_blocks.remove(&oldest);
+            }
+        }
+    }
+
+    fn make_room_for_orphan(&mut self) {
+        if self.orphans.values().map(Vec::len).sum::<usize>() >= MAX_ORPHAN_BLOCKS {
+            let oldest = self
+                .orphans
+                .iter()
+                .flat_map(|(parent, children)| {
+                    children
+                        .iter()
+                        .enumerate()
+                        .map(move |(i, held)| (held.received, *parent, i))
+                })
+                .min();
+            if let Some((_, parent, i)) = oldest {
+                let children = self.orphans.get_mut(&parent).unwrap();
+                children.remove(i);
+                if children.is_empty() {
+                    self.orphans.remove(&parent);
+                }
+            }
+        }
+    }
+
+    fn compact(&mut self, block: &Block) -> Message {
+        self.next_salt += 1;
+        Message::CompactBlock(CompactBlock::new(block, self.next_salt))
+    }
+}
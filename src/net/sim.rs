@@ -0,0 +1,339 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+
+use crate::chain::{BlockStatus, Chain};
+use crate::net::limits::PeerQueue;
+use crate::net::node::{Node, Outbound, PeerId};
+use crate::net::{encode_message, Message};
+use crate::*;
+
+// Runs a whole network of nodes inside one process. Time is simulated and every random choice comes from a
+// seeded generator, so a test given the same seed always plays out exactly the same way.
+// Messages go through the real wire encoding and the node's inbound limits on the way between nodes.
+// Each direction of a connection is an ordered stream with a bounded queue, like a TCP socket with a send buffer.
+
+pub const DEFAULT_LATENCY: u64 = 50;
+
+// Gives up on `run_until_idle` after this many deliveries, in case nodes end up talking to each other forever
+pub const MAX_DELIVERIES: usize = 1_000_000;
+
+pub struct Simulator {
+    pub nodes: Vec<Node>,
+    peers: Vec<BTreeSet<PeerId>>,
+    default_latency: u64,
+    latencies: HashMap<(PeerId, PeerId), u64>,
+    // Which side of a partition each node is on. Messages only make it between nodes in the same group.
+    groups: Option<Vec<usize>>,
+    drop_rate: f64,
+    rng: u64,
+    links: HashMap<(PeerId, PeerId), Link>,
+    queue: BinaryHeap<Reverse<Arrival>>,
+    now: u64,
+    next_seq: u64,
+    pub delivered: usize,
+    pub dropped: usize,
+    // Connections closed because a node's limits rejected what came down them
+    pub disconnects: usize,
+}
+
+// One direction of a connection
+struct Link {
+    queue: PeerQueue,
+    // Messages on a link never overtake each other, even if its latency drops
+    last_arrival: u64,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Arrival {
+    // Ordered by arrival time, then by send order so that ties always break the same way
+    arrives_at: u64,
+    seq: u64,
+    from: PeerId,
+    to: PeerId,
+}
+
+impl Simulator {
+    // Every node starts from the same genesis state, without any connections
+    pub fn new(node_count: usize, genesis_state: &BlockchainState, seed: u64) -> Self {
+        Simulator {
+            nodes: (0..node_count)
+                .map(|_| Node::new(Chain::new(genesis_state.clone())))
+                .collect(),
+            peers: vec![BTreeSet::new(); node_count],
+            default_latency: DEFAULT_LATENCY,
+            latencies: HashMap::new(),
+            groups: None,
+            drop_rate: 0.0,
+            // xorshift gets stuck on 0
+            rng: seed.max(1),
+            links: HashMap::new(),
+            queue: BinaryHeap::new(),
+            now: 0,
+            next_seq: 0,
+            delivered: 0,
+            dropped: 0,
+            disconnects: 0,
+        }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn connect(&mut self, a: PeerId, b: PeerId) {
+        self.peers[a].insert(b);
+        self.peers[b].insert(a);
+    }
+
+    // Anything still queued between the two is lost
+    pub fn disconnect(&mut self, a: PeerId, b: PeerId) {
+        self.peers[a].remove(&b);
+        self.peers[b].remove(&a);
+        self.links.remove(&(a, b));
+        self.links.remove(&(b, a));
+        self.queue.retain(|Reverse(arrival)| {
+            (arrival.from, arrival.to) != (a, b) && (arrival.from, arrival.to) != (b, a)
+        });
+        self.nodes[a].remove_peer(b);
+        self.nodes[b].remove_peer(a);
+    }
+
+    pub fn is_connected(&self, a: PeerId, b: PeerId) -> bool {
+        self.peers[a].contains(&b)
+    }
+
+    pub fn connect_all(&mut self) {
+        for a in 0..self.nodes.len() {
+            for b in (a + 1)..self.nodes.len() {
+                self.connect(a, b);
+            }
+        }
+    }
+
+    pub fn set_default_latency(&mut self, latency: u64) {
+        self.default_latency = latency;
+    }
+
+    // Latency applies in both directions
+    pub fn set_latency(&mut self, a: PeerId, b: PeerId, latency: u64) {
+        self.latencies.insert((a.min(b), a.max(b)), latency);
+    }
+
+    // The chance from 0 to 1 that any single message is lost
+    pub fn set_drop_rate(&mut self, drop_rate: f64) {
+        self.drop_rate = drop_rate;
+    }
+
+    // Nodes not listed in any group end up cut off from everyone
+    pub fn partition(&mut self, groups: &[&[PeerId]]) {
+        let mut assignment: Vec<usize> = (0..self.nodes.len()).map(|i| groups.len() + i).collect();
+
+        for (group, members) in groups.iter().enumerate() {
+            for node in members.iter() {
+                assignment[*node] = group;
+            }
+        }
+
+        self.groups = Some(assignment);
+    }
+
+    // Removes the partition, then has every node announce its tip so the two sides can find out about each other
+    pub fn heal(&mut self) {
+        self.groups = None;
+
+        for node in 0..self.nodes.len() {
+            if let Some(message) = self.nodes[node].announce_tip() {
+                self.broadcast(node, None, &message);
+            }
+        }
+    }
+
+    pub fn submit_block(&mut self, node: PeerId, block: Block) -> Result<BlockStatus, Error> {
+        let status = self.nodes[node].add_block(block, self.now)?;
+
+        if let BlockStatus::Connected { .. } = status {
+            let message = self.nodes[node].announce_tip().unwrap();
+            self.broadcast(node, None, &message);
+        }
+
+        Ok(status)
+    }
+
+    // Adds a txn to the node's mempool and sends it to the node's peers
+    pub fn submit_txn(&mut self, node: PeerId, txn: Txn) -> Result<(), Error> {
+        let message = self.nodes[node].submit_txn(txn, self.now)?;
+        self.broadcast(node, None, &message);
+        Ok(())
+    }
+
+    // Adds a rename to the node's pool and sends it to the node's peers
+    pub fn submit_rename(&mut self, node: PeerId, op: RenameOp) -> Result<(), Error> {
+        let message = self.nodes[node].submit_rename(op, self.now)?;
+        self.broadcast(node, None, &message);
+        Ok(())
+    }
+
+    // Hands a message to a node as though a peer had sent it
+    pub fn inject(&mut self, from: PeerId, to: PeerId, message: &Message) {
+        self.send_raw(
+            from,
+            to,
+            encode_message(message, self.nodes[from].chain.state.network),
+        );
+    }
+
+    // Like inject, but the bytes don't have to be a valid message. Used to play a misbehaving peer.
+    pub fn inject_raw(&mut self, from: PeerId, to: PeerId, data: Vec<u8>) {
+        self.send_raw(from, to, data);
+    }
+
+    // Delivers the next message. Returns false once nothing is left in flight.
+    pub fn step(&mut self) -> bool {
+        let Some(Reverse(arrival)) = self.queue.pop() else {
+            return false;
+        };
+
+        self.now = self.now.max(arrival.arrives_at);
+        let (from, to) = (arrival.from, arrival.to);
+
+        let data = self
+            .links
+            .get_mut(&(from, to))
+            .unwrap()
+            .queue
+            .pop()
+            .unwrap();
+
+        if !self.reachable(from, to) || self.roll_drop() {
+            self.dropped += 1;
+            return true;
+        }
+
+        self.delivered += 1;
+
+        match self.nodes[to].receive(from, &data, self.now) {
+            Ok(outbound) => {
+                for outbound in outbound {
+                    match outbound {
+                        Outbound::Reply(message) => self.send(to, from, &message),
+                        Outbound::Relay(message) => self.broadcast(to, Some(from), &message),
+                    }
+                }
+            }
+            Err(_) => {
+                self.disconnect(from, to);
+                self.disconnects += 1;
+            }
+        }
+
+        true
+    }
+
+    // Returns the number of messages delivered
+    pub fn run_until_idle(&mut self) -> usize {
+        let start = self.delivered;
+
+        while self.delivered - start < MAX_DELIVERIES && self.step() {}
+
+        self.delivered - start
+    }
+
+    // Delivers everything due to arrive in the next `duration`, then moves the clock forward by that much
+    pub fn run_for(&mut self, duration: u64) {
+        let until = self.now + duration;
+
+        while let Some(Reverse(next)) = self.queue.peek() {
+            if next.arrives_at > until {
+                break;
+            }
+            self.step();
+        }
+
+        self.now = until;
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn tips_converged(&self) -> bool {
+        let tip = self.nodes[0].chain.tip_hash();
+        self.nodes.iter().all(|n| n.chain.tip_hash() == tip)
+    }
+
+    pub fn states_converged(&self) -> bool {
+        let state = &self.nodes[0].chain.state;
+        self.nodes.iter().all(|n| n.chain.state == *state)
+    }
+
+    fn broadcast(&mut self, from: PeerId, except: Option<PeerId>, message: &Message) {
+        let peers: Vec<PeerId> = self.peers[from].iter().copied().collect();
+
+        for peer in peers {
+            if Some(peer) != except {
+                self.send(from, peer, message);
+            }
+        }
+    }
+
+    fn send(&mut self, from: PeerId, to: PeerId, message: &Message) {
+        self.send_raw(
+            from,
+            to,
+            encode_message(message, self.nodes[from].chain.state.network),
+        );
+    }
+
+    // Messages which don't fit in the link's queue are dropped
+    fn send_raw(&mut self, from: PeerId, to: PeerId, data: Vec<u8>) {
+        let latency = self
+            .latencies
+            .get(&(from.min(to), from.max(to)))
+            .copied()
+            .unwrap_or(self.default_latency);
+
+        let state = &self.nodes[from].chain.state;
+        let link = self.links.entry((from, to)).or_insert_with(|| Link {
+            queue: PeerQueue::for_state(state),
+            last_arrival: 0,
+        });
+
+        if link.queue.push(data).is_err() {
+            self.dropped += 1;
+            return;
+        }
+
+        let arrives_at = (self.now + latency).max(link.last_arrival);
+        link.last_arrival = arrives_at;
+
+        self.queue.push(Reverse(Arrival {
+            arrives_at,
+            seq: self.next_seq,
+            from,
+            to,
+        }));
+
+        self.next_seq += 1;
+    }
+
+    // Checked on arrival, so messages already on the wire when a partition starts are lost too
+    fn reachable(&self, from: PeerId, to: PeerId) -> bool {
+        match &self.groups {
+            Some(groups) => groups[from] == groups[to],
+            None => true,
+        }
+    }
+
+    fn roll_drop(&mut self) -> bool {
+        if self.drop_rate <= 0.0 {
+            return false;
+        }
+
+        // xorshift64
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+
+        (self.rng as f64 / u64::MAX as f64) < self.drop_rate
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use secp256k1::ecdh::SharedSecret;
+use secp256k1::rand::rngs::OsRng;
+use secp256k1::schnorr::Signature;
+use secp256k1::{Keypair, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::*;
+
+// Frames messages over a stream, optionally encrypting them.
+//
+// The encrypted handshake goes:
+// 1. Both sides send a hello: the network magic and a fresh ephemeral public key.
+// 2. Both sides derive a key for each direction from the ECDH of the ephemeral keys.
+// 3. Both sides send, encrypted, their long-lived identity key and a schnorr signature over the hellos.
+//    This proves who they are, and ties that proof to this one connection so it can't be replayed.
+//
+// After that every frame is a 4 byte length followed by the ChaCha20-Poly1305 sealed message. The length is
+// authenticated as associated data, and the nonce is a per-direction counter.
+
+pub const HELLO_SIZE: usize = 4 + 33;
+
+pub const AUTH_SIZE: usize = 32 + 64;
+
+pub const TAG_SIZE: usize = 16;
+
+// The long-lived key a node is known by. Operators pin peers by its public half.
+#[derive(Debug, Clone)]
+pub struct NodeIdentity {
+    keypair: Keypair,
+}
+
+// Which identities we'll finish a handshake with
+#[derive(Debug, Clone, Default)]
+pub enum PeerPolicy {
+    #[default]
+    Any,
+    Pinned(HashSet<[u8; 32]>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Initiator,
+    Responder,
+}
+
+// One direction of an encrypted session
+struct CipherState {
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+pub struct Session {
+    send: CipherState,
+    recv: CipherState,
+    peer_identity: [u8; 32],
+}
+
+pub enum Transport {
+    Plain,
+    Encrypted(Session),
+}
+
+impl NodeIdentity {
+    pub fn generate() -> Self {
+        NodeIdentity {
+            keypair: Keypair::new(&Secp256k1::new(), &mut OsRng),
+        }
+    }
+
+    pub fn from_secret_bytes(secret: &[u8; 32]) -> Result<Self, Error> {
+        let secret = SecretKey::from_byte_array(secret)
+            .map_err(|_| Error::TransportError("the identity secret key is invalid".into()))?;
+
+        Ok(NodeIdentity {
+            keypair: Keypair::from_secret_key(&Secp256k1::new(), &secret),
+        })
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.keypair.secret_bytes()
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.keypair.x_only_public_key().0.serialize()
+    }
+}
+
+impl PeerPolicy {
+    pub fn allows(&self, identity: &[u8; 32]) -> bool {
+        match self {
+            PeerPolicy::Any => true,
+            PeerPolicy::Pinned(trusted) => trusted.contains(identity),
+        }
+    }
+}
+
+impl CipherState {
+    fn new(key: [u8; 32]) -> Self {
+        CipherState {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            counter: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&self.counter.to_le_bytes());
+        self.counter += 1;
+        nonce
+    }
+}
+
+// What each side signs to prove its identity. The role is mixed in so a peer can't echo our own proof back at us.
+fn auth_message(transcript: &[u8; 32], role: Role) -> [u8; 32] {
+    let mut data = b"gold_2 handshake auth".to_vec();
+    data.push(role as u8);
+    data.extend(transcript);
+    hash(&data)
+}
+
+fn derive_key(shared: &[u8; 32], transcript: &[u8; 32], direction: Role) -> [u8; 32] {
+    let mut data = b"gold_2 session key".to_vec();
+    data.push(direction as u8);
+    data.extend(shared);
+    data.extend(transcript);
+    hash(&data)
+}
+
+impl Session {
+    pub fn peer_identity(&self) -> [u8; 32] {
+        self.peer_identity
+    }
+
+    // Returns the whole frame, length prefix included
+    pub fn seal(&mut self, message: &[u8]) -> Vec<u8> {
+        let len = ((message.len() + TAG_SIZE) as u32).to_le_bytes();
+        let nonce = self.send.next_nonce();
+
+        let sealed = self
+            .send
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: message,
+                    aad: &len,
+                },
+            )
+            .expect("encrypting into a vec can't fail");
+
+        let mut frame = len.to_vec();
+        frame.extend(sealed);
+        frame
+    }
+
+    // Takes a frame's body, without the length prefix
+    pub fn open(&mut self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        let len = (body.len() as u32).to_le_bytes();
+        let nonce = self.recv.next_nonce();
+
+        self.recv
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: body,
+                    aad: &len,
+                },
+            )
+            .map_err(|_| Error::TransportError("a frame failed to authenticate".into()))
+    }
+}
+
+// Runs the whole handshake over `stream`. Fails if the peer is on another network, can't prove its identity,
+// or isn't allowed by `policy`.
+pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    identity: &NodeIdentity,
+    network: Network,
+    role: Role,
+    policy: &PeerPolicy,
+) -> Result<Session, Error> {
+    let secp = Secp256k1::new();
+    let ephemeral = SecretKey::new(&mut OsRng);
+
+    let mut hello = network.magic().to_vec();
+    hello.extend(PublicKey::from_secret_key(&secp, &ephemeral).serialize());
+    stream.write_all(&hello).await?;
+
+    let mut peer_hello = [0; HELLO_SIZE];
+    stream.read_exact(&mut peer_hello).await?;
+
+    if peer_hello[0..4] != network.magic() {
+        transport_error!(format!("the peer is not on {network}"));
+    }
+
+    let peer_ephemeral = PublicKey::from_slice(&peer_hello[4..])
+        .map_err(|_| Error::TransportError("the peer's ephemeral key is invalid".into()))?;
+
+    // Both sides hash the hellos in the same order
+    let (initiator_hello, responder_hello) = match role {
+        Role::Initiator => (&hello[..], &peer_hello[..]),
+        Role::Responder => (&peer_hello[..], &hello[..]),
+    };
+    let transcript = hash(&[initiator_hello, responder_hello].concat());
+
+    let shared = SharedSecret::new(&peer_ephemeral, &ephemeral).secret_bytes();
+    let peer_role = match role {
+        Role::Initiator => Role::Responder,
+        Role::Responder => Role::Initiator,
+    };
+
+    let mut session = Session {
+        send: CipherState::new(derive_key(&shared, &transcript, role)),
+        recv: CipherState::new(derive_key(&shared, &transcript, peer_role)),
+        peer_identity: [0; 32],
+    };
+
+    let signature = secp.sign_schnorr(&auth_message(&transcript, role), &identity.keypair);
+    let mut auth = identity.public_key().to_vec();
+    auth.extend(signature.as_byte_array());
+    stream.write_all(&session.seal(&auth)).await?;
+
+    let peer_auth = read_body(stream, AUTH_SIZE + TAG_SIZE).await?;
+    let peer_auth = session.open(&peer_auth)?;
+
+    if peer_auth.len() != AUTH_SIZE {
+        transport_error!("the peer's auth message was the wrong size");
+    }
+
+    let peer_identity: [u8; 32] = peer_auth[0..32].try_into().unwrap();
+    let peer_key = XOnlyPublicKey::from_byte_array(&peer_identity)
+        .map_err(|_| Error::TransportError("the peer's identity key is invalid".into()))?;
+    let peer_signature = Signature::from_byte_array(peer_auth[32..].try_into().unwrap());
+
+    secp.verify_schnorr(
+        &peer_signature,
+        &auth_message(&transcript, peer_role),
+        &peer_key,
+    )
+    .map_err(|_| Error::TransportError("the peer could not prove its identity".into()))?;
+
+    if !policy.allows(&peer_identity) {
+        transport_error!("the peer's identity is not pinned");
+    }
+
+    session.peer_identity = peer_identity;
+    Ok(session)
+}
+
+impl Transport {
+    pub async fn send<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        message: &[u8],
+    ) -> Result<(), Error> {
+        let frame = match self {
+            Transport::Plain => {
+                let mut frame = (message.len() as u32).to_le_bytes().to_vec();
+                frame.extend(message);
+                frame
+            }
+            Transport::Encrypted(session) => session.seal(message),
+        };
+
+        stream.write_all(&frame).await?;
+        Ok(())
+    }
+
+    // `max_size` should come from the network limits, so a peer can't make us allocate a huge buffer
+    pub async fn recv<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+        max_size: usize,
+    ) -> Result<Vec<u8>, Error> {
+        match self {
+            Transport::Plain => read_body(stream, max_size).await,
+            Transport::Encrypted(session) => {
+                let body = read_body(stream, max_size + TAG_SIZE).await?;
+                session.open(&body)
+            }
+        }
+    }
+
+    pub fn peer_identity(&self) -> Option<[u8; 32]> {
+        match self {
+            Transport::Plain => None,
+            Transport::Encrypted(session) => Some(session.peer_identity()),
+        }
+    }
+}
+
+async fn read_body<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    max_size: usize,
+) -> Result<Vec<u8>, Error> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_le_bytes(len) as usize;
+
+    if len > max_size {
+        peer_limit_error!(format!(
+            "a {len} byte frame is over the {max_size} byte limit"
+        ));
+    }
+
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await?;
+    Ok(body)
+}
@@ -0,0 +1,105 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use crate::*;
+
+// Each network has its own genesis, so blocks from one can never connect to another. Txns and renames are kept apart
+// by signing the network's magic along with them, and p2p messages carry the magic up front.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    // A local network for tests, where blocks are nearly free to mine
+    Regtest,
+}
+
+pub const GENESIS_TIME: u64 = 1_738_368_000;
+
+// Fills the block size history at genesis so that the first blocks still get their free 10kb
+pub const GENESIS_BLOCK_SIZE: usize = 10_000;
+
+impl Network {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+
+    pub fn magic(&self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => *b"GLD2",
+            Network::Testnet => *b"GLDT",
+            Network::Regtest => *b"GLDR",
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Network::Mainnet => 9281,
+            Network::Testnet => 19281,
+            Network::Regtest => 29281,
+        }
+    }
+
+    pub fn initial_difficulty(&self) -> [u8; 32] {
+        let zero_bytes = match self {
+            Network::Mainnet => 3,
+            Network::Testnet => 2,
+            Network::Regtest => 1,
+        };
+
+        let mut difficulty = [255; 32];
+        difficulty[..zero_bytes].fill(0);
+        difficulty
+    }
+
+    // Genesis has no txns. Its merkle root commits to the network name so every network's genesis hash differs.
+    pub fn genesis_header(&self) -> Header {
+        Header {
+            prev_block_hash: [0; 32],
+            merkle_root: hash(self.name().as_bytes()),
+            time: GENESIS_TIME,
+            nonce: 0,
+        }
+    }
+
+    pub fn genesis_hash(&self) -> [u8; 32] {
+        hash_header(&self.genesis_header())
+    }
+
+    // The state every node on this network starts from
+    pub fn genesis_state(&self) -> BlockchainState {
+        BlockchainState {
+            account_set: HashMap::new(),
+            name_set: HashMap::new(),
+            owned_names: HashMap::new(),
+            difficulty: self.initial_difficulty(),
+            height: 0,
+            last_720_times: [GENESIS_TIME; 720],
+            last_100_block_sizes: [GENESIS_BLOCK_SIZE; 100],
+            previous_block_header: self.genesis_header(),
+            network: *self,
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => decode_error!(format!("{s} is not a known network")),
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{watch, Notify};
+
+use crate::api::{unix_millis, ApiError};
+use crate::chain::BlockStatus;
+use crate::context::SharedNode;
+use crate::miner::set_extra_nonce;
+use crate::template::BlockTemplate;
+use crate::*;
+
+// Hands out work to our own mining machines over TCP and keeps count of what each one finds.
+//
+// The protocol is newline delimited JSON, loosely modelled on stratum. A worker sends
+// `{"id": 1, "method": "authorize", "params": {"worker": "rig-1"}}` and gets back a session number, followed by a
+// `job` notification every time the template changes. It then sends
+// `{"id": 2, "method": "submit", "params": {"job_id": 7, "extra_nonce": 8589934592, "nonce": 1234}}` for every header
+// that meets the share target.
+//
+// Each session owns the extra nonces whose top 32 bits are its session number, so no two workers ever hash the same
+// header. Shares are checked against a target much easier than the network's, which lets slow machines show their
+// work regularly. Any share which also meets the network target is submitted to the node as a block.
+
+// Shares for jobs older than this are turned away, even if they're on the current tip
+pub const MAX_JOBS: usize = 8;
+
+// How often the tip is checked for changes
+pub const TIP_POLL_INTERVAL: u64 = 500;
+
+// About one hash in 65,536 makes a share
+pub const DEFAULT_SHARE_DIFFICULTY: [u8; 32] = {
+    let mut difficulty = [255; 32];
+    difficulty[0] = 0;
+    difficulty[1] = 0;
+    difficulty
+};
+
+pub const DEFAULT_JOB_INTERVAL: u64 = 30_000;
+
+// Mining machines are usually elsewhere on the network, so the pool listens on every interface unless told otherwise
+pub const DEFAULT_POOL_ADDR: &str = "0.0.0.0:9282";
+
+// The longest request line a worker can send, newline included. Real requests are a small fraction of this, and a
+// worker which goes over is disconnected rather than buffered without end.
+pub const MAX_LINE_SIZE: usize = 4_096;
+
+// Workers are named by whoever connects, so only this many are kept. A new name pushes out the one which has gone
+// longest without submitting anything.
+pub const MAX_WORKERS: usize = 1_000;
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    // The key every block's coinbase pays
+    pub payout: [u8; 32],
+    pub share_difficulty: [u8; 32],
+    // How often a fresh template is handed out when the tip hasn't changed, in milliseconds
+    pub job_interval: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub id: u64,
+    pub block: Block,
+    pub difficulty: [u8; 32],
+    pub height: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorkerStats {
+    pub accepted: u64,
+    pub rejected: u64,
+    // Only blocks the node connected. One that lost a race to a block from elsewhere isn't counted.
+    pub blocks: u64,
+    // When the worker last submitted anything, in milliseconds
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    pub hash: [u8; 32],
+    // Set when the share met the network target too
+    pub block: Option<Block>,
+}
+
+pub struct Pool {
+    config: PoolConfig,
+    // Oldest first. Every job builds on the same tip.
+    jobs: VecDeque<Job>,
+    next_job_id: u64,
+    next_session: u32,
+    workers: HashMap<String, WorkerStats>,
+    // (job id, extra nonce, nonce) of every share accepted for a live job
+    seen: HashSet<(u64, u64, u64)>,
+}
+
+// The first extra nonce a session owns
+pub fn session_extra_nonce(session: u32) -> u64 {
+    (session as u64) << 32
+}
+
+impl Pool {
+    pub fn new(config: PoolConfig) -> Self {
+        Pool {
+            config,
+            jobs: VecDeque::new(),
+            next_job_id: 0,
+            next_session: 0,
+            workers: HashMap::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    pub fn workers(&self) -> &HashMap<String, WorkerStats> {
+        &self.workers
+    }
+
+    pub fn current_job(&self) -> Option<&Job> {
+        self.jobs.back()
+    }
+
+    pub fn open_session(&mut self) -> u32 {
+        self.next_session = self.next_session.wrapping_add(1);
+        self.next_session
+    }
+
+    // Shares only count if they meet the easier of the two targets, so the pool never asks for more than a block
+    pub fn share_target(&self, job: &Job) -> [u8; 32] {
+        self.config.share_difficulty.max(job.difficulty)
+    }
+
+    // Returns whether workers should drop their old jobs, which is the case when the tip has moved
+    pub fn new_job(
+        &mut self,
+        template: BlockTemplate,
+        difficulty: [u8; 32],
+        height: usize,
+    ) -> bool {
+        let clean = self.current_job().is_none_or(|job| {
+            job.block.header.prev_block_hash != template.block.header.prev_block_hash
+        });
+
+        if clean {
+            self.jobs.clear();
+            self.seen.clear();
+        }
+
+        self.next_job_id += 1;
+        self.jobs.push_back(Job {
+            id: self.next_job_id,
+            block: template.block,
+            difficulty,
+            height,
+        });
+
+        if self.jobs.len() > MAX_JOBS {
+            let dropped = self.jobs.pop_front().unwrap().id;
+            self.seen.retain(|(job_id, _, _)| *job_id != dropped);
+        }
+
+        clean
+    }
+
+    // `now` is in milliseconds
+    pub fn submit(
+        &mut self,
+        worker: &str,
+        session: u32,
+        job_id: u64,
+        extra_nonce: u64,
+        nonce: u64,
+        now: u64,
+    ) -> Result<Share, Error> {
+        let result = self.check_share(session, job_id, extra_nonce, nonce);
+
+        if !self.workers.contains_key(worker) && self.workers.len() >= MAX_WORKERS {
+            let idlest = self
+                .workers
+                .iter()
+                .min_by_key(|(_, stats)| stats.last_seen)
+                .map(|(name, _)| name.clone());
+            if let Some(idlest) = idlest {
+                self.workers.remove(&idlest);
+            }
+        }
+
+        let stats = self.workers.entry(worker.into()).or_default();
+        stats.last_seen = now;
+        match &result {
+            Ok(_) => stats.accepted += 1,
+            Err(_) => stats.rejected += 1,
+        }
+
+        result
+    }
+
+    // Called once a share's block has made it onto the main chain
+    pub fn block_connected(&mut self, worker: &str) {
+        if let Some(stats) = self.workers.get_mut(worker) {
+            stats.blocks += 1;
+        }
+    }
+
+    fn check_share(
+        &mut self,
+        session: u32,
+        job_id: u64,
+        extra_nonce: u64,
+        nonce: u64,
+    ) -> Result<Share, Error> {
+        let Some(job) = self.jobs.iter().find(|job| job.id == job_id) else {
+            share_error!("the job is unknown or stale");
+        };
+
+        if extra_nonce >> 32 != session as u64 {
+            share_error!("the extra nonce belongs to another session");
+        }
+
+        if self.seen.contains(&(job_id, extra_nonce, nonce)) {
+            share_error!("it was already submitted");
+        }
+
+        let mut block = job.block.clone();
+        set_extra_nonce(&mut block, extra_nonce);
+        block.header.nonce = nonce;
+        let hash = hash_header(&block.header);
+
+        if !meets_difficulty(&hash, &self.share_target(job)) {
+            share_error!("the hash does not meet the share target");
+        }
+
+        let block = meets_difficulty(&hash, &job.difficulty).then_some(block);
+        self.seen.insert((job_id, extra_nonce, nonce));
+
+        Ok(Share { hash, block })
+    }
+}
+
+// --- PROTOCOL ---
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolRequest {
+    pub id: u64,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizeParams {
+    pub worker: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitParams {
+    pub job_id: u64,
+    pub extra_nonce: u64,
+    pub nonce: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobParams {
+    pub job_id: u64,
+    pub height: usize,
+    // The encoded block, with the coinbase already holding the session's first extra nonce
+    pub block: String,
+    pub extra_nonce: u64,
+    pub share_target: String,
+    pub target: String,
+    // Work on older jobs is now worthless
+    pub clean: bool,
+}
+
+pub fn job_params(job: &Job, share_target: [u8; 32], session: u32, clean: bool) -> JobParams {
+    let extra_nonce = session_extra_nonce(session);
+    let mut block = job.block.clone();
+    set_extra_nonce(&mut block, extra_nonce);
+
+    JobParams {
+        job_id: job.id,
+        height: job.height,
+        block: hex::encode(encode_block(&block)),
+        extra_nonce,
+        share_target: hex::encode(share_target),
+        target: hex::encode(job.difficulty),
+        clean,
+    }
+}
+
+// Builds jobs for as long as the pool is running. Workers pick them up from the returned channel.
+fn spawn_job_builder(
+    node: SharedNode,
+    pool: Arc<Mutex<Pool>>,
+    refresh: Arc<Notify>,
+) -> watch::Receiver<bool> {
+    let (jobs, receiver) = watch::channel(false);
+
+    tokio::spawn(async move {
+        let mut last_job = 0;
+
+        loop {
+            let now = unix_millis();
+            let tip = node.tip();
+            let clean = {
+                let mut pool = pool.lock().unwrap();
+                let tip_moved = pool
+                    .current_job()
+                    .is_none_or(|job| job.block.header.prev_block_hash != tip.hash);
+
+                if tip_moved || now >= last_job + pool.config().job_interval {
+                    let payout = pool.config().payout;
+                    let (template, difficulty, height) = node.read(|node| {
+                        let state = &node.chain.state;
+                        let template = node.block_template(payout, now / 1_000);
+                        (template, state.difficulty, state.height + 1)
+                    });
+                    last_job = now;
+                    Some(pool.new_job(template, difficulty, height))
+                } else {
+                    None
+                }
+            };
+
+            if let Some(clean) = clean {
+                if jobs.send(clean).is_err() {
+                    return;
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(TIP_POLL_INTERVAL)) => {}
+                _ = refresh.notified() => {}
+            }
+        }
+    });
+
+    receiver
+}
+
+// Accepts workers until the listener fails
+pub async fn serve(
+    listener: TcpListener,
+    node: SharedNode,
+    pool: Arc<Mutex<Pool>>,
+) -> Result<(), Error> {
+    let refresh = Arc::new(Notify::new());
+    let jobs = spawn_job_builder(node.clone(), pool.clone(), refresh.clone());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_worker(
+            stream,
+            node.clone(),
+            pool.clone(),
+            jobs.clone(),
+            refresh.clone(),
+        ));
+    }
+}
+
+struct Worker {
+    name: String,
+    session: u32,
+}
+
+async fn handle_worker(
+    stream: TcpStream,
+    node: SharedNode,
+    pool: Arc<Mutex<Pool>>,
+    mut jobs: watch::Receiver<bool>,
+    refresh: Arc<Notify>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = vec![];
+    let mut worker: Option<Worker> = None;
+
+    loop {
+        let mut outgoing = vec![];
+
+        tokio::select! {
+            request = next_line(&mut reader, &mut line) => {
+                let Some(request) = request else { return };
+                let authorized = worker.is_some();
+
+                outgoing.push(handle_request(&request, &mut worker, &node, &pool, &refresh));
+
+                // Newly authorized workers get the current job straight away
+                if let (false, Some(worker)) = (authorized, &worker) {
+                    jobs.borrow_and_update();
+                    outgoing.extend(job_notification(&pool, worker.session, true));
+                }
+            }
+            changed = jobs.changed(), if worker.is_some() => {
+                if changed.is_err() {
+                    return;
+                }
+                let clean = *jobs.borrow_and_update();
+                outgoing.extend(job_notification(&pool, worker.as_ref().unwrap().session, clean));
+            }
+        }
+
+        for message in outgoing {
+            if writer
+                .write_all(format!("{message}\n").as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+// Reads the next line, without its newline. A partly read line stays in `buf` if the future is dropped, so this can be
+// raced in select! without losing anything. None once the worker hangs up, sends something that isn't UTF-8, or
+// goes over MAX_LINE_SIZE.
+async fn next_line(reader: &mut BufReader<OwnedReadHalf>, buf: &mut Vec<u8>) -> Option<String> {
+    while buf.last() != Some(&b'\n') {
+        if buf.len() >= MAX_LINE_SIZE {
+            return None;
+        }
+
+        let limit = (MAX_LINE_SIZE - buf.len()) as u64;
+        if (&mut *reader)
+            .take(limit)
+            .read_until(b'\n', buf)
+            .await
+            .ok()?
+            == 0
+        {
+            return None;
+        }
+    }
+
+    let line = String::from_utf8(std::mem::take(buf)).ok()?;
+    Some(line.trim_end_matches(['\n', '\r']).into())
+}
+
+fn job_notification(pool: &Mutex<Pool>, session: u32, clean: bool) -> Option<Value> {
+    let pool = pool.lock().unwrap();
+    let job = pool.current_job()?;
+    let params = job_params(job, pool.share_target(job), session, clean);
+    Some(json!({ "method": "job", "params": params }))
+}
+
+fn handle_request(
+    line: &str,
+    worker: &mut Option<Worker>,
+    node: &SharedNode,
+    pool: &Mutex<Pool>,
+    refresh: &Notify,
+) -> Value {
+    let request: PoolRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = ApiError::from(Error::DecodeError(e.to_string()));
+            return json!({ "id": null, "result": null, "error": error });
+        }
+    };
+
+    match run_request(
+        request.method.as_str(),
+        request.params,
+        worker,
+        node,
+        pool,
+        refresh,
+    ) {
+        Ok(result) => json!({ "id": request.id, "result": result, "error": null }),
+        Err(error) => json!({ "id": request.id, "result": null, "error": error }),
+    }
+}
+
+fn run_request(
+    method: &str,
+    params: Value,
+    worker: &mut Option<Worker>,
+    node: &SharedNode,
+    pool: &Mutex<Pool>,
+    refresh: &Notify,
+) -> Result<Value, ApiError> {
+    let parse_error = |e: serde_json::Error| ApiError::from(Error::DecodeError(e.to_string()));
+
+    match method {
+        "authorize" => {
+            let params: AuthorizeParams = serde_json::from_value(params).map_err(parse_error)?;
+            let session = pool.lock().unwrap().open_session();
+            *worker = Some(Worker {
+                name: params.worker,
+                session,
+            });
+            Ok(json!({ "session": session }))
+        }
+        "submit" => {
+            let Some(worker) = worker else {
+                return Err(ApiError::bad_request("the worker has not authorized"));
+            };
+            let params: SubmitParams = serde_json::from_value(params).map_err(parse_error)?;
+
+            let share = pool.lock().unwrap().submit(
+                &worker.name,
+                worker.session,
+                params.job_id,
+                params.extra_nonce,
+                params.nonce,
+                unix_millis(),
+            )?;
+
+            // The share counts either way. The block might still lose a race with one from elsewhere.
+            let block = match share.block {
+                Some(block) => {
+                    // A block that only made a side chain lost the race, and one we already had isn't ours
+                    let announcement =
+                        node.write(|node| match node.add_block(block, unix_millis()) {
+                            Ok(BlockStatus::Connected { .. }) => node.announce_tip(),
+                            _ => None,
+                        });
+                    let accepted = announcement.is_some();
+                    if let Some(message) = announcement {
+                        node.relay(message);
+                        pool.lock().unwrap().block_connected(&worker.name);
+                    }
+                    refresh.notify_one();
+                    accepted.then(|| hex::encode(share.hash))
+                }
+                None => None,
+            };
+
+            Ok(json!({ "hash": hex::encode(share.hash), "block": block }))
+        }
+        method => Err(ApiError::bad_request(format!(
+            "{method} is not a pool method"
+        ))),
+    }
+}
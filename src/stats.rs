@@ -0,0 +1,89 @@
+use crate::chain::Chain;
+use crate::*;
+
+// Estimates of how much work is going into the chain.
+//
+// A header meets a target `t` with probability (t + 1) / 2^256, so a block at that target takes 2^256 / (t + 1) hashes
+// on average. Dividing by how far apart recent blocks actually were gives the network's hashrate.
+//
+// Difficulty doesn't adjust yet (see the TODO on push_block). When it does it'll be every RETARGET_INTERVAL blocks,
+// over the window last_720_times already keeps, and the retarget figures here are reported against that schedule.
+
+pub const RETARGET_INTERVAL: usize = 720;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainStats {
+    pub height: usize,
+    pub difficulty: [u8; 32],
+    pub expected_hashes: f64,
+    // In hashes per second. None until there are two blocks with different times to measure between.
+    pub hashrate: Option<f64>,
+    // In seconds
+    pub average_interval: Option<f64>,
+    pub next_retarget_height: usize,
+    pub seconds_to_retarget: Option<f64>,
+}
+
+// The target as a number, which loses precision past 53 bits but is plenty for an estimate
+pub fn target_value(difficulty: &[u8; 32]) -> f64 {
+    difficulty
+        .iter()
+        .fold(0.0, |value, byte| value * 256.0 + *byte as f64)
+}
+
+pub fn expected_hashes(difficulty: &[u8; 32]) -> f64 {
+    2f64.powi(256) / (target_value(difficulty) + 1.0)
+}
+
+// The mean spacing of the blocks recorded in `times`, which is ordered oldest first. At heights below the window
+// size the front of the window is genesis filler, so only the last `height` gaps are real.
+pub fn average_block_interval(times: &[u64; 720], height: usize) -> Option<f64> {
+    let gaps = height.min(times.len() - 1);
+    if gaps == 0 {
+        return None;
+    }
+
+    let newest = times[times.len() - 1];
+    let oldest = times[times.len() - 1 - gaps];
+    Some(newest.saturating_sub(oldest) as f64 / gaps as f64)
+}
+
+pub fn estimate_hashrate(blockchain_state: &BlockchainState) -> Option<f64> {
+    let interval =
+        average_block_interval(&blockchain_state.last_720_times, blockchain_state.height)?;
+
+    if interval == 0.0 {
+        return None;
+    }
+
+    Some(expected_hashes(&blockchain_state.difficulty) / interval)
+}
+
+// The first height after `height` at which difficulty is recalculated
+pub fn next_retarget_height(height: usize) -> usize {
+    (height / RETARGET_INTERVAL + 1) * RETARGET_INTERVAL
+}
+
+pub fn chain_stats(blockchain_state: &BlockchainState) -> ChainStats {
+    let height = blockchain_state.height;
+    let average_interval = average_block_interval(&blockchain_state.last_720_times, height);
+    let next_retarget_height = next_retarget_height(height);
+
+    ChainStats {
+        height,
+        difficulty: blockchain_state.difficulty,
+        expected_hashes: expected_hashes(&blockchain_state.difficulty),
+        hashrate: estimate_hashrate(blockchain_state),
+        average_interval,
+        next_retarget_height,
+        seconds_to_retarget: average_interval
+            .map(|interval| interval * (next_retarget_height - height) as f64),
+    }
+}
+
+// (height, difficulty) for up to `count` main branch blocks starting at `from`
+pub fn difficulty_history(chain: &Chain, from: usize, count: usize) -> Vec<(usize, [u8; 32])> {
+    (from..from.saturating_add(count))
+        .map_while(|height| Some((height, chain.difficulty_at(height)?)))
+        .collect()
+}
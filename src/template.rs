@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use crate::mempool::{Mempool, MempoolEntry, RenamePool};
+use crate::*;
+
+// Picks what goes in the next block.
+//
+// A miner earns the coinbase plus every txn fee. Rename fees are burned, so renames only ever cost the miner space.
+// Past median + FREE_BLOCK_SPACE the coinbase shrinks with the square of the overshoot, so a txn is only worth
+// including while its fee makes up for the coinbase it costs. Txns are tried best fee rate first and the builder keeps
+// whichever run of them earns the most. Renames then go in wherever they don't cost any coinbase.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTemplate {
+    // Ready to mine, apart from the nonce
+    pub block: Block,
+    pub size: usize,
+    // The coinbase before fees
+    pub reward: u64,
+    pub fees: u64,
+}
+
+pub fn coinbase_txn(miner: [u8; 32], amount: u64) -> Txn {
+    Txn {
+        sender: Address::Key([0; 32]),
+        recievers: vec![(Address::Key(miner), amount)],
+        signature: [0; 64],
+        fee: 0,
+    }
+}
+
+// `time` is used as the block's time unless it's before the tip's
+pub fn build_template(
+    blockchain_state: &BlockchainState,
+    mempool: &Mempool,
+    renames: &RenamePool,
+    miner: [u8; 32],
+    time: u64,
+) -> BlockTemplate {
+    let previous = &blockchain_state.previous_block_header;
+    let median = median_block_size(&blockchain_state.last_100_block_sizes);
+    let max_size = max_block_size(median);
+
+    let mut block = Block {
+        header: Header {
+            prev_block_hash: hash_header(previous),
+            merkle_root: [0; 32],
+            time: time.max(previous.time),
+            nonce: 0,
+        },
+        txns: vec![coinbase_txn(miner, 0)],
+        name_changes: vec![],
+    };
+
+    let base_size = block_size(&block);
+    let txns = pick_txns(mempool, blockchain_state, base_size, max_size, median);
+
+    let mut size = base_size + txns.iter().map(|e| e.size).sum::<usize>();
+    let fees: u64 = txns.iter().map(|e| e.txn.fee).sum();
+
+    // What each account has spent so far in the block, so renames don't charge a payer more than they have left
+    let mut spent: HashMap<[u8; 32], u64> = HashMap::new();
+    for entry in txns.iter() {
+        *spent.entry(entry.sender).or_insert(0) += entry.spend;
+    }
+    block.txns.extend(txns.into_iter().map(|e| e.txn.clone()));
+
+    for entry in renames.by_fee_rate() {
+        let op_size = encode_name_change(&entry.op).len();
+        let balance = blockchain_state
+            .account_set
+            .get(&entry.op.pk)
+            .copied()
+            .unwrap_or(0);
+        let payer_spent = spent.entry(entry.op.pk).or_insert(0);
+
+        if size + op_size > max_size
+            || calc_coinbase(size + op_size, median) < calc_coinbase(size, median)
+            || balance - *payer_spent < entry.op.fee
+        {
+            continue;
+        }
+
+        *payer_spent += entry.op.fee;
+        size += op_size;
+        block.name_changes.push(entry.op.clone());
+    }
+
+    let reward = calc_coinbase(size, median);
+    block.txns[0].recievers[0].1 = reward + fees;
+    block.header.merkle_root = merkle_root(&block.txns, &block.name_changes);
+
+    BlockTemplate {
+        block,
+        size,
+        reward,
+        fees,
+    }
+}
+
+// Walks the mempool best fee rate first, skipping anything that won't fit or that the sender can't cover, and keeps
+// the run which earns the most. On a tie the longer run wins.
+fn pick_txns<'a>(
+    mempool: &'a Mempool,
+    blockchain_state: &BlockchainState,
+    base_size: usize,
+    max_size: usize,
+    median: usize,
+) -> Vec<&'a MempoolEntry> {
+    let mut picked = vec![];
+    let mut spent: HashMap<[u8; 32], u64> = HashMap::new();
+    let mut size = base_size;
+    let mut fees = 0;
+
+    let mut best_revenue = calc_coinbase(size, median);
+    let mut best_len = 0;
+
+    for entry in mempool.by_fee_rate() {
+        let balance = blockchain_state
+            .account_set
+            .get(&entry.sender)
+            .copied()
+            .unwrap_or(0);
+        let sender_spent = spent.entry(entry.sender).or_insert(0);
+
+        if size + entry.size > max_size || *sender_spent + entry.spend > balance {
+            continue;
+        }
+
+        *sender_spent += entry.spend;
+        size += entry.size;
+        fees += entry.txn.fee;
+        picked.push(entry);
+
+        let revenue = calc_coinbase(size, median) + fees;
+        if revenue >= best_revenue {
+            best_revenue = revenue;
+            best_len = picked.len();
+        }
+    }
+
+    picked.truncate(best_len);
+    picked
+}
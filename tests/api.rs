@@ -0,0 +1,810 @@
+mod common;
+
+#[cfg(test)]
+mod api {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use gold_2::api::blocks::*;
+    use gold_2::api::json::*;
+    use gold_2::api::mining::*;
+    use gold_2::api::rpc::*;
+    use gold_2::api::*;
+    use gold_2::chain::{BlockStatus, Chain};
+    use gold_2::context::*;
+    use gold_2::net::node::Node;
+    use gold_2::net::Message;
+    use gold_2::*;
+    use http_body_util::BodyExt;
+    use secp256k1::Keypair;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use crate::common::*;
+
+    fn node_for(keys: &[&Keypair]) -> SharedNode {
+        let mut state = funded_state(keys, 1_000_000_000_000_000);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        NodeContext::new(Node::new(Chain::new(state)))
+    }
+
+    async fn call(
+        node: &SharedNode,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map_or(Body::empty(), |b| Body::from(b.to_string())))
+            .unwrap();
+
+        let response = router(node.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    async fn template(node: &SharedNode, miner: [u8; 32]) -> TemplateResponse {
+        let uri = format!("/mining/template?miner={}", hex::encode(miner));
+        let (status, body) = call(node, "GET", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn solve(template: &TemplateResponse) -> Block {
+        let data = hex::decode(&template.block).unwrap();
+        let mut block = decode_block(&mut data.as_slice()).unwrap();
+        let target: [u8; 32] = hex::decode(&template.target).unwrap().try_into().unwrap();
+
+        while !meets_difficulty(&hash_header(&block.header), &target) {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    async fn submit(node: &SharedNode, block: &Block) -> (StatusCode, Value) {
+        let body = json!({ "block": hex::encode(encode_block(block)) });
+        call(node, "POST", "/mining/submit", Some(body)).await
+    }
+
+    #[tokio::test]
+    async fn solved_templates_are_accepted() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let node = node_for(&[&a]);
+        let txn = signed_txn(&a, pk(&b), 1_000);
+        node.write(|node| node.submit_txn(txn.clone(), 0)).unwrap();
+
+        let template = template(&node, pk(&b)).await;
+
+        assert_eq!(template.height, 1);
+        assert_eq!(template.txns.len(), 1);
+        assert_eq!(template.txns[0].hash, hex::encode(txn_hash(&txn)));
+        assert_eq!(template.coinbase_value, DEFAULT_COINBASE + txn.fee);
+        assert_eq!(template.fees, txn.fee);
+
+        let block = solve(&template);
+        let mut relayed = node.subscribe_relay();
+        let (status, body) = submit(&node, &block).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let response: SubmitBlockResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(response.status, "connected");
+        assert_eq!(response.hash, hex::encode(hash_header(&block.header)));
+        assert!(matches!(
+            relayed.try_recv(),
+            Ok(Message::CompactBlock(compact)) if compact.header == block.header
+        ));
+
+        // Only a new tip is announced
+        submit(&node, &block).await;
+        assert!(relayed.try_recv().is_err());
+
+        assert!(node.read(|node| node.mempool.is_empty()));
+        let tip = node.tip();
+        assert_eq!(tip.state.height, 1);
+        assert_eq!(
+            tip.state.account_set[&pk(&b)],
+            DEFAULT_COINBASE + txn.fee + 1_000
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_miner_keys_are_rejected() {
+        let node = node_for(&[]);
+
+        let (status, body) = call(&node, "GET", "/mining/template?miner=abcd", None).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "bad_request");
+
+        // The right length, but not a point on the curve
+        let uri = format!("/mining/template?miner={}", hex::encode([0xff; 32]));
+        let (status, body) = call(&node, "GET", &uri, None).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "bad_request");
+    }
+
+    #[tokio::test]
+    async fn invalid_blocks_come_back_with_the_reason() {
+        let node = node_for(&[]);
+        let mut template = template(&node, pk(&new_keypair())).await;
+
+        // Claim more than the coinbase is worth, then fix up the merkle root so only the amount is wrong
+        let data = hex::decode(&template.block).unwrap();
+        let mut block = decode_block(&mut data.as_slice()).unwrap();
+        block.txns[0].recievers[0].1 += 1;
+        block.header.merkle_root = merkle_root(&block.txns, &block.name_changes);
+        template.block = hex::encode(encode_block(&block));
+
+        let (status, body) = submit(&node, &solve(&template)).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "txn_validation");
+        assert!(body["message"].as_str().unwrap().contains("Coinbase"));
+        assert_eq!(node.tip().state.height, 0);
+
+        // A coinbase paying nobody at all
+        block.txns[0].recievers.clear();
+        block.header.merkle_root = merkle_root(&block.txns, &block.name_changes);
+        template.block = hex::encode(encode_block(&block));
+
+        let (status, body) = submit(&node, &solve(&template)).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "block_validation");
+        assert_eq!(node.tip().state.height, 0);
+    }
+
+    #[tokio::test]
+    async fn undecodable_and_orphan_blocks_are_rejected() {
+        let node = node_for(&[]);
+        let template = template(&node, pk(&new_keypair())).await;
+
+        let truncated = &template.block[..template.block.len() - 2];
+        let (status, body) = call(
+            &node,
+            "POST",
+            "/mining/submit",
+            Some(json!({ "block": truncated })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "decode");
+
+        let (status, body) = call(&node, "POST", "/mining/submit", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "bad_request");
+
+        let mut block = solve(&template);
+        block.header.prev_block_hash = [7; 32];
+        while !meets_difficulty(&hash_header(&block.header), &TRIVIAL_DIFFICULTY) {
+            block.header.nonce += 1;
+        }
+
+        let (status, body) = submit(&node, &block).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["kind"], "unknown_parent");
+    }
+
+    #[tokio::test]
+    async fn stats_follow_the_chain() {
+        let node = node_for(&[]);
+        let miner = pk(&new_keypair());
+
+        for _ in 0..2 {
+            let block = solve(&template(&node, miner).await);
+            assert_eq!(submit(&node, &block).await.0, StatusCode::OK);
+        }
+
+        let (status, body) = call(&node, "GET", "/stats/mining", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["height"], 2);
+        assert_eq!(body["difficulty"], hex::encode(TRIVIAL_DIFFICULTY));
+        assert!(body["average_interval"].as_f64().unwrap() > 0.0);
+
+        let (status, body) = call(&node, "GET", "/stats/difficulty?from=1&count=5", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let heights: Vec<u64> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["height"].as_u64().unwrap())
+            .collect();
+        assert_eq!(heights, vec![1, 2]);
+
+        let (status, _) = call(&node, "GET", "/stats/difficulty?count=5000", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn accounts_show_balances_names_and_pending_amounts() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let mut state = funded_state(&[&a], 1_000_000_000_000);
+        set_name_owner(
+            &mut state.name_set,
+            &mut state.owned_names,
+            "alice",
+            Some(pk(&a)),
+        );
+        let node = NodeContext::new(Node::new(Chain::new(state)));
+
+        // Payments to a name count towards whoever owns it
+        let mut to_name = Txn {
+            sender: Address::Key(pk(&a)),
+            recievers: vec![(Address::Name("alice".into()), 7)],
+            signature: [0; 64],
+            fee: 0,
+        };
+        finalize_txn(&mut to_name, &a, Network::Regtest);
+        let to_b = signed_txn(&a, pk(&b), 1_000);
+        node.write(|node| node.submit_txn(to_b.clone(), 0)).unwrap();
+        node.write(|node| node.submit_txn(to_name.clone(), 0))
+            .unwrap();
+
+        let (status, body) = call(
+            &node,
+            "GET",
+            &format!("/accounts/{}", hex::encode(pk(&a))),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["balance"], 1_000_000_000_000u64);
+        assert_eq!(body["names"], json!(["alice"]));
+        assert_eq!(
+            body["pending_outgoing"],
+            txn_total_spend(&to_b).unwrap() + txn_total_spend(&to_name).unwrap()
+        );
+        assert_eq!(body["pending_incoming"], 7);
+        assert_eq!(body["height"], 0);
+
+        // b has nothing confirmed, but is owed the pending payment
+        let (status, body) = call(
+            &node,
+            "GET",
+            &format!("/accounts/{}", hex::encode(pk(&b))),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["balance"], 0);
+        assert_eq!(body["pending_incoming"], 1_000);
+    }
+
+    #[tokio::test]
+    async fn unknown_and_malformed_accounts_are_errors() {
+        let node = node_for(&[]);
+
+        let unknown = format!("/accounts/{}", hex::encode(pk(&new_keypair())));
+        let (status, body) = call(&node, "GET", &unknown, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["kind"], "not_found");
+
+        for bad in ["zz", "abcd", &hex::encode([255; 32])] {
+            let (status, body) = call(&node, "GET", &format!("/accounts/{bad}"), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["kind"], "bad_request");
+        }
+    }
+
+    #[tokio::test]
+    async fn names_resolve_in_both_directions() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let mut state = funded_state(&[&a], 1_000_000_000_000);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        let block = mined_block(
+            &state,
+            pk(&b),
+            vec![],
+            vec![signed_rename(&a, &a, "gold"), signed_rename(&a, &a, "ag")],
+        );
+        let node = NodeContext::new(Node::new(Chain::new(state)));
+        node.write(|node| node.add_block(block, 0)).unwrap();
+
+        let (status, body) = call(&node, "GET", "/names/gold", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["owner"], hex::encode(pk(&a)));
+        assert_eq!(body["height"], 1);
+
+        let uri = format!("/accounts/{}/names", hex::encode(pk(&a)));
+        let (status, body) = call(&node, "GET", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["names"], json!(["ag", "gold"]));
+
+        let uri = format!("/accounts/{}/names", hex::encode(pk(&b)));
+        let (_, body) = call(&node, "GET", &uri, None).await;
+        assert_eq!(body["names"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn unowned_and_oversized_names_are_errors() {
+        let node = node_for(&[]);
+
+        let (status, body) = call(&node, "GET", "/names/nobody", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["kind"], "not_found");
+
+        let long = "x".repeat(MAX_NAME_SIZE + 1);
+        let (status, _) = call(&node, "GET", &format!("/names/{long}"), None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = call(&node, "GET", "/accounts/1234/names", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn txns_can_be_submitted_as_json_or_hex() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let node = node_for(&[&a]);
+        let mut relayed = node.subscribe_relay();
+
+        let first = signed_txn(&a, pk(&b), 1_000);
+        let second = signed_txn(&a, pk(&b), 2_000);
+
+        let (status, body) = call(&node, "POST", "/txns", Some(json!(TxnJson::from(&first)))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["hash"], hex::encode(txn_hash(&first)));
+        assert!(matches!(relayed.try_recv(), Ok(Message::Txn(txn)) if txn == first));
+
+        let hex_body = json!({ "hex": hex::encode(encode_txn(&second)) });
+        let (status, _) = call(&node, "POST", "/txns", Some(hex_body.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(matches!(relayed.try_recv(), Ok(Message::Txn(txn)) if txn == second));
+        assert_eq!(node.read(|node| node.mempool.len()), 2);
+
+        // Nothing is relayed twice
+        let (status, body) = call(&node, "POST", "/txns", Some(hex_body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "mempool");
+        assert!(relayed.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn rejected_txns_say_why() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let node = node_for(&[&a]);
+
+        let mut forged = signed_txn(&a, pk(&b), 1_000);
+        forged.recievers[0].1 += 1;
+        let (status, body) =
+            call(&node, "POST", "/txns", Some(json!(TxnJson::from(&forged)))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "txn_validation");
+
+        let truncated = hex::encode(&encode_txn(&signed_txn(&a, pk(&b), 5))[..20]);
+        let (_, body) = call(&node, "POST", "/txns", Some(json!({ "hex": truncated }))).await;
+        assert_eq!(body["kind"], "decode");
+
+        for bad in [json!({ "hex": "zz" }), json!({ "sender": 1 }), json!([])] {
+            let (status, body) = call(&node, "POST", "/txns", Some(bad)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["kind"], "bad_request");
+        }
+
+        assert!(node.read(|node| node.mempool.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn renames_can_be_submitted_as_json_or_hex() {
+        let (a, poor) = (new_keypair(), new_keypair());
+        let node = node_for(&[&a]);
+        let mut relayed = node.subscribe_relay();
+
+        let op = signed_rename(&a, &a, "gold");
+        let (status, body) = call(
+            &node,
+            "POST",
+            "/renames",
+            Some(json!(RenameJson::from(&op))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["hash"], hex::encode(name_change_hash(&op)));
+        assert!(matches!(relayed.try_recv(), Ok(Message::Rename(r)) if r == op));
+        assert!(node.read(|node| node.renames.get("gold").is_some()));
+
+        // Nothing to pay the fee with
+        let op = signed_rename(&poor, &poor, "silver");
+        let hex_body = json!({ "hex": hex::encode(encode_name_change(&op)) });
+        let (status, body) = call(&node, "POST", "/renames", Some(hex_body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "txn_validation");
+        assert!(relayed.try_recv().is_err());
+    }
+
+    async fn mine(node: &SharedNode, miner: [u8; 32]) -> Block {
+        let block = solve(&template(node, miner).await);
+        assert_eq!(submit(node, &block).await.0, StatusCode::OK);
+        block
+    }
+
+    #[tokio::test]
+    async fn blocks_are_served_by_hash_and_height() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let node = node_for(&[&a]);
+        let txn = signed_txn(&a, pk(&b), 1_000);
+        let op = signed_rename(&a, &a, "gold");
+        node.write(|node| node.submit_txn(txn.clone(), 0)).unwrap();
+        node.write(|node| node.submit_rename(op.clone(), 0))
+            .unwrap();
+
+        let block = mine(&node, pk(&b)).await;
+        let hash = hex::encode(hash_header(&block.header));
+
+        let (status, body) = call(&node, "GET", &format!("/blocks/{hash}"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let response: BlockResponse = serde_json::from_value(body.clone()).unwrap();
+        assert_eq!(response.header.hash, hash);
+        assert_eq!(response.header.height, 1);
+        assert!(response.header.main_chain);
+        assert_eq!(response.header.target, hex::encode(TRIVIAL_DIFFICULTY));
+        assert_eq!(response.size, block_size(&block));
+        assert_eq!(response.coinbase_value, DEFAULT_COINBASE + txn.fee);
+        assert_eq!(response.fees, txn.fee);
+        assert_eq!(response.txns[0].hash, hex::encode(txn_hash(&txn)));
+        assert_eq!(response.txns[0].size, encode_txn(&txn).len());
+        assert_eq!(response.txns[0].item, TxnJson::from(&txn));
+        assert_eq!(response.name_changes[0].item, RenameJson::from(&op));
+        assert_eq!(body["txns"][0]["fee"], txn.fee);
+
+        let (_, by_height) = call(&node, "GET", "/blocks/height/1", None).await;
+        assert_eq!(by_height, body);
+
+        let (status, body) = call(&node, "GET", "/blocks/height/0", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["hash"], hex::encode(block.header.prev_block_hash));
+        assert!(body["coinbase"].is_null());
+
+        for (uri, status) in [
+            ("/blocks/height/2".to_string(), StatusCode::NOT_FOUND),
+            (
+                format!("/blocks/{}", hex::encode([7; 32])),
+                StatusCode::NOT_FOUND,
+            ),
+            ("/blocks/height/tip".to_string(), StatusCode::BAD_REQUEST),
+            ("/blocks/abcd".to_string(), StatusCode::BAD_REQUEST),
+        ] {
+            assert_eq!(call(&node, "GET", &uri, None).await.0, status, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_side_chain_blocks_are_still_served() {
+        let node = node_for(&[]);
+        mine(&node, pk(&new_keypair())).await;
+        mine(&node, pk(&new_keypair())).await;
+        let genesis = node.read(|node| node.chain.genesis_hash());
+
+        // Side chain blocks are only stored, so neither of these is ever checked
+        let side_block = |txns: Vec<Txn>| {
+            let mut block = Block {
+                header: Header {
+                    prev_block_hash: genesis,
+                    merkle_root: [0; 32],
+                    time: u64::MAX,
+                    nonce: 0,
+                },
+                txns,
+                name_changes: vec![],
+            };
+            block.header.merkle_root = merkle_root(&block.txns, &block.name_changes);
+            while !meets_difficulty(&hash_header(&block.header), &TRIVIAL_DIFFICULTY) {
+                block.header.nonce += 1;
+            }
+            block
+        };
+        let overpaying = Txn {
+            sender: Address::Key([0; 32]),
+            recievers: vec![
+                (Address::Key([1; 32]), u64::MAX),
+                (Address::Key([2; 32]), 2),
+            ],
+            signature: [0; 64],
+            fee: u64::MAX,
+        };
+
+        for (block, coinbase_value, fees) in [
+            (side_block(vec![]), 0, 0),
+            (
+                side_block(vec![overpaying.clone(), overpaying.clone(), overpaying]),
+                u64::MAX,
+                u64::MAX,
+            ),
+        ] {
+            let status = node.write(|node| node.add_block(block.clone(), 0)).unwrap();
+            assert!(matches!(status, BlockStatus::SideChain));
+
+            let hash = hex::encode(hash_header(&block.header));
+            let (status, body) = call(&node, "GET", &format!("/blocks/{hash}"), None).await;
+            assert_eq!(status, StatusCode::OK);
+            let response: BlockResponse = serde_json::from_value(body).unwrap();
+            assert!(!response.header.main_chain);
+            assert_eq!(response.coinbase.is_some(), !block.txns.is_empty());
+            assert_eq!(response.coinbase_value, coinbase_value);
+            assert_eq!(response.fees, fees);
+        }
+    }
+
+    #[tokio::test]
+    async fn headers_follow_the_main_branch() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let node = node_for(&[]);
+
+        // Two blocks on genesis, the second of which loses
+        let first = solve(&template(&node, pk(&a)).await);
+        let second = solve(&template(&node, pk(&b)).await);
+        submit(&node, &first).await;
+        assert_eq!(submit(&node, &second).await.1["status"], "side_chain");
+        mine(&node, pk(&a)).await;
+        let tip = mine(&node, pk(&a)).await;
+
+        let (_, body) = call(&node, "GET", "/tip", None).await;
+        let response: HeaderResponse = serde_json::from_value(body).unwrap();
+        assert_eq!(response.hash, hex::encode(node.tip().hash));
+        assert_eq!(response.height, 3);
+        assert_eq!(response.header, HeaderJson::from(&tip.header));
+
+        let (_, body) = call(&node, "GET", "/headers", None).await;
+        let headers: Vec<HeaderResponse> = serde_json::from_value(body).unwrap();
+        assert_eq!(headers.len(), 4);
+        for (height, pair) in headers.windows(2).enumerate() {
+            assert_eq!(pair[0].height, height);
+            assert_eq!(pair[1].header.prev_block_hash, pair[0].hash);
+        }
+
+        let (_, body) = call(&node, "GET", "/headers?from=1&count=1", None).await;
+        assert_eq!(body[0]["hash"], hex::encode(hash_header(&first.header)));
+        assert_eq!(body.as_array().unwrap().len(), 1);
+
+        let uri = format!("/blocks/{}", hex::encode(hash_header(&second.header)));
+        let (_, body) = call(&node, "GET", &uri, None).await;
+        assert_eq!(body["height"], 1);
+        assert_eq!(body["main_chain"], false);
+
+        let uri = format!("/headers?count={}", MAX_HEADERS + 1);
+        assert_eq!(
+            call(&node, "GET", &uri, None).await.0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    // Reads `count` server-sent events off the body, as (name, data) pairs
+    async fn read_events(body: &mut Body, count: usize) -> Vec<(String, Value)> {
+        let mut text = String::new();
+        while text.matches("\n\n").count() < count {
+            let frame = tokio::time::timeout(Duration::from_secs(5), body.frame())
+                .await
+                .expect("timed out waiting for events")
+                .unwrap()
+                .unwrap();
+            text.push_str(std::str::from_utf8(frame.data_ref().unwrap()).unwrap());
+        }
+
+        text.split_terminator("\n\n")
+            .map(|event| {
+                let field = |name: &str| {
+                    event
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .unwrap()
+                        .to_string()
+                };
+                let data = serde_json::from_str(&field("data: ")).unwrap();
+                (field("event: "), data)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn events_stream_filtered_by_name() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let node = node_for(&[&a, &b]);
+
+        let request = Request::get("/events?names=gold")
+            .body(Body::empty())
+            .unwrap();
+        let response = router(node.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body();
+
+        let op = signed_rename(&a, &a, "gold");
+        // Neither touches gold
+        node.write(|node| node.submit_txn(signed_txn(&b, pk(&a), 5), 0))
+            .unwrap();
+        node.write(|node| node.submit_rename(signed_rename(&b, &b, "silver"), 0))
+            .unwrap();
+        node.write(|node| node.submit_rename(op.clone(), 0))
+            .unwrap();
+        let block = mine(&node, pk(&b)).await;
+
+        let events = read_events(&mut body, 3).await;
+        let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["rename", "block_connected", "name_owner"]);
+
+        assert_eq!(events[0].1["hash"], hex::encode(name_change_hash(&op)));
+        assert_eq!(events[1].1["hash"], hex::encode(hash_header(&block.header)));
+        assert_eq!(events[1].1["height"], 1);
+        assert_eq!(events[1].1["txns"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            events[2].1,
+            json!({ "name": "gold", "old_owner": null, "new_owner": hex::encode(pk(&a)) })
+        );
+    }
+
+    #[tokio::test]
+    async fn event_filters_must_be_valid_keys() {
+        let node = node_for(&[]);
+        let (status, body) = call(&node, "GET", "/events?keys=abcd", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["kind"], "bad_request");
+    }
+
+    async fn rpc(node: &SharedNode, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": 1 });
+        let (status, body) = call(node, "POST", "/rpc", Some(request)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], 1);
+        body
+    }
+
+    #[tokio::test]
+    async fn rpc_methods_answer_like_rest() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let node = node_for(&[&a]);
+
+        let info = rpc(&node, "get_chain_info", json!([])).await["result"].clone();
+        assert_eq!(info["network"], "regtest");
+        assert_eq!(info["height"], 0);
+        assert_eq!(info["tip"], hex::encode(node.tip().hash));
+
+        let txn = signed_txn(&a, pk(&b), 1_000);
+        let hex_txn = hex::encode(encode_txn(&txn));
+        let body = rpc(&node, "submit_txn", json!([hex_txn])).await;
+        assert_eq!(body["result"]["hash"], hex::encode(txn_hash(&txn)));
+        let op = signed_rename(&a, &a, "gold");
+        let body = rpc(&node, "submit_rename", json!(RenameJson::from(&op))).await;
+        assert_eq!(body["result"]["hash"], hex::encode(name_change_hash(&op)));
+
+        let body = rpc(
+            &node,
+            "get_template",
+            json!({ "miner": hex::encode(pk(&b)) }),
+        )
+        .await;
+        let template: TemplateResponse = serde_json::from_value(body["result"].clone()).unwrap();
+        let block = hex::encode(encode_block(&solve(&template)));
+        let body = rpc(&node, "submit_block", json!([block])).await;
+        assert_eq!(body["result"]["status"], "connected");
+
+        let balance = rpc(&node, "get_balance", json!([hex::encode(pk(&b))])).await;
+        assert_eq!(balance["result"], template.coinbase_value + 1_000);
+        let owner = rpc(&node, "get_name", json!({ "name": "gold" })).await;
+        assert_eq!(owner["result"]["owner"], hex::encode(pk(&a)));
+
+        for (method, params, uri) in [
+            ("get_block_at", json!([1]), "/blocks/height/1".to_string()),
+            (
+                "get_headers",
+                json!({ "from": 1 }),
+                "/headers?from=1".to_string(),
+            ),
+            ("get_tip", json!(null), "/tip".to_string()),
+            (
+                "get_account",
+                json!([hex::encode(pk(&a))]),
+                format!("/accounts/{}", hex::encode(pk(&a))),
+            ),
+        ] {
+            let rest = call(&node, "GET", &uri, None).await.1;
+            assert_eq!(rpc(&node, method, params).await["result"], rest, "{method}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rpc_errors_carry_standard_codes_and_kinds() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let node = node_for(&[&a]);
+        let hex_txn = hex::encode(encode_txn(&signed_txn(&a, pk(&b), 1_000)));
+        rpc(&node, "submit_txn", json!([hex_txn])).await;
+
+        let mut forged = signed_txn(&a, pk(&b), 5);
+        forged.fee += 1;
+
+        for (method, params, code, kind) in [
+            ("submit_txn", json!([hex_txn]), MEMPOOL_REJECTED, "mempool"),
+            (
+                "submit_txn",
+                json!(TxnJson::from(&forged)),
+                TXN_VALIDATION,
+                "txn_validation",
+            ),
+            ("submit_block", json!(["00"]), INVALID_PARAMS, "decode"),
+            ("get_name", json!(["nobody"]), NOT_FOUND, "not_found"),
+            (
+                "get_balance",
+                json!(["abcd"]),
+                INVALID_PARAMS,
+                "bad_request",
+            ),
+        ] {
+            let error = rpc(&node, method, params).await["error"].clone();
+            assert_eq!(error["code"], code, "{method}");
+            assert_eq!(error["data"]["kind"], kind, "{method}");
+        }
+
+        for (method, params, code) in [
+            ("get_block_at", json!(["one"]), INVALID_PARAMS),
+            ("get_name", json!(["gold", "silver"]), INVALID_PARAMS),
+            ("get_name", json!("gold"), INVALID_PARAMS),
+            ("get_everything", json!([]), METHOD_NOT_FOUND),
+        ] {
+            let body = rpc(&node, method, params).await;
+            assert_eq!(body["error"]["code"], code, "{method}");
+            assert!(body.get("result").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn rpc_batches_skip_notifications() {
+        let node = node_for(&[]);
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "method": "get_chain_info", "id": "info" },
+            { "jsonrpc": "2.0", "method": "get_tip" },
+            { "jsonrpc": "2.0", "method": "nope", "id": 2 },
+            { "jsonrpc": "1.0", "method": "get_tip", "id": 3 },
+        ]);
+        let (status, body) = call(&node, "POST", "/rpc", Some(batch)).await;
+        assert_eq!(status, StatusCode::OK);
+        let responses: Vec<RpcResponse> = serde_json::from_value(body).unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0].id, "info");
+        assert_eq!(responses[0].result.as_ref().unwrap()["height"], 0);
+        assert_eq!(responses[1].error.as_ref().unwrap().code, METHOD_NOT_FOUND);
+        assert_eq!(responses[2].id, Value::Null);
+        assert_eq!(responses[2].error.as_ref().unwrap().code, INVALID_REQUEST);
+
+        let notifications = json!([{ "jsonrpc": "2.0", "method": "get_tip" }]);
+        let (status, _) = call(&node, "POST", "/rpc", Some(notifications)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (_, body) = call(&node, "POST", "/rpc", Some(json!([]))).await;
+        assert_eq!(body["error"]["code"], INVALID_REQUEST);
+
+        let tip = json!({ "jsonrpc": "2.0", "method": "get_tip", "id": 1 });
+        let (_, body) = call(
+            &node,
+            "POST",
+            "/rpc",
+            Some(json!(vec![tip.clone(); MAX_BATCH_SIZE])),
+        )
+        .await;
+        assert_eq!(body.as_array().unwrap().len(), MAX_BATCH_SIZE);
+        let (_, body) = call(
+            &node,
+            "POST",
+            "/rpc",
+            Some(json!(vec![tip; MAX_BATCH_SIZE + 1])),
+        )
+        .await;
+        assert_eq!(body["error"]["code"], INVALID_REQUEST);
+        assert_eq!(body["id"], Value::Null);
+
+        let request = Request::post("/rpc")
+            .body(Body::from("{ not json"))
+            .unwrap();
+        let response = router(node.clone()).oneshot(request).await.unwrap();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["code"], PARSE_ERROR);
+        assert_eq!(body["id"], Value::Null);
+    }
+}
@@ -0,0 +1,127 @@
+// Helpers shared by the integration tests. Not every test file uses every helper.
+#![allow(dead_code)]
+
+use secp256k1::{rand::rngs::OsRng, Keypair, Secp256k1};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use gold_2::*;
+
+pub const EASY_DIFFICULTY: [u8; 32] = [
+    0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+];
+
+pub fn new_keypair() -> Keypair {
+    Keypair::new(&Secp256k1::new(), &mut OsRng)
+}
+
+pub fn pk(keypair: &Keypair) -> [u8; 32] {
+    keypair.x_only_public_key().0.serialize()
+}
+
+// A state where each of the given keys holds `balance`
+pub fn funded_state(keys: &[&Keypair], balance: u64) -> BlockchainState {
+    let account_set: Accounts = keys.iter().map(|k| (pk(k), balance)).collect();
+
+    BlockchainState {
+        account_set,
+        name_set: HashMap::new(),
+        owned_names: HashMap::new(),
+        difficulty: EASY_DIFFICULTY,
+        height: 0,
+        last_720_times: [100; 720],
+        last_100_block_sizes: [10_000; 100],
+        previous_block_header: Header {
+            prev_block_hash: [0; 32],
+            merkle_root: [0; 32],
+            time: 820,
+            nonce: 0,
+        },
+        network: Network::Regtest,
+    }
+}
+
+pub fn signed_txn(from: &Keypair, to: [u8; 32], amount: u64) -> Txn {
+    let mut txn = Txn {
+        sender: Address::Key(pk(from)),
+        recievers: vec![(Address::Key(to), amount)],
+        signature: [0; 64],
+        fee: 0,
+    };
+    finalize_txn(&mut txn, from, Network::Regtest);
+    txn
+}
+
+// Like signed_txn, but pays `extra` on top of the minimum fee
+pub fn signed_txn_paying(from: &Keypair, to: [u8; 32], amount: u64, extra: u64) -> Txn {
+    let mut txn = signed_txn(from, to, amount);
+    txn.fee += extra;
+    txn.signature = *Secp256k1::new()
+        .sign_schnorr(&txn_signing_data(&txn, Network::Regtest), from)
+        .as_byte_array();
+    txn
+}
+
+// `signer` is the current owner of the name if it has one, otherwise the new owner
+pub fn signed_rename(new_owner: &Keypair, signer: &Keypair, name: &str) -> RenameOp {
+    let mut op = RenameOp {
+        pk: pk(new_owner),
+        sig: [0; 64],
+        new_name: name.into(),
+        fee: 0,
+    };
+    finalize_name_change(&mut op, signer, Network::Regtest);
+    op
+}
+
+// Builds a block on top of `state` paying the coinbase to `miner`, then grinds the nonce
+pub fn mined_block(
+    state: &BlockchainState,
+    miner: [u8; 32],
+    txns: Vec<Txn>,
+    name_changes: Vec<RenameOp>,
+) -> Block {
+    let mut block = Block {
+        header: Header {
+            prev_block_hash: hash_header(&state.previous_block_header),
+            merkle_root: [0; 32],
+            time: state.previous_block_header.time + 1,
+            nonce: 0,
+        },
+        txns,
+        name_changes,
+    };
+
+    let coinbase = Txn {
+        sender: Address::Key([0; 32]),
+        recievers: vec![(Address::Key(miner), 0)],
+        signature: [0; 64],
+        fee: 0,
+    };
+    block.txns.insert(0, coinbase);
+
+    let fees: u64 = block.txns.iter().map(|t| t.fee).sum();
+    block.txns[0].recievers[0].1 = calc_coinbase(
+        block_size(&block),
+        median_block_size(&state.last_100_block_sizes),
+    ) + fees;
+
+    block.header.merkle_root = merkle_root(&block.txns, &block.name_changes);
+    while !meets_difficulty(&hash_header(&block.header), &state.difficulty) {
+        block.header.nonce += 1;
+    }
+
+    block
+}
+
+// Roughly one hash in 256 passes, which keeps tests that mine lots of blocks fast
+pub const TRIVIAL_DIFFICULTY: [u8; 32] = [
+    0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+];
+
+// A path in the temp dir that's unique to this test process
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gold_2_{}_{name}", std::process::id()))
+}
@@ -0,0 +1,185 @@
+mod common;
+
+#[cfg(test)]
+mod compact_blocks {
+    use gold_2::chain::Chain;
+    use gold_2::net::compact::*;
+    use gold_2::net::limits::{MAX_PARTIAL_BLOCKS, PARTIAL_BLOCK_TIMEOUT};
+    use gold_2::net::node::{Node, Outbound};
+    use gold_2::net::*;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    fn rename(name: &str) -> RenameOp {
+        RenameOp {
+            pk: [7; 32],
+            sig: [1; 64],
+            new_name: name.into(),
+            fee: 1_000,
+        }
+    }
+
+    fn block_with_items() -> Block {
+        let alice = new_keypair();
+        let state = funded_state(&[&alice], 1_000_000_000_000);
+        let txns = (1..=3)
+            .map(|i| signed_txn(&alice, [2; 32], i * 1_000))
+            .collect();
+
+        mined_block(&state, pk(&alice), txns, vec![rename("a"), rename("b")])
+    }
+
+    #[test]
+    fn reconstructs_from_mempool() {
+        let block = block_with_items();
+        let compact = CompactBlock::new(&block, 42);
+
+        assert_eq!(compact.txn_ids.len(), 3);
+        assert_eq!(compact.rename_ids.len(), 2);
+
+        let mut partial = PartialBlock::new(&compact);
+        partial.fill_from(block.txns.iter(), block.name_changes.iter());
+
+        assert!(partial.missing().is_none());
+        assert_eq!(partial.finish().unwrap(), block);
+    }
+
+    #[test]
+    fn requests_missing_items() {
+        let block = block_with_items();
+        let compact = CompactBlock::new(&block, 7);
+
+        // The receiver only knows about the second txn and the first rename
+        let mut partial = PartialBlock::new(&compact);
+        partial.fill_from(block.txns[2..3].iter(), block.name_changes[0..1].iter());
+
+        let request = partial.missing().unwrap();
+        assert_eq!(request.txn_indexes, vec![0, 2]);
+        assert_eq!(request.rename_indexes, vec![1]);
+
+        let response = block_txns(&block, &request).unwrap();
+        partial.fill_missing(response).unwrap();
+
+        assert_eq!(partial.finish().unwrap(), block);
+    }
+
+    #[test]
+    fn rejects_wrong_response() {
+        let block = block_with_items();
+        let mut partial = PartialBlock::new(&CompactBlock::new(&block, 7));
+        let request = partial.missing().unwrap();
+
+        let mut response = block_txns(&block, &request).unwrap();
+        response.renames.swap(0, 1);
+
+        assert!(matches!(
+            partial.fill_missing(response),
+            Err(Error::DecodeError(_))
+        ));
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let block = block_with_items();
+        let compact = CompactBlock::new(&block, 99);
+
+        let messages = [
+            Message::Block(block.clone()),
+            Message::CompactBlock(compact.clone()),
+            Message::GetBlockTxns(PartialBlock::new(&compact).missing().unwrap()),
+        ];
+
+        for message in messages.iter() {
+            let encoded = encode_message(message, Network::Regtest);
+            let decoded = decode_message(&encoded, Network::Regtest).unwrap();
+            assert_eq!(encode_message(&decoded, Network::Regtest), encoded);
+        }
+
+        let mut encoded = encode_message(&Message::Block(block.clone()), Network::Regtest);
+        assert_eq!(encoded.len(), block_size(&block) + MESSAGE_HEADER_SIZE);
+
+        encoded.push(0);
+        assert!(decode_message(&encoded, Network::Regtest).is_err());
+    }
+
+    // A node and `count` different blocks on top of its tip, each with a txn the node hasn't seen
+    fn node_and_blocks(count: u64) -> (Node, Vec<Block>) {
+        let alice = new_keypair();
+        let mut state = funded_state(&[&alice], 1_000_000_000_000);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+
+        let blocks = (1..=count)
+            .map(|i| {
+                mined_block(
+                    &state,
+                    pk(&alice),
+                    vec![signed_txn(&alice, [2; 32], i)],
+                    vec![],
+                )
+            })
+            .collect();
+
+        (Node::new(Chain::new(state)), blocks)
+    }
+
+    fn announce(node: &mut Node, from: usize, block: &Block, now: u64) -> bool {
+        let message = Message::CompactBlock(CompactBlock::new(block, 1));
+        let outbound = node.handle_message(from, message, now);
+        matches!(
+            outbound.as_slice(),
+            [Outbound::Reply(Message::GetBlockTxns(_))]
+        )
+    }
+
+    #[test]
+    fn unsolved_headers_are_not_held() {
+        let (mut node, mut blocks) = node_and_blocks(1);
+        let block = &mut blocks[0];
+
+        while meets_difficulty(&hash_header(&block.header), &node.chain.state.difficulty) {
+            block.header.nonce += 1;
+        }
+
+        assert!(!announce(&mut node, 1, block, 0));
+    }
+
+    #[test]
+    fn stalled_partials_make_way_for_new_ones() {
+        let (mut node, blocks) = node_and_blocks(MAX_PARTIAL_BLOCKS as u64 + 1);
+
+        for (i, block) in blocks.iter().enumerate() {
+            assert!(announce(&mut node, 1, block, i as u64));
+        }
+
+        // The first partial was the oldest, so it went to make room for the last one
+        assert!(announce(&mut node, 1, &blocks[0], 100));
+        assert!(!announce(&mut node, 1, &blocks[5], 100));
+
+        // Nobody answered, so after the timeout it can be asked for again
+        assert!(announce(
+            &mut node,
+            1,
+            &blocks[5],
+            5 + PARTIAL_BLOCK_TIMEOUT
+        ));
+    }
+
+    #[test]
+    fn disconnecting_drops_what_the_peer_left_waiting() {
+        let (mut node, blocks) = node_and_blocks(1);
+        let parent = &blocks[0];
+
+        let mut other = Node::new(Chain::new(node.chain.state.clone()));
+        other.add_block(parent.clone(), 0).unwrap();
+        let child = mined_block(&other.chain.state, [3; 32], vec![], vec![]);
+
+        assert!(announce(&mut node, 1, parent, 0));
+        node.handle_message(1, Message::Block(child), 0);
+        node.remove_peer(1);
+
+        assert!(announce(&mut node, 2, parent, 0));
+        node.handle_message(2, Message::Block(parent.clone()), 0);
+        assert_eq!(node.chain.height(), 1);
+    }
+}
@@ -0,0 +1,96 @@
+mod common;
+
+#[cfg(test)]
+mod context {
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+
+    use gold_2::chain::Chain;
+    use gold_2::context::*;
+    use gold_2::net::node::Node;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    fn context_for(state: BlockchainState) -> SharedNode {
+        NodeContext::new(Node::new(Chain::new(state)))
+    }
+
+    #[test]
+    fn the_snapshot_starts_at_the_tip() {
+        let state = funded_state(&[&new_keypair()], 1_000);
+        let context = context_for(state.clone());
+
+        let tip = context.tip();
+        assert_eq!(tip.state, state);
+        assert_eq!(tip.hash, hash_header(&state.previous_block_header));
+    }
+
+    #[test]
+    fn connecting_a_block_publishes_a_new_snapshot() {
+        let a = new_keypair();
+        let mut state = funded_state(&[&a], 1_000_000_000_000);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        let context = context_for(state.clone());
+        let before = context.tip();
+
+        // Pool changes leave the tip alone
+        context
+            .write(|node| node.submit_txn(signed_txn(&a, [1; 32], 5), 0))
+            .unwrap();
+        assert!(Arc::ptr_eq(&before, &context.tip()));
+
+        let block = mined_block(&state, pk(&a), vec![], vec![]);
+        let hash = hash_header(&block.header);
+        context.write(|node| node.add_block(block, 0)).unwrap();
+
+        let after = context.tip();
+        assert_eq!(after.hash, hash);
+        assert_eq!(after.state.height, 1);
+        assert_eq!(after.state, context.read(|node| node.chain.state.clone()));
+
+        // Anyone still holding the old snapshot sees it unchanged
+        assert_eq!(before.state, state);
+    }
+
+    #[test]
+    fn failed_writes_leave_the_snapshot_alone() {
+        let mut state = funded_state(&[], 0);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        let context = context_for(state.clone());
+
+        let mut block = mined_block(&state, [1; 32], vec![], vec![]);
+        block.txns[0].recievers[0].1 += 1;
+        block.header.merkle_root = merkle_root(&block.txns, &block.name_changes);
+        while !meets_difficulty(&hash_header(&block.header), &state.difficulty) {
+            block.header.nonce += 1;
+        }
+
+        assert!(context.write(|node| node.add_block(block, 0)).is_err());
+        assert_eq!(context.tip().state, state);
+    }
+
+    #[test]
+    fn tip_reads_do_not_wait_for_writes() {
+        let context = context_for(funded_state(&[], 0));
+        let (locked, wait_for_locked) = mpsc::channel();
+        let (release, wait_for_release) = mpsc::channel::<()>();
+
+        let writer = {
+            let context = context.clone();
+            thread::spawn(move || {
+                context.write(|_| {
+                    locked.send(()).unwrap();
+                    wait_for_release.recv().unwrap();
+                })
+            })
+        };
+
+        // The writer holds the node now, but the tip is still readable
+        wait_for_locked.recv().unwrap();
+        assert_eq!(context.tip().state.height, 0);
+
+        release.send(()).unwrap();
+        writer.join().unwrap();
+    }
+}
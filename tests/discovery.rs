@@ -0,0 +1,119 @@
+mod common;
+
+#[cfg(test)]
+mod peer_discovery {
+    use std::fs;
+    use std::net::SocketAddr;
+
+    use gold_2::net::discovery::*;
+    use gold_2::net::sim::Simulator;
+    use gold_2::net::Message;
+
+    use crate::common::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn seed_list_skips_comments() {
+        let path = temp_path("seeds.txt");
+        fs::write(&path, "# seeds\n1.2.3.4:9281\n\n  [::1]:9281  # local\n").unwrap();
+
+        let seeds = load_seed_list(&path).unwrap();
+        assert_eq!(seeds, vec![addr("1.2.3.4:9281"), addr("[::1]:9281")]);
+
+        fs::write(&path, "not an address\n").unwrap();
+        assert!(load_seed_list(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn address_book_round_trips() {
+        let path = temp_path("peers.txt");
+        let mut book = AddressBook::new();
+        book.add(addr("1.2.3.4:9281"), NOW - 100, NOW);
+        book.add(addr("[2001:db8::1]:9281"), NOW - 5, NOW);
+        book.mark_failed(&addr("1.2.3.4:9281"));
+
+        book.save(&path).unwrap();
+        assert_eq!(AddressBook::load(&path).unwrap(), book);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bad_timestamps_are_corrected() {
+        let mut book = AddressBook::new();
+
+        book.add(addr("1.2.3.4:9281"), NOW + 24 * 60 * 60, NOW);
+        assert!(book.get(&addr("1.2.3.4:9281")).unwrap().last_seen < NOW);
+
+        book.add(addr("5.6.7.8:9281"), NOW - ADDRESS_EXPIRY - 1, NOW);
+        assert!(book.get(&addr("5.6.7.8:9281")).is_none());
+    }
+
+    #[test]
+    fn outbound_picks_are_diverse() {
+        let mut book = AddressBook::new();
+        for a in [
+            "10.0.0.1:1",
+            "10.0.0.2:1",
+            "10.0.5.5:1",
+            "11.0.0.1:1",
+            "12.0.0.1:1",
+        ] {
+            book.add(addr(a), NOW, NOW);
+        }
+
+        let picked = book.select_outbound(&[], 5);
+        assert_eq!(picked.len(), 3);
+        assert_eq!(
+            picked
+                .iter()
+                .filter(|a| a.to_string().starts_with("10.0."))
+                .count(),
+            1
+        );
+
+        let picked = book.select_outbound(&[addr("10.0.9.9:1")], 5);
+        assert!(picked.iter().all(|a| !a.to_string().starts_with("10.0.")));
+    }
+
+    #[test]
+    fn peer_manager_tops_up_to_target() {
+        let mut book = AddressBook::new();
+        book.add_seeds(
+            &[addr("1.0.0.1:1"), addr("2.0.0.1:1"), addr("3.0.0.1:1")],
+            NOW,
+        );
+        let mut manager = PeerManager::new(book, 2);
+
+        let dials = manager.next_dials();
+        assert_eq!(dials.len(), 2);
+        assert!(manager.next_dials().is_empty());
+
+        manager.connected(dials[0], NOW);
+        manager.failed(dials[1]);
+
+        let retry = manager.next_dials();
+        assert_eq!(retry.len(), 1);
+        assert_ne!(retry[0], dials[0]);
+        assert_eq!(manager.outbound(), &[dials[0]]);
+    }
+
+    #[test]
+    fn addresses_are_gossiped() {
+        let mut sim = Simulator::new(2, &funded_state(&[], 0), 1);
+        sim.connect(0, 1);
+        sim.nodes[0].addresses.add(addr("1.2.3.4:9281"), 0, 0);
+
+        sim.inject(1, 0, &Message::GetAddr);
+        sim.run_until_idle();
+
+        assert!(sim.nodes[1].addresses.get(&addr("1.2.3.4:9281")).is_some());
+    }
+}
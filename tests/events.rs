@@ -0,0 +1,217 @@
+mod common;
+
+#[cfg(test)]
+mod events {
+    use gold_2::chain::Chain;
+    use gold_2::context::*;
+    use gold_2::events::*;
+    use gold_2::mempool::{Eviction, EvictionReason, TxnSource, MIN_REPLACEMENT_FEE_PER_BYTE};
+    use gold_2::net::node::Node;
+    use gold_2::net::Message;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    const BALANCE: u64 = 1_000_000_000_000;
+
+    fn recording_node(state: &BlockchainState) -> Node {
+        let mut node = Node::new(Chain::new(state.clone()));
+        node.record_events();
+        node
+    }
+
+    #[test]
+    fn nothing_is_kept_until_asked_for() {
+        let a = new_keypair();
+        let mut node = Node::new(Chain::new(funded_state(&[&a], BALANCE)));
+
+        node.submit_txn(signed_txn(&a, [1; 32], 5), 0).unwrap();
+        assert!(node.take_events().is_empty());
+    }
+
+    #[test]
+    fn pool_additions_are_recorded_from_peers_and_locally() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b], BALANCE);
+        let mut node = recording_node(&state);
+
+        let local = signed_txn(&a, pk(&b), 5);
+        let from_peer = signed_txn(&b, pk(&a), 7);
+        let op = signed_rename(&a, &a, "gold");
+        node.submit_txn(local.clone(), 0).unwrap();
+        node.handle_message(1, Message::Txn(from_peer.clone()), 0);
+        node.handle_message(1, Message::Rename(op.clone()), 0);
+        // Already pending, so not new
+        node.handle_message(2, Message::Txn(local.clone()), 0);
+
+        assert_eq!(
+            node.take_events(),
+            vec![
+                Event::Txn {
+                    txn: local,
+                    keys: vec![pk(&a), pk(&b)],
+                },
+                Event::Txn {
+                    txn: from_peer,
+                    keys: vec![pk(&b), pk(&a)],
+                },
+                Event::Rename { op, owner: None },
+            ]
+        );
+        assert!(node.take_events().is_empty());
+    }
+
+    #[test]
+    fn evictions_are_recorded_and_never_pile_up() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b], BALANCE);
+        let original = signed_txn(&a, pk(&b), 5);
+        let extra = MIN_REPLACEMENT_FEE_PER_BYTE * encode_txn(&original).len() as u64;
+        let bump = signed_txn_paying(&a, pk(&b), 5, extra);
+
+        let mut quiet = Node::new(Chain::new(state.clone()));
+        quiet.submit_txn(original.clone(), 0).unwrap();
+        quiet.submit_txn(bump.clone(), 0).unwrap();
+        assert!(quiet.mempool.take_evictions().is_empty());
+
+        let mut node = recording_node(&state);
+        node.submit_txn(original.clone(), 0).unwrap();
+        node.handle_message(1, Message::Txn(bump.clone()), 0);
+
+        assert_eq!(
+            node.take_events(),
+            vec![
+                Event::Txn {
+                    txn: original.clone(),
+                    keys: vec![pk(&a), pk(&b)],
+                },
+                Event::Txn {
+                    txn: bump,
+                    keys: vec![pk(&a), pk(&b)],
+                },
+                Event::TxnEvicted(Eviction {
+                    hash: txn_hash(&original),
+                    sender: pk(&a),
+                    source: TxnSource::Local,
+                    reason: EvictionReason::Replaced,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn reorgs_report_blocks_and_the_names_they_moved() {
+        let a = new_keypair();
+        let state = funded_state(&[&a], BALANCE);
+        let context = NodeContext::new(Node::new(Chain::new(state.clone())));
+        let mut events = context.subscribe_events();
+
+        let claim = mined_block(&state, [1; 32], vec![], vec![signed_rename(&a, &a, "gold")]);
+        context
+            .write(|node| node.add_block(claim.clone(), 0))
+            .unwrap();
+
+        assert_eq!(
+            events.try_recv().unwrap(),
+            Event::BlockConnected {
+                block: claim.clone(),
+                height: 1,
+            }
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            Event::NameOwner {
+                name: "gold".into(),
+                old_owner: None,
+                new_owner: Some(pk(&a)),
+            }
+        );
+
+        // A longer branch without the claim
+        let first = mined_block(&state, [2; 32], vec![], vec![]);
+        let mut branch_state = state.clone();
+        push_block(first.clone(), &mut branch_state).unwrap();
+        let second = mined_block(&branch_state, [2; 32], vec![], vec![]);
+
+        context
+            .write(|node| node.add_block(first.clone(), 0))
+            .unwrap();
+        assert!(events.try_recv().is_err());
+        context
+            .write(|node| node.add_block(second.clone(), 0))
+            .unwrap();
+
+        let received: Vec<Event> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(
+            received,
+            vec![
+                Event::BlockDisconnected {
+                    block: claim,
+                    height: 1,
+                },
+                Event::BlockConnected {
+                    block: first,
+                    height: 1,
+                },
+                Event::BlockConnected {
+                    block: second,
+                    height: 2,
+                },
+                Event::NameOwner {
+                    name: "gold".into(),
+                    old_owner: Some(pk(&a)),
+                    new_owner: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_pick_out_keys_and_names() {
+        let (a, b, c) = ([1; 32], [2; 32], [3; 32]);
+        let txn = Txn {
+            sender: Address::Key(a),
+            recievers: vec![(Address::Name("gold".into()), 5)],
+            signature: [0; 64],
+            fee: 0,
+        };
+        let txn = Event::Txn {
+            txn,
+            keys: vec![a, b],
+        };
+        let owner = Event::NameOwner {
+            name: "silver".into(),
+            old_owner: Some(c),
+            new_owner: None,
+        };
+        let block = Event::BlockConnected {
+            block: mined_block(&funded_state(&[], 0), c, vec![], vec![]),
+            height: 1,
+        };
+
+        let filter = |keys: &[[u8; 32]], names: &[&str]| EventFilter {
+            keys: keys.iter().copied().collect(),
+            names: names.iter().map(|n| n.to_string()).collect(),
+        };
+
+        assert!(filter(&[], &[]).matches(&txn));
+        assert!(filter(&[b], &[]).matches(&txn));
+        assert!(filter(&[], &["gold"]).matches(&txn));
+        assert!(!filter(&[c], &["silver"]).matches(&txn));
+
+        assert!(filter(&[c], &[]).matches(&owner));
+        assert!(filter(&[], &["silver"]).matches(&owner));
+        assert!(!filter(&[a], &["gold"]).matches(&owner));
+
+        assert!(filter(&[a], &[]).matches(&block));
+
+        let evicted = Event::TxnEvicted(Eviction {
+            hash: [4; 32],
+            sender: a,
+            source: TxnSource::Local,
+            reason: EvictionReason::Expired,
+        });
+        assert!(filter(&[a], &[]).matches(&evicted));
+        assert!(!filter(&[b], &["gold"]).matches(&evicted));
+    }
+}
@@ -0,0 +1,94 @@
+mod common;
+
+#[cfg(test)]
+mod fee_estimation {
+    use gold_2::fees::*;
+    use gold_2::mempool::*;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    const EXTRA: u64 = 1_000_000_000;
+
+    #[test]
+    fn defaults_to_the_minimum_fee() {
+        let state = funded_state(&[], 0);
+        let estimator = FeeEstimator::new();
+
+        assert_eq!(
+            estimator.estimate(&Mempool::new(), 1, &state),
+            TXN_FEES_PER_BYTE
+        );
+        assert_eq!(
+            estimator.estimate(&Mempool::new(), 10, &state),
+            TXN_FEES_PER_BYTE
+        );
+    }
+
+    #[test]
+    fn sooner_targets_pay_more_based_on_history() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a], 1_000_000_000_000_000);
+        let mut estimator = FeeEstimator::new();
+
+        // Half the included txns paid the minimum, half paid well over it
+        for i in 0..4 {
+            let txns = vec![
+                signed_txn(&a, pk(&b), i),
+                signed_txn_paying(&a, pk(&b), i, EXTRA),
+            ];
+            estimator.block_connected(&mined_block(&state, pk(&b), txns, vec![]));
+        }
+
+        let fast = estimator.estimate(&Mempool::new(), 1, &state);
+        let slow = estimator.estimate(&Mempool::new(), 10, &state);
+
+        assert!(fast > TXN_FEES_PER_BYTE);
+        assert_eq!(slow, TXN_FEES_PER_BYTE);
+    }
+
+    #[test]
+    fn a_deep_mempool_raises_the_estimate() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a], 1_000_000_000_000_000);
+        let mut mempool = Mempool::new();
+
+        let median = median_block_size(&state.last_100_block_sizes);
+        let mut queued = 0;
+        let mut amount = 0;
+
+        // A bit over one block of well paying txns
+        while queued < penalty_free_block_size(median) + 1_000 {
+            let txn = signed_txn_paying(&a, pk(&b), amount, EXTRA);
+            queued += encode_txn(&txn).len();
+            mempool.add_txn(txn, TxnSource::Local, &state, 0).unwrap();
+            amount += 1;
+        }
+
+        let estimator = FeeEstimator::new();
+        let lowest_queued = mempool.by_fee_rate().last().unwrap().fee_rate();
+
+        assert!(estimator.estimate(&mempool, 1, &state) > lowest_queued);
+        assert_eq!(estimator.estimate(&mempool, 2, &state), TXN_FEES_PER_BYTE);
+    }
+
+    #[test]
+    fn history_is_bounded_and_follows_disconnects() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a], 1_000_000_000_000_000);
+        let mut estimator = FeeEstimator::new();
+
+        let empty = mined_block(&state, pk(&b), vec![], vec![]);
+        for _ in 0..FEE_HISTORY_BLOCKS + 5 {
+            estimator.block_connected(&empty);
+        }
+        assert_eq!(estimator.len(), FEE_HISTORY_BLOCKS);
+
+        let paying = vec![signed_txn_paying(&a, pk(&b), 1, EXTRA)];
+        estimator.block_connected(&mined_block(&state, pk(&b), paying, vec![]));
+        assert!(estimator.history_estimate(1) > TXN_FEES_PER_BYTE);
+
+        estimator.block_disconnected();
+        assert_eq!(estimator.history_estimate(1), TXN_FEES_PER_BYTE);
+    }
+}
@@ -37,8 +37,9 @@ mod blockchain_validation {
         let keypair = Keypair::new(&secp, &mut OsRng);
         let serialized_pk = keypair.x_only_public_key().0.serialize();
 
-        let mut account_set: Accounts = create_dummy_account_set(serialized_pk, 200_000_000_000);
-        let mut name_set: Names = create_dummy_name_set("GitMonke".into(), serialized_pk);
+        let account_set: Accounts = create_dummy_account_set(serialized_pk, 200_000_000_000);
+        let name_set: Names = create_dummy_name_set("GitMonke".into(), serialized_pk);
+        let owned_names = index_names(&name_set);
 
         let header = Header {
             prev_block_hash: [0; 32],
@@ -51,6 +52,7 @@ mod blockchain_validation {
             BlockchainState {
                 account_set,
                 name_set,
+                owned_names,
                 difficulty: [
                     0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
                     255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
@@ -59,6 +61,7 @@ mod blockchain_validation {
                 last_720_times: [100; 720],
                 last_100_block_sizes: [10_000; 100],
                 previous_block_header: header,
+                network: Network::Regtest,
             },
             keypair,
         )
@@ -76,7 +79,7 @@ mod blockchain_validation {
             fee: 0,
         };
 
-        finalize_txn(&mut example_txn, &keypair);
+        finalize_txn(&mut example_txn, &keypair, Network::Regtest);
 
         let txns = vec![example_txn];
         let renames = vec![];
@@ -95,7 +98,7 @@ mod blockchain_validation {
         // inserting the coinbase txn needs refactoring
 
         // All 0's sends a coinbase txn to GitMonke
-        let mut txn = Txn {
+        let txn = Txn {
             sender: Address::Key([0; 32]),
             recievers: vec![(Address::Name("GitMonke".into()), 0)],
             signature: [0; 64],
@@ -181,9 +184,9 @@ mod blockchain_validation {
 
     #[test]
     fn test_pushblock() {
-        let (mut state, mut block, _) = create_dummy_valid_block();
+        let (mut state, block, _) = create_dummy_valid_block();
 
-        push_block(block.clone(), &mut state);
+        push_block(block.clone(), &mut state).unwrap();
 
         assert_eq!(state.last_100_block_sizes[99], block_size(&block));
         assert_eq!(state.last_720_times[719], 821);
@@ -192,14 +195,95 @@ mod blockchain_validation {
 
     #[test]
     fn test_popblock() {
-        let (mut state, mut block, _) = create_dummy_valid_block();
+        let (mut state, block, _) = create_dummy_valid_block();
         let state_before_push = state.clone();
-        let prev_block_size = state.last_100_block_sizes[99];
-        let prev_header = state.previous_block_header.clone();
 
-        let undo_block = push_block(block.clone(), &mut state);
+        let undo_block = push_block(block.clone(), &mut state).unwrap();
         pop_block(&undo_block, &mut state);
 
         assert_eq!(state, state_before_push);
     }
+
+    #[test]
+    fn popping_renames_restores_names_and_fees() {
+        let (mut state, mut block, keypair) = create_dummy_valid_block();
+        let pk = keypair.x_only_public_key().0.serialize();
+        let rename = |name: &str| RenameOp {
+            pk,
+            sig: [0; 64],
+            new_name: name.into(),
+            fee: 1_000,
+        };
+
+        // A fresh claim, a name claimed twice in one block, and a name which already had an owner
+        block.name_changes = vec![
+            rename("Fresh"),
+            rename("Twice"),
+            rename("Twice"),
+            rename("GitMonke"),
+        ];
+        let state_before_push = state.clone();
+
+        let undo_block = push_block(block, &mut state).unwrap();
+        assert_eq!(state.name_set["Fresh"], pk);
+
+        pop_block(&undo_block, &mut state);
+        assert_eq!(state, state_before_push);
+    }
+
+    #[test]
+    fn push_to_back_undoes_push_to_front() {
+        let mut values = [1, 2, 3, 4];
+
+        let removed = push_to_front(&mut values, 5);
+        assert_eq!(values, [2, 3, 4, 5]);
+
+        push_to_back(&mut values, removed);
+        assert_eq!(values, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn emptied_accounts_are_removed_and_restored() {
+        let (mut state, keypair) = create_dummy_blockchainstate();
+        let secp = Secp256k1::new();
+        let a = keypair.x_only_public_key().0.serialize();
+        let b = Keypair::new(&secp, &mut OsRng)
+            .x_only_public_key()
+            .0
+            .serialize();
+        let c = Keypair::new(&secp, &mut OsRng)
+            .x_only_public_key()
+            .0
+            .serialize();
+        let balance = state.account_set[&a];
+
+        let pay = |from: [u8; 32], to: [u8; 32]| Txn {
+            sender: Address::Key(from),
+            recievers: vec![(Address::Key(to), balance)],
+            signature: [0; 64],
+            fee: 0,
+        };
+
+        // b is paid everything a has, then passes it all on to c in the same block
+        let block = Block {
+            header: Header {
+                prev_block_hash: hash_header(&state.previous_block_header),
+                merkle_root: [0; 32],
+                time: 821,
+                nonce: 0,
+            },
+            txns: vec![pay(a, b), pay(b, c)],
+            name_changes: vec![],
+        };
+        let state_before_push = state.clone();
+
+        let undo_block = push_block(block, &mut state).unwrap();
+        assert!(!state.account_set.contains_key(&a));
+        assert!(!state.account_set.contains_key(&b));
+        assert_eq!(state.account_set[&c], balance);
+        assert_eq!(state.height, 1);
+
+        pop_block(&undo_block, &mut state);
+        assert_eq!(state, state_before_push);
+    }
 }
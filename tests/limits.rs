@@ -0,0 +1,103 @@
+mod common;
+
+#[cfg(test)]
+mod network_limits {
+    use gold_2::net::limits::*;
+    use gold_2::net::sim::Simulator;
+    use gold_2::net::*;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    #[test]
+    fn block_limit_follows_median() {
+        let mut state = funded_state(&[], 0);
+
+        state.last_100_block_sizes = [5_000; 100];
+        assert_eq!(
+            max_message_size(BLOCK_TAG, &state),
+            MIN_MAX_BLOCK_SIZE + MESSAGE_HEADER_SIZE
+        );
+
+        state.last_100_block_sizes = [50_000; 100];
+        assert_eq!(
+            max_message_size(BLOCK_TAG, &state),
+            100_000 + MESSAGE_HEADER_SIZE
+        );
+
+        let mut too_big = Network::Regtest.magic().to_vec();
+        too_big.push(BLOCK_TAG);
+        too_big.resize(100_000 + MESSAGE_HEADER_SIZE + 1, 0);
+        assert!(check_message_size(&too_big, &state).is_err());
+        assert!(check_message_size(&too_big[..100_000 + MESSAGE_HEADER_SIZE], &state).is_ok());
+    }
+
+    #[test]
+    fn rename_limit_follows_name_limit() {
+        let state = funded_state(&[], 0);
+        let op = RenameOp {
+            pk: [1; 32],
+            sig: [0; 64],
+            new_name: "a".repeat(MAX_NAME_SIZE),
+            fee: 0,
+        };
+
+        let data = encode_message(&Message::Rename(op), Network::Regtest);
+        assert_eq!(data.len(), max_message_size(RENAME_TAG, &state));
+        assert!(check_message_size(&data, &state).is_ok());
+
+        let mut data = data;
+        data.push(0);
+        assert!(check_message_size(&data, &state).is_err());
+    }
+
+    #[test]
+    fn token_bucket_refills() {
+        let mut bucket = TokenBucket::new(2, 10, 0);
+
+        assert!(bucket.try_take(2, 0));
+        assert!(!bucket.try_take(1, 0));
+        assert!(!bucket.try_take(1, 99));
+        assert!(bucket.try_take(1, 100));
+        // Refilling never goes past the capacity
+        assert!(bucket.try_take(2, 10_000));
+        assert!(!bucket.try_take(1, 10_000));
+    }
+
+    #[test]
+    fn peer_queue_is_bounded() {
+        let mut queue = PeerQueue::new(2, 10);
+
+        queue.push(vec![0; 6]).unwrap();
+        assert!(queue.push(vec![0; 5]).is_err());
+        queue.push(vec![0; 4]).unwrap();
+        assert!(queue.push(vec![]).is_err());
+
+        assert_eq!(queue.pop().unwrap().len(), 6);
+        assert_eq!(queue.bytes(), 4);
+    }
+
+    #[test]
+    fn misbehaving_peers_are_disconnected() {
+        let mut sim = Simulator::new(3, &funded_state(&[], 0), 1);
+        sim.connect(0, 1);
+        sim.connect(0, 2);
+
+        // Peer 1 sends a block bigger than consensus allows
+        let mut data = Network::Regtest.magic().to_vec();
+        data.push(BLOCK_TAG);
+        data.resize(MIN_MAX_BLOCK_SIZE + MESSAGE_HEADER_SIZE + 1, 0);
+        sim.inject_raw(1, 0, data);
+
+        // Peer 2 floods requests faster than the message bucket refills
+        for _ in 0..(MESSAGE_BURST + 1) {
+            sim.inject(2, 0, &Message::GetBlock([0; 32]));
+        }
+
+        sim.run_until_idle();
+
+        assert!(!sim.is_connected(0, 1));
+        assert!(!sim.is_connected(0, 2));
+        assert_eq!(sim.disconnects, 2);
+    }
+}
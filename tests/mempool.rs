@@ -0,0 +1,556 @@
+mod common;
+
+#[cfg(test)]
+mod mempool {
+    use gold_2::chain::{BlockStatus, Chain};
+    use gold_2::mempool::*;
+    use gold_2::net::node::Node;
+    use gold_2::net::sim::*;
+    use gold_2::*;
+    use secp256k1::Keypair;
+
+    use crate::common::*;
+
+    #[test]
+    fn orders_by_fee_rate() {
+        let (a, b, c) = (new_keypair(), new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b, &c], 100_000_000_000);
+        let mut mempool = Mempool::new();
+
+        let low = mempool
+            .add_txn(
+                signed_txn_paying(&a, pk(&b), 10, 0),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+        let high = mempool
+            .add_txn(
+                signed_txn_paying(&b, pk(&c), 10, 1_000_000_000),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+        let mid = mempool
+            .add_txn(
+                signed_txn_paying(&c, pk(&a), 10, 1_000_000),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+
+        let order: Vec<[u8; 32]> = mempool.by_fee_rate().map(|e| e.hash).collect();
+        assert_eq!(order, vec![high, mid, low]);
+        assert_eq!(mempool.len(), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_and_duplicate_txns() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a], 100_000_000_000);
+        let mut mempool = Mempool::new();
+
+        let txn = signed_txn(&a, pk(&b), 10);
+        mempool
+            .add_txn(txn.clone(), TxnSource::Local, &state, 0)
+            .unwrap();
+        assert!(matches!(
+            mempool.add_txn(txn, TxnSource::Local, &state, 0),
+            Err(Error::MempoolError(_))
+        ));
+
+        // b has no account
+        assert!(mempool
+            .add_txn(signed_txn(&b, pk(&a), 10), TxnSource::Local, &state, 0)
+            .is_err());
+
+        let mut bad_signature = signed_txn(&a, pk(&b), 20);
+        bad_signature.signature[0] ^= 1;
+        assert!(mempool
+            .add_txn(bad_signature, TxnSource::Local, &state, 0)
+            .is_err());
+
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn rejects_txns_whose_amounts_overflow() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a], 100_000_000_000);
+        let mut mempool = Mempool::new();
+
+        let mut txn = Txn {
+            sender: Address::Key(pk(&a)),
+            recievers: vec![(Address::Key(pk(&b)), u64::MAX), (Address::Key(pk(&b)), 2)],
+            signature: [0; 64],
+            fee: 0,
+        };
+        finalize_txn(&mut txn, &a, Network::Regtest);
+
+        assert!(matches!(
+            mempool.add_txn(txn.clone(), TxnSource::Local, &state, 0),
+            Err(Error::TxnValidationError(_))
+        ));
+        assert!(mempool.is_empty());
+
+        let block = mined_block(&state, pk(&a), vec![txn], vec![]);
+        assert!(validate_block(&block, &state).is_err());
+    }
+
+    #[test]
+    fn rejects_txns_paying_names_nobody_owns() {
+        let a = new_keypair();
+        let state = funded_state(&[&a], 100_000_000_000);
+        let mut mempool = Mempool::new();
+
+        let mut txn = Txn {
+            sender: Address::Key(pk(&a)),
+            recievers: vec![(Address::Name("nobody".into()), 10)],
+            signature: [0; 64],
+            fee: 0,
+        };
+        finalize_txn(&mut txn, &a, Network::Regtest);
+
+        assert!(matches!(
+            mempool.add_txn(txn.clone(), TxnSource::Local, &state, 0),
+            Err(Error::TxnValidationError(_))
+        ));
+
+        let block = mined_block(&state, pk(&a), vec![txn], vec![]);
+        assert!(validate_block(&block, &state).is_err());
+        assert!(push_block(block, &mut state.clone()).is_err());
+
+        // Nor can the coinbase pay one
+        let mut block = mined_block(&state, pk(&a), vec![], vec![]);
+        block.txns[0].recievers[0].0 = Address::Name("nobody".into());
+        block.header.merkle_root = merkle_root(&block.txns, &block.name_changes);
+        while !meets_difficulty(&hash_header(&block.header), &state.difficulty) {
+            block.header.nonce += 1;
+        }
+        assert!(matches!(
+            validate_block(&block, &state),
+            Err(Error::TxnValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn tracks_pending_spend_per_sender() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let txn = signed_txn(&a, pk(&b), 1_000);
+        let spend = txn_total_spend(&txn).unwrap();
+
+        // Enough for two of these txns, but not three
+        let state = funded_state(&[&a], spend * 2 + spend / 2);
+        let mut mempool = Mempool::new();
+
+        let first = mempool.add_txn(txn, TxnSource::Local, &state, 0).unwrap();
+        mempool
+            .add_txn(
+                signed_txn(&a, pk(&b), 1_000 + 1),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+        assert!(mempool
+            .add_txn(
+                signed_txn(&a, pk(&b), 1_000 + 2),
+                TxnSource::Local,
+                &state,
+                0
+            )
+            .is_err());
+
+        mempool.remove_txn(&first);
+        assert_eq!(mempool.pending_spend(&pk(&a)), spend + 1);
+        mempool
+            .add_txn(
+                signed_txn(&a, pk(&b), 1_000 + 2),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn confirmed_and_invalidated_txns_are_removed() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let first = signed_txn(&a, pk(&b), 1_000);
+        let balance = txn_total_spend(&first).unwrap() * 3 / 2;
+        let state = funded_state(&[&a], balance);
+        let mut mempool = Mempool::new();
+
+        mempool
+            .add_txn(first.clone(), TxnSource::Local, &state, 0)
+            .unwrap();
+        mempool.remove_block(&mined_block(&state, pk(&b), vec![first.clone()], vec![]));
+        assert!(mempool.is_empty());
+
+        // A txn we never saw spends enough of a's balance that the pending one no longer fits
+        mempool.add_txn(first, TxnSource::Local, &state, 0).unwrap();
+        let block = mined_block(&state, pk(&b), vec![signed_txn(&a, pk(&b), 1_001)], vec![]);
+
+        let mut next = state.clone();
+        push_block(block, &mut next).unwrap();
+        let removed = mempool.revalidate(&next);
+
+        assert_eq!(removed.len(), 1);
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn txns_relay_and_confirm_across_the_network() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let mut genesis = funded_state(&[&a], 100_000_000_000);
+        genesis.difficulty = TRIVIAL_DIFFICULTY;
+
+        let mut sim = Simulator::new(3, &genesis, 7);
+        sim.connect(0, 1);
+        sim.connect(1, 2);
+
+        let txn = signed_txn(&a, pk(&b), 1_000);
+        sim.submit_txn(0, txn.clone()).unwrap();
+        sim.run_until_idle();
+
+        assert!(sim.nodes.iter().all(|n| n.mempool.len() == 1));
+
+        // Every peer already holds the txn, so the compact block is rebuilt without fetching it
+        let block = mined_block(&sim.nodes[2].chain.state, pk(&b), vec![txn], vec![]);
+        sim.submit_block(2, block).unwrap();
+        sim.run_until_idle();
+
+        assert!(sim.states_converged());
+        assert!(sim.nodes.iter().all(|n| n.mempool.is_empty()));
+    }
+
+    // A node starting from a funded genesis, along with that genesis
+    fn node_and_genesis(keys: &[&Keypair], balance: u64) -> (Node, BlockchainState) {
+        let mut genesis = funded_state(keys, balance);
+        genesis.difficulty = TRIVIAL_DIFFICULTY;
+        (Node::new(Chain::new(genesis.clone())), genesis)
+    }
+
+    fn after(state: &BlockchainState, block: &Block) -> BlockchainState {
+        let mut state = state.clone();
+        push_block(block.clone(), &mut state).unwrap();
+        state
+    }
+
+    #[test]
+    fn disconnected_txns_return_unless_confirmed_again() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let (mut node, genesis) = node_and_genesis(&[&a, &b], 100_000_000_000);
+
+        let only_on_a = signed_txn(&a, pk(&b), 1_000);
+        let on_both = signed_txn(&b, pk(&a), 2_000);
+
+        let a1 = mined_block(
+            &genesis,
+            pk(&a),
+            vec![only_on_a.clone(), on_both.clone()],
+            vec![],
+        );
+        node.add_block(a1, 0).unwrap();
+        assert!(node.mempool.is_empty());
+
+        let b1 = mined_block(&genesis, pk(&b), vec![], vec![]);
+        let b2 = mined_block(&after(&genesis, &b1), pk(&b), vec![on_both.clone()], vec![]);
+        node.add_block(b1, 0).unwrap();
+        let status = node.add_block(b2, 0).unwrap();
+
+        assert!(matches!(status, BlockStatus::Connected { .. }));
+        assert_eq!(node.mempool.len(), 1);
+        assert!(node.mempool.contains(&txn_hash(&only_on_a)));
+        assert!(!node.mempool.contains(&txn_hash(&on_both)));
+    }
+
+    #[test]
+    fn disconnected_txns_invalid_on_new_tip_are_dropped() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let first = signed_txn(&a, pk(&b), 1_000);
+        let balance = txn_total_spend(&first).unwrap() * 3 / 2;
+        let (mut node, genesis) = node_and_genesis(&[&a], balance);
+
+        let a1 = mined_block(&genesis, pk(&b), vec![first.clone()], vec![]);
+        node.add_block(a1, 0).unwrap();
+
+        // The new branch spends most of a's balance on something else
+        let b1 = mined_block(
+            &genesis,
+            pk(&b),
+            vec![signed_txn(&a, pk(&b), 1_001)],
+            vec![],
+        );
+        let b2 = mined_block(&after(&genesis, &b1), pk(&b), vec![], vec![]);
+        node.add_block(b1, 0).unwrap();
+        node.add_block(b2, 0).unwrap();
+
+        assert_eq!(node.chain.height(), 2);
+        assert!(node.mempool.is_empty());
+    }
+
+    #[test]
+    fn disconnected_txns_win_over_pending_ones() {
+        let (a, b, c) = (new_keypair(), new_keypair(), new_keypair());
+        let (mut node, genesis) = node_and_genesis(&[&a], 100_000_000_000);
+
+        let confirmed = signed_txn(&a, pk(&b), 60_000_000_000);
+        let a1 = mined_block(&genesis, pk(&b), vec![confirmed.clone()], vec![]);
+        node.add_block(a1, 0).unwrap();
+
+        let pending = signed_txn(&a, pk(&c), 30_000_000_000);
+        node.submit_txn(pending.clone(), 0).unwrap();
+
+        // The new branch leaves a room for one of them but not both
+        let b1 = mined_block(
+            &genesis,
+            pk(&b),
+            vec![signed_txn(&a, pk(&b), 35_000_000_000)],
+            vec![],
+        );
+        let b2 = mined_block(&after(&genesis, &b1), pk(&b), vec![], vec![]);
+        node.add_block(b1, 0).unwrap();
+        node.add_block(b2, 0).unwrap();
+
+        assert_eq!(node.mempool.len(), 1);
+        assert!(node.mempool.contains(&txn_hash(&confirmed)));
+        assert!(!node.mempool.contains(&txn_hash(&pending)));
+    }
+
+    #[test]
+    fn fee_bump_replaces_the_pending_payment() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let original = signed_txn(&a, pk(&b), 1_000);
+        let size = encode_txn(&original).len() as u64;
+        let bump = MIN_REPLACEMENT_FEE_PER_BYTE * size;
+
+        // Only enough for one copy of the payment, so the bump has to free up the original's spend
+        let state = funded_state(&[&a], txn_total_spend(&original).unwrap() + bump);
+        let mut mempool = Mempool::new();
+
+        let original_hash = mempool
+            .add_txn(original, TxnSource::Local, &state, 0)
+            .unwrap();
+        let bumped = signed_txn_paying(&a, pk(&b), 1_000, bump);
+        let bumped_hash = mempool
+            .add_txn(bumped.clone(), TxnSource::Local, &state, 0)
+            .unwrap();
+
+        assert_eq!(mempool.len(), 1);
+        assert!(!mempool.contains(&original_hash));
+        assert!(mempool.contains(&bumped_hash));
+        assert_eq!(
+            mempool.pending_spend(&pk(&a)),
+            txn_total_spend(&bumped).unwrap()
+        );
+    }
+
+    #[test]
+    fn fee_bump_must_clear_the_minimum_increment() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a], 100_000_000_000);
+        let mut mempool = Mempool::new();
+
+        let original = signed_txn(&a, pk(&b), 1_000);
+        let size = encode_txn(&original).len() as u64;
+        let original_hash = mempool
+            .add_txn(original, TxnSource::Local, &state, 0)
+            .unwrap();
+
+        // Same fee, and a higher rate that doesn't pay for the replacement's bytes
+        let same_fee = signed_txn(&a, pk(&b), 1_000);
+        let too_small =
+            signed_txn_paying(&a, pk(&b), 1_000, MIN_REPLACEMENT_FEE_PER_BYTE * size - 1);
+
+        for txn in [same_fee, too_small] {
+            assert!(matches!(
+                mempool.add_txn(txn, TxnSource::Local, &state, 0),
+                Err(Error::MempoolError(_))
+            ));
+        }
+
+        // A different payment from the same sender isn't a replacement
+        mempool
+            .add_txn(signed_txn(&a, pk(&b), 2_000), TxnSource::Local, &state, 0)
+            .unwrap();
+
+        assert_eq!(mempool.len(), 2);
+        assert!(mempool.contains(&original_hash));
+    }
+
+    #[test]
+    fn full_pool_evicts_the_worst_paying_and_raises_the_minimum_fee() {
+        let (a, b, c) = (new_keypair(), new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b, &c], 100_000_000_000);
+        let size = encode_txn(&signed_txn(&a, pk(&b), 1)).len();
+
+        // Room for two txns
+        let mut mempool = Mempool::with_limits(size * 2 + size / 2, DEFAULT_MEMPOOL_EXPIRY);
+
+        let low = mempool
+            .add_txn(signed_txn(&a, pk(&b), 1), TxnSource::Peer(3), &state, 0)
+            .unwrap();
+        mempool
+            .add_txn(
+                signed_txn_paying(&b, pk(&c), 1, 1_000_000),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+        mempool
+            .add_txn(
+                signed_txn_paying(&c, pk(&a), 1, 2_000_000),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+
+        assert_eq!(mempool.len(), 2);
+        assert!(!mempool.contains(&low));
+        assert_eq!(
+            mempool.take_evictions(),
+            vec![Eviction {
+                hash: low,
+                sender: pk(&a),
+                source: TxnSource::Peer(3),
+                reason: EvictionReason::Full,
+            }]
+        );
+
+        // Paying what the evicted txn paid is no longer enough, until the minimum decays
+        assert!(mempool.min_fee_rate(0) > TXN_FEES_PER_BYTE);
+        assert!(matches!(
+            mempool.add_txn(signed_txn(&a, pk(&c), 2), TxnSource::Local, &state, 0),
+            Err(Error::MempoolError(_))
+        ));
+        assert_eq!(
+            mempool.min_fee_rate(MIN_FEE_HALF_LIFE * 64),
+            TXN_FEES_PER_BYTE
+        );
+    }
+
+    #[test]
+    fn txn_that_would_be_evicted_straight_away_is_rejected() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b], 100_000_000_000);
+        let size = encode_txn(&signed_txn(&a, pk(&b), 1)).len();
+        let mut mempool = Mempool::with_limits(size + size / 2, DEFAULT_MEMPOOL_EXPIRY);
+
+        let kept = mempool
+            .add_txn(
+                signed_txn_paying(&a, pk(&b), 1, 1_000_000),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+
+        assert!(matches!(
+            mempool.add_txn(signed_txn(&b, pk(&a), 1), TxnSource::Local, &state, 0),
+            Err(Error::MempoolError(_))
+        ));
+        assert!(mempool.contains(&kept));
+        assert!(mempool.take_evictions().is_empty());
+    }
+
+    #[test]
+    fn old_txns_expire() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b], 100_000_000_000);
+        let mut mempool = Mempool::with_limits(DEFAULT_MAX_MEMPOOL_BYTES, 1_000);
+
+        let old = mempool
+            .add_txn(signed_txn(&a, pk(&b), 1), TxnSource::Local, &state, 0)
+            .unwrap();
+        let young = mempool
+            .add_txn(signed_txn(&b, pk(&a), 1), TxnSource::Local, &state, 500)
+            .unwrap();
+
+        mempool.expire(1_200);
+
+        assert!(!mempool.contains(&old));
+        assert!(mempool.contains(&young));
+        assert_eq!(mempool.take_evictions()[0].reason, EvictionReason::Expired);
+    }
+
+    #[test]
+    fn replaced_and_invalidated_txns_are_reported() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a], 100_000_000_000);
+        let mut mempool = Mempool::new();
+
+        let original = signed_txn(&a, pk(&b), 1_000);
+        let bump = MIN_REPLACEMENT_FEE_PER_BYTE * encode_txn(&original).len() as u64;
+        let original = mempool
+            .add_txn(original, TxnSource::Peer(1), &state, 0)
+            .unwrap();
+        let bumped = mempool
+            .add_txn(
+                signed_txn_paying(&a, pk(&b), 1_000, bump),
+                TxnSource::Local,
+                &state,
+                0,
+            )
+            .unwrap();
+
+        // a's account is gone on the new tip
+        mempool.revalidate(&funded_state(&[], 0));
+
+        let reports: Vec<(TxnSource, EvictionReason)> = mempool
+            .take_evictions()
+            .iter()
+            .map(|e| (e.source, e.reason))
+            .collect();
+        assert_eq!(
+            reports,
+            vec![
+                (TxnSource::Peer(1), EvictionReason::Replaced),
+                (TxnSource::Local, EvictionReason::Invalidated),
+            ]
+        );
+        assert!(!mempool.contains(&original) && !mempool.contains(&bumped));
+    }
+
+    #[test]
+    fn saved_mempool_reloads_only_what_is_still_valid() {
+        let (a, b, c) = (new_keypair(), new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b], 100_000_000_000);
+        let path = temp_path("mempool.dat");
+        let mut mempool = Mempool::with_limits(DEFAULT_MAX_MEMPOOL_BYTES, 10_000);
+
+        let kept = mempool
+            .add_txn(signed_txn(&a, pk(&c), 1), TxnSource::Peer(2), &state, 5_000)
+            .unwrap();
+        let expires = mempool
+            .add_txn(signed_txn(&b, pk(&c), 1), TxnSource::Local, &state, 0)
+            .unwrap();
+        mempool.save(&path).unwrap();
+
+        // By the time we restart b's txn has sat for longer than the max age
+        let mut restarted = Mempool::with_limits(DEFAULT_MAX_MEMPOOL_BYTES, 10_000);
+        assert_eq!(restarted.load(&path, &state, 12_000).unwrap(), 1);
+        assert!(restarted.contains(&kept));
+        assert!(!restarted.contains(&expires));
+        assert_eq!(restarted.get(&kept).unwrap().added, 5_000);
+
+        // Restarting on a tip where a has no account leaves a's txn out
+        let mut other_tip = Mempool::new();
+        assert_eq!(
+            other_tip
+                .load(&path, &funded_state(&[&b], 100_000_000_000), 0)
+                .unwrap(),
+            1
+        );
+        assert!(other_tip.contains(&expires));
+
+        std::fs::write(&path, [1, 0, 0, 0, 7]).unwrap();
+        assert!(Mempool::new().load(&path, &state, 0).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
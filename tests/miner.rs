@@ -0,0 +1,95 @@
+mod common;
+
+#[cfg(test)]
+mod cpu_miner {
+    use std::thread;
+    use std::time::Duration;
+
+    use gold_2::mempool::*;
+    use gold_2::miner::*;
+    use gold_2::template::*;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    fn template(state: &BlockchainState) -> Block {
+        build_template(
+            state,
+            &Mempool::new(),
+            &RenamePool::new(),
+            pk(&new_keypair()),
+            0,
+        )
+        .block
+    }
+
+    #[test]
+    fn mines_a_valid_block_on_several_threads() {
+        let state = funded_state(&[], 0);
+        let miner = Miner::new(MinerConfig {
+            threads: 4,
+            ..MinerConfig::default()
+        });
+
+        let block = miner.mine(&template(&state), &state.difficulty).unwrap();
+
+        validate_block(&block, &state).unwrap();
+        assert!(miner.hashes() > 0);
+    }
+
+    #[test]
+    fn rolls_the_extra_nonce_once_the_nonces_run_out() {
+        let state = funded_state(&[], 0);
+        let miner = Miner::new(MinerConfig {
+            threads: 2,
+            nonces_per_extra_nonce: 64,
+        });
+
+        let template = template(&state);
+        let block = miner.mine(&template, &state.difficulty).unwrap();
+
+        // 2 leading zero bytes takes about 65k hashes, far more than 64
+        assert!(extra_nonce(&block) > 0);
+        assert!(block.header.nonce < 64);
+        // The template was timed at the tip, long before the roll
+        assert!(block.header.time > template.header.time);
+        validate_block(&block, &state).unwrap();
+    }
+
+    #[test]
+    fn extra_nonce_changes_the_merkle_root_but_not_the_size() {
+        let state = funded_state(&[], 0);
+        let mut block = template(&state);
+        let (root, size) = (block.header.merkle_root, block_size(&block));
+
+        set_extra_nonce(&mut block, 7);
+
+        assert_eq!(extra_nonce(&block), 7);
+        assert_ne!(block.header.merkle_root, root);
+        assert_eq!(block_size(&block), size);
+        assert_eq!(
+            block.header.merkle_root,
+            merkle_root(&block.txns, &block.name_changes)
+        );
+    }
+
+    #[test]
+    fn new_tip_abandons_work() {
+        let state = funded_state(&[], 0);
+        let miner = Miner::new(MinerConfig {
+            threads: 2,
+            ..MinerConfig::default()
+        });
+        let template = template(&state);
+
+        let mining = {
+            let miner = miner.clone();
+            thread::spawn(move || miner.mine(&template, &[0; 32]))
+        };
+
+        thread::sleep(Duration::from_millis(50));
+        miner.tip_changed();
+
+        assert_eq!(mining.join().unwrap(), None);
+    }
+}
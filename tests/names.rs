@@ -0,0 +1,86 @@
+mod common;
+
+#[cfg(test)]
+mod name_index {
+    use gold_2::*;
+
+    use crate::common::*;
+
+    const BALANCE: u64 = 1_000_000_000_000;
+
+    fn names_of(state: &BlockchainState, key: [u8; 32]) -> Vec<&str> {
+        state
+            .owned_names
+            .get(&key)
+            .map_or(vec![], |names| names.iter().map(String::as_str).collect())
+    }
+
+    #[test]
+    fn claims_and_transfers_move_names_between_owners() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let mut state = funded_state(&[&a, &b], BALANCE);
+
+        let block = mined_block(
+            &state,
+            [1; 32],
+            vec![],
+            vec![
+                signed_rename(&a, &a, "gold"),
+                signed_rename(&a, &a, "alpha"),
+            ],
+        );
+        let first = push_block(block, &mut state).unwrap();
+        assert_eq!(names_of(&state, pk(&a)), vec!["alpha", "gold"]);
+
+        // a hands gold over to b
+        let block = mined_block(&state, [1; 32], vec![], vec![signed_rename(&b, &a, "gold")]);
+        let second = push_block(block, &mut state).unwrap();
+        assert_eq!(names_of(&state, pk(&a)), vec!["alpha"]);
+        assert_eq!(names_of(&state, pk(&b)), vec!["gold"]);
+        assert_eq!(state.owned_names, index_names(&state.name_set));
+
+        pop_block(&second, &mut state);
+        assert_eq!(names_of(&state, pk(&a)), vec!["alpha", "gold"]);
+        assert!(!state.owned_names.contains_key(&pk(&b)));
+
+        pop_block(&first, &mut state);
+        assert!(state.owned_names.is_empty());
+    }
+
+    #[test]
+    fn a_name_claimed_twice_in_a_block_unwinds_to_nobody() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let mut state = funded_state(&[&a, &b], BALANCE);
+        let before = state.clone();
+
+        let block = mined_block(
+            &state,
+            [1; 32],
+            vec![],
+            vec![signed_rename(&a, &a, "gold"), signed_rename(&b, &a, "gold")],
+        );
+        let undo = push_block(block, &mut state).unwrap();
+        assert_eq!(names_of(&state, pk(&b)), vec!["gold"]);
+        assert!(!state.owned_names.contains_key(&pk(&a)));
+
+        pop_block(&undo, &mut state);
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn set_name_owner_keeps_both_maps_in_step() {
+        let (a, b) = ([1; 32], [2; 32]);
+        let mut names = Names::new();
+        let mut owned = OwnedNames::new();
+
+        set_name_owner(&mut names, &mut owned, "x", Some(a));
+        set_name_owner(&mut names, &mut owned, "y", Some(a));
+        set_name_owner(&mut names, &mut owned, "x", Some(b));
+        assert_eq!(owned, index_names(&names));
+
+        set_name_owner(&mut names, &mut owned, "y", None);
+        set_name_owner(&mut names, &mut owned, "x", None);
+        assert!(names.is_empty());
+        assert!(owned.is_empty());
+    }
+}
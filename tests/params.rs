@@ -0,0 +1,94 @@
+mod common;
+
+#[cfg(test)]
+mod networks {
+    use gold_2::chain::{BlockStatus, Chain};
+    use gold_2::net::*;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    const NETWORKS: [Network; 3] = [Network::Mainnet, Network::Testnet, Network::Regtest];
+
+    fn state_on(network: Network, funded: &[&secp256k1::Keypair]) -> BlockchainState {
+        let mut state = network.genesis_state();
+        for key in funded.iter() {
+            state.account_set.insert(pk(key), 1_000_000_000_000);
+        }
+        state
+    }
+
+    #[test]
+    fn networks_are_distinct() {
+        for (i, a) in NETWORKS.iter().enumerate() {
+            assert_eq!(a.name().parse::<Network>().unwrap(), *a);
+
+            for b in NETWORKS[i + 1..].iter() {
+                assert_ne!(a.genesis_hash(), b.genesis_hash());
+                assert_ne!(a.magic(), b.magic());
+                assert_ne!(a.default_port(), b.default_port());
+            }
+        }
+    }
+
+    #[test]
+    fn txn_signatures_are_network_specific() {
+        let alice = new_keypair();
+        let mut txn = Txn {
+            sender: Address::Key(pk(&alice)),
+            recievers: vec![(Address::Key([2; 32]), 1_000)],
+            signature: [0; 64],
+            fee: 0,
+        };
+        finalize_txn(&mut txn, &alice, Network::Testnet);
+
+        assert!(check_txn(&txn, &state_on(Network::Testnet, &[&alice])).is_ok());
+        assert!(check_txn(&txn, &state_on(Network::Mainnet, &[&alice])).is_err());
+    }
+
+    #[test]
+    fn rename_signatures_are_network_specific() {
+        let alice = new_keypair();
+        let mut op = RenameOp {
+            pk: pk(&alice),
+            sig: [0; 64],
+            new_name: "alice".into(),
+            fee: 0,
+        };
+        finalize_name_change(&mut op, &alice, Network::Testnet);
+
+        assert!(check_name_change(&op, &state_on(Network::Testnet, &[&alice])).is_ok());
+        assert!(check_name_change(&op, &state_on(Network::Mainnet, &[&alice])).is_err());
+    }
+
+    #[test]
+    fn messages_from_other_networks_are_rejected() {
+        let message = Message::GetBlock([1; 32]);
+        let data = encode_message(&message, Network::Testnet);
+
+        assert!(decode_message(&data, Network::Testnet).is_ok());
+        assert!(decode_message(&data, Network::Mainnet).is_err());
+    }
+
+    #[test]
+    fn blocks_from_other_networks_do_not_connect() {
+        let miner = pk(&new_keypair());
+        let regtest_block = mined_block(&Network::Regtest.genesis_state(), miner, vec![], vec![]);
+
+        // Give testnet regtest's difficulty so the block only fails on its genesis
+        let mut testnet = Network::Testnet.genesis_state();
+        testnet.difficulty = Network::Regtest.initial_difficulty();
+
+        let mut chain = Chain::new(testnet);
+        assert_eq!(
+            chain.add_block(regtest_block.clone()).unwrap(),
+            BlockStatus::Orphan
+        );
+
+        let mut chain = Chain::new(Network::Regtest.genesis_state());
+        assert!(matches!(
+            chain.add_block(regtest_block).unwrap(),
+            BlockStatus::Connected { .. }
+        ));
+    }
+}
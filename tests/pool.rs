@@ -0,0 +1,267 @@
+mod common;
+
+#[cfg(test)]
+mod pool {
+    use std::sync::{Arc, Mutex};
+
+    use gold_2::chain::Chain;
+    use gold_2::context::NodeContext;
+    use gold_2::mempool::*;
+    use gold_2::miner::set_extra_nonce;
+    use gold_2::net::node::Node;
+    use gold_2::pool::*;
+    use gold_2::template::build_template;
+    use gold_2::*;
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::common::*;
+
+    // Every hash is a share
+    const ANY_HASH: [u8; 32] = [255; 32];
+
+    fn pool_with(share_difficulty: [u8; 32]) -> Pool {
+        Pool::new(PoolConfig {
+            payout: pk(&new_keypair()),
+            share_difficulty,
+            job_interval: DEFAULT_JOB_INTERVAL,
+        })
+    }
+
+    fn add_job(pool: &mut Pool, state: &BlockchainState) -> (u64, bool) {
+        let template = build_template(
+            state,
+            &Mempool::new(),
+            &RenamePool::new(),
+            pool.config().payout,
+            0,
+        );
+        let clean = pool.new_job(template, state.difficulty, state.height + 1);
+        (pool.current_job().unwrap().id, clean)
+    }
+
+    // The first nonce whose header does or doesn't meet `target`
+    fn find_nonce(job: &Job, extra_nonce: u64, target: &[u8; 32], meets: bool) -> u64 {
+        let mut block = job.block.clone();
+        set_extra_nonce(&mut block, extra_nonce);
+        (0..)
+            .find(|nonce| {
+                block.header.nonce = *nonce;
+                meets_difficulty(&hash_header(&block.header), target) == meets
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn shares_are_counted_per_worker() {
+        let state = funded_state(&[], 0);
+        let mut pool = pool_with(ANY_HASH);
+        let (job_id, _) = add_job(&mut pool, &state);
+        let job = pool.current_job().unwrap().clone();
+
+        let (a, b) = (pool.open_session(), pool.open_session());
+        let (extra_a, extra_b) = (session_extra_nonce(a), session_extra_nonce(b));
+        let nonce_a = find_nonce(&job, extra_a, &state.difficulty, false);
+        let nonce_b = find_nonce(&job, extra_b, &state.difficulty, false);
+
+        let share = pool.submit("a", a, job_id, extra_a, nonce_a, 0).unwrap();
+        assert_eq!(share.block, None);
+        pool.submit("a", a, job_id, extra_a, nonce_a + 1_000_000, 0)
+            .unwrap();
+        pool.submit("b", b, job_id, extra_b, nonce_b, 0).unwrap();
+
+        assert_eq!(pool.workers()["a"].accepted, 2);
+        assert_eq!(pool.workers()["b"].accepted, 1);
+        assert_eq!(pool.workers()["b"].rejected, 0);
+    }
+
+    #[test]
+    fn bad_shares_are_rejected_and_counted() {
+        let state = funded_state(&[], 0);
+        let mut pool = pool_with(TRIVIAL_DIFFICULTY);
+        let (job_id, _) = add_job(&mut pool, &state);
+        let job = pool.current_job().unwrap().clone();
+        let session = pool.open_session();
+        let extra = session_extra_nonce(session);
+
+        let weak = find_nonce(&job, extra, &TRIVIAL_DIFFICULTY, false);
+        let good = find_nonce(&job, extra, &TRIVIAL_DIFFICULTY, true);
+
+        assert!(matches!(
+            pool.submit("w", session, job_id, extra, weak, 0),
+            Err(Error::ShareError(_))
+        ));
+        assert!(pool
+            .submit("w", session, job_id + 1, extra, good, 0)
+            .is_err());
+        assert!(pool
+            .submit(
+                "w",
+                session,
+                job_id,
+                session_extra_nonce(session + 1),
+                good,
+                0
+            )
+            .is_err());
+
+        pool.submit("w", session, job_id, extra, good, 0).unwrap();
+        assert!(pool.submit("w", session, job_id, extra, good, 0).is_err());
+
+        assert_eq!(pool.workers()["w"].accepted, 1);
+        assert_eq!(pool.workers()["w"].rejected, 4);
+    }
+
+    #[test]
+    fn the_idlest_worker_makes_way_when_full() {
+        let mut pool = pool_with(ANY_HASH);
+        let session = pool.open_session();
+        let extra = session_extra_nonce(session);
+
+        // No job has been handed out, so every share is rejected, but each still names a worker
+        for i in 0..MAX_WORKERS as u64 {
+            assert!(pool
+                .submit(&i.to_string(), session, 1, extra, 0, i)
+                .is_err());
+        }
+        assert!(pool.submit("0", session, 1, extra, 0, 5_000).is_err());
+        assert!(pool.submit("new", session, 1, extra, 0, 5_000).is_err());
+
+        assert_eq!(pool.workers().len(), MAX_WORKERS);
+        assert!(pool.workers().contains_key("0"));
+        assert!(!pool.workers().contains_key("1"));
+        assert_eq!(pool.workers()["new"].last_seen, 5_000);
+    }
+
+    #[test]
+    fn full_difficulty_shares_are_valid_blocks() {
+        let mut state = funded_state(&[], 0);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        let mut pool = pool_with(ANY_HASH);
+        let (job_id, _) = add_job(&mut pool, &state);
+        let job = pool.current_job().unwrap().clone();
+        let session = pool.open_session();
+        let extra = session_extra_nonce(session) + 5;
+
+        let nonce = find_nonce(&job, extra, &state.difficulty, true);
+        let share = pool.submit("w", session, job_id, extra, nonce, 0).unwrap();
+
+        let block = share.block.unwrap();
+        assert_eq!(hash_header(&block.header), share.hash);
+        validate_block(&block, &state).unwrap();
+
+        // Only counted once the node has connected it
+        assert_eq!(pool.workers()["w"].blocks, 0);
+        pool.block_connected("w");
+        assert_eq!(pool.workers()["w"].blocks, 1);
+    }
+
+    #[test]
+    fn a_new_tip_makes_old_jobs_stale() {
+        let mut state = funded_state(&[], 0);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        let mut pool = pool_with(ANY_HASH);
+        let session = pool.open_session();
+        let extra = session_extra_nonce(session);
+
+        let (first, clean) = add_job(&mut pool, &state);
+        assert!(clean);
+        let (second, clean) = add_job(&mut pool, &state);
+        assert!(!clean);
+        pool.submit("w", session, first, extra, 0, 0).unwrap();
+
+        for _ in 0..MAX_JOBS {
+            add_job(&mut pool, &state);
+        }
+        assert!(pool.submit("w", session, second, extra, 0, 0).is_err());
+
+        let block = mined_block(&state, [1; 32], vec![], vec![]);
+        push_block(block, &mut state).unwrap();
+        let (latest, clean) = add_job(&mut pool, &state);
+        assert!(clean);
+        assert!(pool.submit("w", session, latest - 1, extra, 1, 0).is_err());
+        pool.submit("w", session, latest, extra, 1, 0).unwrap();
+    }
+
+    async fn read_message(lines: &mut tokio::io::Lines<BufReader<TcpStream>>) -> Value {
+        serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn workers_mine_blocks_over_tcp() {
+        let mut state = funded_state(&[], 0);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        let node = NodeContext::new(Node::new(Chain::new(state)));
+        let pool = Arc::new(Mutex::new(pool_with(ANY_HASH)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, node.clone(), pool.clone()));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let authorize = json!({ "id": 1, "method": "authorize", "params": { "worker": "rig" } });
+        stream
+            .write_all(format!("{authorize}\n").as_bytes())
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(stream).lines();
+
+        let response = read_message(&mut lines).await;
+        assert_eq!(response["id"], 1);
+        let job = read_message(&mut lines).await;
+        assert_eq!(job["method"], "job");
+        let job: JobParams = serde_json::from_value(job["params"].clone()).unwrap();
+        assert_eq!(job.height, 1);
+
+        let data = hex::decode(&job.block).unwrap();
+        let mut block = decode_block(&mut data.as_slice()).unwrap();
+        while !meets_difficulty(&hash_header(&block.header), &TRIVIAL_DIFFICULTY) {
+            block.header.nonce += 1;
+        }
+
+        let submit = json!({
+            "id": 2,
+            "method": "submit",
+            "params": { "job_id": job.job_id, "extra_nonce": job.extra_nonce, "nonce": block.header.nonce },
+        });
+        lines
+            .get_mut()
+            .write_all(format!("{submit}\n").as_bytes())
+            .await
+            .unwrap();
+
+        let response = read_message(&mut lines).await;
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["error"], Value::Null);
+        assert_eq!(
+            response["result"]["block"],
+            hex::encode(hash_header(&block.header))
+        );
+
+        // The pool moves on to the new tip
+        let job = read_message(&mut lines).await;
+        assert_eq!(job["params"]["height"], 2);
+        assert_eq!(job["params"]["clean"], true);
+
+        assert_eq!(node.tip().state.height, 1);
+        assert_eq!(pool.lock().unwrap().workers()["rig"].blocks, 1);
+    }
+
+    #[tokio::test]
+    async fn workers_sending_overlong_lines_are_dropped() {
+        let node = NodeContext::new(Node::new(Chain::new(funded_state(&[], 0))));
+        let pool = Arc::new(Mutex::new(pool_with(ANY_HASH)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, node, pool));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&[b' '; MAX_LINE_SIZE]).await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+
+        // Either a clean close or a reset, depending on whether the kernel still held unread bytes
+        assert!(!matches!(lines.next_line().await, Ok(Some(_))));
+    }
+}
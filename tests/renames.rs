@@ -0,0 +1,283 @@
+mod common;
+
+#[cfg(test)]
+mod rename_pool {
+    use gold_2::chain::Chain;
+    use gold_2::mempool::*;
+    use gold_2::net::node::Node;
+    use gold_2::net::sim::*;
+    use gold_2::*;
+    use secp256k1::{Keypair, Secp256k1};
+
+    use crate::common::*;
+
+    const BALANCE: u64 = 1_000_000_000_000;
+
+    // Like signed_rename, but pays `extra` on top of the minimum fee
+    fn signed_rename_paying(
+        new_owner: &Keypair,
+        signer: &Keypair,
+        name: &str,
+        extra: u64,
+    ) -> RenameOp {
+        let mut op = signed_rename(new_owner, signer, name);
+        op.fee += extra;
+        op.sig = *Secp256k1::new()
+            .sign_schnorr(&name_change_signing_data(&op, Network::Regtest), signer)
+            .as_byte_array();
+        op
+    }
+
+    #[test]
+    fn highest_fee_claim_wins() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b], BALANCE);
+        let mut pool = RenamePool::new();
+
+        let first = pool
+            .add_op(signed_rename(&a, &a, "gold"), &state, 0)
+            .unwrap();
+
+        assert!(matches!(
+            pool.add_op(signed_rename(&b, &b, "gold"), &state, 0),
+            Err(Error::MempoolError(_))
+        ));
+
+        let better = pool
+            .add_op(signed_rename_paying(&b, &b, "gold", 1), &state, 0)
+            .unwrap();
+
+        assert_eq!(pool.len(), 1);
+        assert!(!pool.contains(&first));
+        assert_eq!(pool.get("gold").unwrap().hash, better);
+        assert_eq!(pool.pending_fees(&pk(&a)), 0);
+    }
+
+    #[test]
+    fn claims_are_dropped_when_the_name_changes_owner() {
+        let (a, b, c) = (new_keypair(), new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b, &c], BALANCE);
+        let mut pool = RenamePool::new();
+
+        let claim = pool
+            .add_op(signed_rename(&b, &b, "gold"), &state, 0)
+            .unwrap();
+        let other = pool
+            .add_op(signed_rename(&b, &b, "silver"), &state, 0)
+            .unwrap();
+
+        let block = mined_block(&state, pk(&c), vec![], vec![signed_rename(&c, &c, "gold")]);
+        let mut next = state.clone();
+        push_block(block.clone(), &mut next).unwrap();
+
+        pool.remove_block(&block);
+        let removed = pool.revalidate(&next);
+
+        assert_eq!(removed, vec![claim]);
+        assert!(pool.contains(&other));
+        assert_eq!(
+            pool.pending_fees(&pk(&b)),
+            pool.get("silver").unwrap().op.fee
+        );
+    }
+
+    #[test]
+    fn confirmed_claims_cannot_be_replayed() {
+        let a = new_keypair();
+        let state = funded_state(&[&a], BALANCE);
+        let op = signed_rename(&a, &a, "gold");
+
+        let block = mined_block(&state, pk(&a), vec![], vec![op.clone()]);
+        let mut next = state.clone();
+        push_block(block, &mut next).unwrap();
+
+        assert!(matches!(
+            RenamePool::new().add_op(op, &next, 0),
+            Err(Error::MempoolError(_))
+        ));
+    }
+
+    #[test]
+    fn new_owner_must_afford_every_pending_fee() {
+        let a = new_keypair();
+        let op = signed_rename(&a, &a, "gold");
+        let state = funded_state(&[&a], op.fee + op.fee / 2);
+        let mut pool = RenamePool::new();
+        let min_fee = op.fee;
+
+        pool.add_op(op, &state, 0).unwrap();
+        assert!(pool
+            .add_op(signed_rename(&a, &a, "silver"), &state, 0)
+            .is_err());
+
+        // Nor can a fee too big to add to what's already pending
+        assert!(pool
+            .add_op(
+                signed_rename_paying(&a, &a, "iron", u64::MAX - min_fee),
+                &state,
+                0
+            )
+            .is_err());
+
+        // An account that doesn't exist can't pay at all
+        let nobody = new_keypair();
+        assert!(pool
+            .add_op(signed_rename(&nobody, &nobody, "bronze"), &state, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn full_pool_evicts_the_worst_paying_and_raises_the_minimum_fee() {
+        let (a, b, c) = (new_keypair(), new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b, &c], BALANCE);
+        let low = signed_rename(&a, &a, "gold");
+        let size = encode_name_change(&low).len();
+        let extra = size as u64 * NAME_CHANGE_FEES_PER_BYTE;
+
+        // Room for two renames
+        let mut pool = RenamePool::with_max_bytes(size * 2 + size / 2);
+
+        let low = pool.add_op(low, &state, 0).unwrap();
+        pool.add_op(signed_rename_paying(&b, &b, "iron", extra), &state, 0)
+            .unwrap();
+        pool.add_op(signed_rename_paying(&c, &c, "lead", extra * 2), &state, 0)
+            .unwrap();
+
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.contains(&low));
+        assert_eq!(pool.pending_fees(&pk(&a)), 0);
+
+        // Paying what the evicted rename paid is no longer enough, until the minimum decays
+        assert!(pool.min_fee_rate(0) > NAME_CHANGE_FEES_PER_BYTE);
+        assert!(matches!(
+            pool.add_op(signed_rename(&a, &a, "zinc"), &state, 0),
+            Err(Error::MempoolError(_))
+        ));
+        assert_eq!(
+            pool.min_fee_rate(MIN_FEE_HALF_LIFE * 64),
+            NAME_CHANGE_FEES_PER_BYTE
+        );
+
+        // A rename that would be the first to go is turned away without evicting anything
+        let mut pool = RenamePool::with_max_bytes(size + size / 2);
+        let kept = pool
+            .add_op(signed_rename_paying(&b, &b, "iron", extra), &state, 0)
+            .unwrap();
+        assert!(matches!(
+            pool.add_op(signed_rename(&a, &a, "gold"), &state, 0),
+            Err(Error::MempoolError(_))
+        ));
+        assert!(pool.contains(&kept));
+        assert_eq!(pool.bytes(), size);
+    }
+
+    #[test]
+    fn blocks_cannot_charge_renames_to_accounts_that_cant_pay() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let op = signed_rename(&a, &a, "gold");
+        let state = funded_state(&[&a], op.fee);
+
+        // No account at all
+        let block = mined_block(&state, pk(&a), vec![], vec![signed_rename(&b, &b, "gold")]);
+        assert!(matches!(
+            validate_block(&block, &state),
+            Err(Error::TxnValidationError(_))
+        ));
+        let mut chain = Chain::new(state.clone());
+        assert!(chain.add_block(block.clone()).is_err());
+        assert_eq!(chain.state, state);
+
+        // Pushing it anyway is turned away before the coinbase is paid out
+        let mut pushed = state.clone();
+        assert!(push_block(block, &mut pushed).is_err());
+        assert_eq!(pushed, state);
+
+        // The fee has to fit in what the payer's txns in the block leave over
+        let spend = signed_txn(&a, pk(&b), 1);
+        let block = mined_block(&state, pk(&a), vec![spend], vec![op.clone()]);
+        assert!(validate_block(&block, &state).is_err());
+
+        let block = mined_block(&state, pk(&a), vec![], vec![op]);
+        assert!(validate_block(&block, &state).is_ok());
+    }
+
+    #[test]
+    fn disconnected_renames_win_over_pending_ones() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let (gold, silver) = (
+            signed_rename(&a, &a, "gold"),
+            signed_rename(&a, &a, "silver"),
+        );
+        let spend = signed_txn(&a, pk(&b), 1_000);
+        // After the spend, a can pay for one of the claims but not both
+        let balance = gold.fee + silver.fee + txn_total_spend(&spend).unwrap() - 1;
+        let mut genesis = funded_state(&[&a], balance);
+        genesis.difficulty = TRIVIAL_DIFFICULTY;
+        let mut node = Node::new(Chain::new(genesis.clone()));
+
+        let a1 = mined_block(&genesis, pk(&b), vec![], vec![gold.clone()]);
+        node.add_block(a1, 0).unwrap();
+        node.submit_rename(silver.clone(), 0).unwrap();
+
+        let b1 = mined_block(&genesis, pk(&b), vec![spend], vec![]);
+        let mut next = genesis.clone();
+        push_block(b1.clone(), &mut next).unwrap();
+        let b2 = mined_block(&next, pk(&b), vec![], vec![]);
+        node.add_block(b1, 0).unwrap();
+        node.add_block(b2, 0).unwrap();
+
+        assert_eq!(node.renames.len(), 1);
+        assert!(node.renames.contains(&name_change_hash(&gold)));
+        assert!(!node.renames.contains(&name_change_hash(&silver)));
+    }
+
+    #[test]
+    fn renames_relay_and_confirm_across_the_network() {
+        let a = new_keypair();
+        let mut genesis = funded_state(&[&a], BALANCE);
+        genesis.difficulty = TRIVIAL_DIFFICULTY;
+
+        let mut sim = Simulator::new(3, &genesis, 11);
+        sim.connect(0, 1);
+        sim.connect(1, 2);
+
+        let op = signed_rename(&a, &a, "gold");
+        sim.submit_rename(0, op.clone()).unwrap();
+        sim.run_until_idle();
+
+        assert!(sim.nodes.iter().all(|n| n.renames.len() == 1));
+
+        let block = mined_block(&sim.nodes[2].chain.state, pk(&a), vec![], vec![op]);
+        sim.submit_block(2, block).unwrap();
+        sim.run_until_idle();
+
+        assert!(sim.states_converged());
+        assert!(sim.nodes.iter().all(|n| n.renames.is_empty()));
+        assert_eq!(sim.nodes[0].chain.state.name_set["gold"], pk(&a));
+    }
+
+    #[test]
+    fn saved_pool_reloads_only_what_is_still_valid() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let state = funded_state(&[&a, &b], BALANCE);
+        let path = temp_path("renames.dat");
+        let mut pool = RenamePool::new();
+
+        pool.add_op(signed_rename(&a, &a, "gold"), &state, 0)
+            .unwrap();
+        let silver = pool
+            .add_op(signed_rename(&b, &b, "silver"), &state, 0)
+            .unwrap();
+        pool.save(&path).unwrap();
+
+        // While we were down, someone else took "gold"
+        let block = mined_block(&state, pk(&b), vec![], vec![signed_rename(&b, &b, "gold")]);
+        let mut next = state.clone();
+        push_block(block, &mut next).unwrap();
+
+        let mut restarted = RenamePool::new();
+        assert_eq!(restarted.load(&path, &next, 0).unwrap(), 1);
+        assert!(restarted.contains(&silver));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+mod common;
+
+#[cfg(test)]
+mod network_simulation {
+    use gold_2::chain::BlockStatus;
+    use gold_2::net::sim::*;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    fn genesis() -> BlockchainState {
+        let mut state = funded_state(&[], 0);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        state
+    }
+
+    // Mines `count` empty blocks on top of `node`'s tip
+    fn mine(sim: &mut Simulator, node: usize, count: usize) {
+        let miner = pk(&new_keypair());
+
+        for _ in 0..count {
+            let block = mined_block(&sim.nodes[node].chain.state, miner, vec![], vec![]);
+            let status = sim.submit_block(node, block).unwrap();
+            assert!(matches!(status, BlockStatus::Connected { .. }));
+        }
+    }
+
+    #[test]
+    fn blocks_propagate_along_a_line() {
+        let mut sim = Simulator::new(4, &genesis(), 1);
+        sim.connect(0, 1);
+        sim.connect(1, 2);
+        sim.connect(2, 3);
+
+        mine(&mut sim, 0, 3);
+        sim.run_until_idle();
+
+        assert!(sim.tips_converged());
+        assert!(sim.states_converged());
+        assert_eq!(sim.nodes[3].chain.height(), 3);
+    }
+
+    #[test]
+    fn partition_heals_onto_longest_branch() {
+        let mut sim = Simulator::new(4, &genesis(), 2);
+        sim.connect_all();
+
+        mine(&mut sim, 0, 1);
+        sim.run_until_idle();
+
+        sim.partition(&[&[0, 1], &[2, 3]]);
+        mine(&mut sim, 0, 2);
+        mine(&mut sim, 3, 3);
+        sim.run_until_idle();
+
+        assert!(!sim.tips_converged());
+        assert_eq!(sim.nodes[1].chain.height(), 3);
+        assert_eq!(sim.nodes[2].chain.height(), 4);
+
+        let winning_tip = sim.nodes[3].chain.tip_hash();
+        sim.heal();
+        sim.run_until_idle();
+
+        assert!(sim.tips_converged());
+        assert!(sim.states_converged());
+        assert_eq!(sim.nodes[0].chain.tip_hash(), winning_tip);
+    }
+
+    #[test]
+    fn latency_delays_delivery() {
+        let mut sim = Simulator::new(2, &genesis(), 3);
+        sim.connect(0, 1);
+        sim.set_latency(0, 1, 1_000);
+
+        mine(&mut sim, 0, 1);
+
+        sim.run_for(999);
+        assert_eq!(sim.nodes[1].chain.height(), 0);
+
+        sim.run_until_idle();
+        assert_eq!(sim.nodes[1].chain.height(), 1);
+        assert!(sim.now() >= 1_000);
+    }
+
+    #[test]
+    fn dropped_messages_recover_after_reannounce() {
+        let mut sim = Simulator::new(3, &genesis(), 4);
+        sim.connect_all();
+        sim.set_drop_rate(1.0);
+
+        mine(&mut sim, 0, 2);
+        sim.run_until_idle();
+
+        assert!(sim.dropped > 0);
+        assert_eq!(sim.nodes[1].chain.height(), 0);
+
+        sim.set_drop_rate(0.0);
+        sim.heal();
+        sim.run_until_idle();
+
+        assert!(sim.tips_converged());
+        assert!(sim.states_converged());
+    }
+}
@@ -0,0 +1,105 @@
+mod common;
+
+#[cfg(test)]
+mod stats {
+    use gold_2::chain::Chain;
+    use gold_2::stats::*;
+    use gold_2::*;
+
+    use crate::common::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= b.abs() * 1e-9
+    }
+
+    // A state at `height` whose blocks came `spacing` seconds apart
+    fn state_at(height: usize, spacing: u64) -> BlockchainState {
+        let mut state = funded_state(&[], 0);
+        for i in 1..=height as u64 {
+            push_to_front(&mut state.last_720_times, 100 + i * spacing);
+        }
+        state.height = height;
+        state
+    }
+
+    #[test]
+    fn expected_hashes_follow_the_target() {
+        assert!(close(expected_hashes(&[255; 32]), 1.0));
+        assert!(close(expected_hashes(&TRIVIAL_DIFFICULTY), 256.0));
+        assert!(close(expected_hashes(&EASY_DIFFICULTY), 65_536.0));
+        assert!(expected_hashes(&Network::Mainnet.initial_difficulty()) > 1e7);
+    }
+
+    #[test]
+    fn intervals_only_count_real_blocks() {
+        assert_eq!(
+            average_block_interval(&state_at(0, 60).last_720_times, 0),
+            None
+        );
+
+        let state = state_at(3, 60);
+        assert_eq!(
+            average_block_interval(&state.last_720_times, state.height),
+            Some(60.0)
+        );
+
+        let state = state_at(1_000, 30);
+        assert_eq!(
+            average_block_interval(&state.last_720_times, state.height),
+            Some(30.0)
+        );
+    }
+
+    #[test]
+    fn hashrate_is_work_over_spacing() {
+        let mut state = state_at(10, 64);
+        state.difficulty = EASY_DIFFICULTY;
+        assert!(close(estimate_hashrate(&state).unwrap(), 1_024.0));
+
+        assert_eq!(estimate_hashrate(&state_at(0, 64)), None);
+        assert_eq!(estimate_hashrate(&state_at(10, 0)), None);
+    }
+
+    #[test]
+    fn retargets_come_every_interval() {
+        assert_eq!(next_retarget_height(0), RETARGET_INTERVAL);
+        assert_eq!(
+            next_retarget_height(RETARGET_INTERVAL - 1),
+            RETARGET_INTERVAL
+        );
+        assert_eq!(
+            next_retarget_height(RETARGET_INTERVAL),
+            2 * RETARGET_INTERVAL
+        );
+
+        let stats = chain_stats(&state_at(20, 10));
+        assert_eq!(stats.height, 20);
+        assert_eq!(
+            stats.seconds_to_retarget,
+            Some(10.0 * (RETARGET_INTERVAL - 20) as f64)
+        );
+    }
+
+    #[test]
+    fn history_covers_the_main_branch() {
+        let mut state = funded_state(&[], 0);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        let mut chain = Chain::new(state);
+
+        for _ in 0..3 {
+            let block = mined_block(&chain.state, [1; 32], vec![], vec![]);
+            chain.add_block(block).unwrap();
+        }
+
+        let history = difficulty_history(&chain, 0, 10);
+        assert_eq!(
+            history,
+            (0..=3).map(|h| (h, TRIVIAL_DIFFICULTY)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            difficulty_history(&chain, 2, 1),
+            vec![(2, TRIVIAL_DIFFICULTY)]
+        );
+        assert!(difficulty_history(&chain, 4, 10).is_empty());
+    }
+}
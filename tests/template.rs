@@ -0,0 +1,149 @@
+mod common;
+
+#[cfg(test)]
+mod block_template {
+    use gold_2::mempool::*;
+    use gold_2::template::*;
+    use gold_2::*;
+    use secp256k1::Keypair;
+
+    use crate::common::*;
+
+    const BALANCE: u64 = 1_000_000_000_000_000;
+
+    fn state_for(keys: &[&Keypair]) -> BlockchainState {
+        let mut state = funded_state(keys, BALANCE);
+        state.difficulty = TRIVIAL_DIFFICULTY;
+        state
+    }
+
+    // Grinds the nonce, then checks the block is valid and can be pushed
+    fn mine_and_check(template: &BlockTemplate, state: &BlockchainState) {
+        let mut block = template.block.clone();
+        while !meets_difficulty(&hash_header(&block.header), &state.difficulty) {
+            block.header.nonce += 1;
+        }
+
+        validate_block(&block, state).unwrap();
+        push_block(block, &mut state.clone()).unwrap();
+    }
+
+    // Queues txns from `sender` paying `extra` over the minimum fee until `bytes` worth are waiting
+    fn fill(
+        mempool: &mut Mempool,
+        state: &BlockchainState,
+        sender: &Keypair,
+        bytes: usize,
+        extra: u64,
+    ) {
+        let to = pk(&new_keypair());
+        let mut amount = 1;
+
+        while mempool.bytes() < bytes {
+            let txn = signed_txn_paying(sender, to, amount, extra);
+            mempool.add_txn(txn, TxnSource::Local, state, 0).unwrap();
+            amount += 1;
+        }
+    }
+
+    #[test]
+    fn empty_pools_give_a_bare_coinbase() {
+        let state = state_for(&[]);
+        let miner = pk(&new_keypair());
+
+        let template = build_template(&state, &Mempool::new(), &RenamePool::new(), miner, 0);
+
+        assert_eq!(
+            template.block.txns,
+            vec![coinbase_txn(miner, DEFAULT_COINBASE)]
+        );
+        assert_eq!(template.block.header.time, state.previous_block_header.time);
+        assert_eq!(template.size, block_size(&template.block));
+        mine_and_check(&template, &state);
+    }
+
+    #[test]
+    fn minimum_fee_txns_stop_at_the_free_space() {
+        let a = new_keypair();
+        let state = state_for(&[&a]);
+        let median = median_block_size(&state.last_100_block_sizes);
+        let mut mempool = Mempool::new();
+
+        fill(
+            &mut mempool,
+            &state,
+            &a,
+            penalty_free_block_size(median) + 2_000,
+            0,
+        );
+
+        let template = build_template(&state, &mempool, &RenamePool::new(), pk(&a), 0);
+
+        assert!(template.size <= penalty_free_block_size(median));
+        assert_eq!(template.reward, DEFAULT_COINBASE);
+        assert!(template.fees > 0);
+        mine_and_check(&template, &state);
+    }
+
+    #[test]
+    fn well_paying_txns_are_worth_the_penalty_but_not_the_cap() {
+        let a = new_keypair();
+        let mut state = state_for(&[&a]);
+        // At the genesis median the cap and the free space are the same size
+        state.last_100_block_sizes = [20_000; 100];
+        let median = median_block_size(&state.last_100_block_sizes);
+        let mut mempool = Mempool::new();
+
+        // Paying far more per byte than the coinbase loses
+        fill(
+            &mut mempool,
+            &state,
+            &a,
+            max_block_size(median) + 2_000,
+            1_000_000_000_000,
+        );
+
+        let template = build_template(&state, &mempool, &RenamePool::new(), pk(&a), 0);
+
+        assert!(template.size > penalty_free_block_size(median));
+        assert!(template.size <= max_block_size(median));
+        assert!(template.reward < DEFAULT_COINBASE);
+        assert!(template.reward + template.fees > DEFAULT_COINBASE);
+        mine_and_check(&template, &state);
+    }
+
+    #[test]
+    fn renames_fill_free_space_when_the_payer_can_afford_them() {
+        let (a, b) = (new_keypair(), new_keypair());
+        let poor = new_keypair();
+        let mut state = state_for(&[&a, &b]);
+        let mut mempool = Mempool::new();
+        let mut renames = RenamePool::new();
+
+        let txn = signed_txn(&a, pk(&b), 1_000);
+        let op = signed_rename(&poor, &poor, "poor");
+        let fee = op.fee;
+        state.account_set.insert(pk(&poor), fee);
+
+        mempool.add_txn(txn, TxnSource::Local, &state, 0).unwrap();
+        renames
+            .add_op(signed_rename(&b, &b, "gold"), &state, 0)
+            .unwrap();
+        renames.add_op(op, &state, 0).unwrap();
+
+        // poor spends some of their balance before the template is built
+        state.account_set.insert(pk(&poor), fee - 1);
+
+        let template = build_template(&state, &mempool, &renames, pk(&b), 0);
+
+        assert_eq!(template.block.txns.len(), 2);
+        let names: Vec<&str> = template
+            .block
+            .name_changes
+            .iter()
+            .map(|op| op.new_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["gold"]);
+        mine_and_check(&template, &state);
+    }
+}
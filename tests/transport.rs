@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod peer_transport {
+    use std::collections::HashSet;
+
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use gold_2::net::transport::*;
+    use gold_2::*;
+
+    async fn connect(
+        a: &NodeIdentity,
+        b: &NodeIdentity,
+        a_network: Network,
+        b_policy: PeerPolicy,
+    ) -> (
+        Result<Session, Error>,
+        Result<Session, Error>,
+        DuplexStream,
+        DuplexStream,
+    ) {
+        let (mut a_stream, mut b_stream) = duplex(4096);
+
+        let (a_session, b_session) = tokio::join!(
+            handshake(
+                &mut a_stream,
+                a,
+                a_network,
+                Role::Initiator,
+                &PeerPolicy::Any
+            ),
+            handshake(
+                &mut b_stream,
+                b,
+                Network::Regtest,
+                Role::Responder,
+                &b_policy
+            ),
+        );
+
+        (a_session, b_session, a_stream, b_stream)
+    }
+
+    #[tokio::test]
+    async fn encrypted_messages_round_trip() {
+        let (a, b) = (NodeIdentity::generate(), NodeIdentity::generate());
+        let (a_session, b_session, mut a_stream, mut b_stream) =
+            connect(&a, &b, Network::Regtest, PeerPolicy::Any).await;
+
+        let (a_session, b_session) = (a_session.unwrap(), b_session.unwrap());
+        assert_eq!(a_session.peer_identity(), b.public_key());
+        assert_eq!(b_session.peer_identity(), a.public_key());
+
+        let mut a_transport = Transport::Encrypted(a_session);
+        let mut b_transport = Transport::Encrypted(b_session);
+
+        for message in [&b"first"[..], &b"second"[..], &[7; 1000][..]] {
+            a_transport.send(&mut a_stream, message).await.unwrap();
+            let received = b_transport.recv(&mut b_stream, 1000).await.unwrap();
+            assert_eq!(received, message);
+        }
+
+        // Anything over the limit is refused before it's read
+        a_transport.send(&mut a_stream, &[0; 1001]).await.unwrap();
+        assert!(matches!(
+            b_transport.recv(&mut b_stream, 1000).await,
+            Err(Error::PeerLimitError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn tampered_frames_are_rejected() {
+        let (a, b) = (NodeIdentity::generate(), NodeIdentity::generate());
+        let (a_session, b_session, _, _) = connect(&a, &b, Network::Regtest, PeerPolicy::Any).await;
+        let (mut a_session, mut b_session) = (a_session.unwrap(), b_session.unwrap());
+
+        let mut frame = a_session.seal(b"pay alice 10");
+        frame[6] ^= 1;
+
+        assert!(matches!(
+            b_session.open(&frame[4..]),
+            Err(Error::TransportError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn unpinned_peers_are_refused() {
+        let (a, b) = (NodeIdentity::generate(), NodeIdentity::generate());
+
+        let pinned = PeerPolicy::Pinned(HashSet::from([a.public_key()]));
+        let (_, b_session, _, _) = connect(&a, &b, Network::Regtest, pinned).await;
+        assert!(b_session.is_ok());
+
+        let stranger = NodeIdentity::generate();
+        let pinned = PeerPolicy::Pinned(HashSet::from([a.public_key()]));
+        let (_, b_session, _, _) = connect(&stranger, &b, Network::Regtest, pinned).await;
+        assert!(matches!(b_session, Err(Error::TransportError(_))));
+    }
+
+    #[tokio::test]
+    async fn other_networks_are_refused() {
+        let (a, b) = (NodeIdentity::generate(), NodeIdentity::generate());
+        let (a_session, b_session, _, _) = connect(&a, &b, Network::Testnet, PeerPolicy::Any).await;
+
+        assert!(a_session.is_err());
+        assert!(b_session.is_err());
+    }
+
+    #[tokio::test]
+    async fn plain_transport_frames_messages() {
+        let (mut a_stream, mut b_stream) = duplex(4096);
+
+        Transport::Plain
+            .send(&mut a_stream, b"hello")
+            .await
+            .unwrap();
+        a_stream.flush().await.unwrap();
+
+        let mut raw = [0; 9];
+        b_stream.read_exact(&mut raw).await.unwrap();
+        assert_eq!(&raw[0..4], &5u32.to_le_bytes());
+        assert_eq!(&raw[4..], b"hello");
+    }
+}